@@ -1,13 +1,19 @@
 use bytes::Bytes;
-use futures::{Future, future, Stream, stream};
+use bottle_kind::Registry;
+use futures::{Future, future, Poll, Stream, stream};
 use std::io;
+use std::sync::Arc;
 
+pub use encoder::FramingPolicy;
 use header::{BottleType, Header};
+use observer::SharedObserver;
+use reader_limits::{Limiter, ReaderLimits};
 use stream_toolkit::{
   BufferedByteStream,
   ByteFrame,
   ByteStream,
   ByteStreamStream,
+  generate_skippable_stream,
   generate_stream,
   IoFuture,
   OptionToFuture,
@@ -18,8 +24,6 @@ use stream_toolkit::{
 use table::Table;
 use zint;
 
-const MIN_BUFFER: usize = 1024;
-
 /// Bottle of some known type, metadata table, and a "stream of streams".
 pub struct Bottle<S>
   where
@@ -27,7 +31,9 @@ pub struct Bottle<S>
     S::Item: ByteStream,
 {
   pub header: Header,
-  pub streams: S
+  pub streams: S,
+  /// Limits for reading any bottles nested inside this one.
+  pub limiter: Limiter
 }
 
 impl<S> Bottle<S>
@@ -36,37 +42,175 @@ impl<S> Bottle<S>
     S::Item: ByteStream,
 {
   pub fn new(bottle_type: BottleType, table: Table, streams: S) -> Bottle<S> {
-    Bottle { header: Header::new(bottle_type, table), streams }
+    Bottle { header: Header::new(bottle_type, table), streams, limiter: Limiter::new(ReaderLimits::default()) }
+  }
+
+  /// Check this bottle's header against a registry of application bottle
+  /// types when it's encoded.
+  pub fn with_registry(self, registry: Arc<Registry>) -> Bottle<S> {
+    Bottle { limiter: self.limiter.with_registry(registry), ..self }
   }
 
   /// Consume the streams by encoding everything into one big happy byte
   /// stream.
   pub fn encode(self) -> impl ByteStream {
-    let header_stream = self.header.encode();
-    let streams_stream = self.streams.map(|s| write_framed_stream(s)).flatten();
+    self.encode_with_policy(FramingPolicy::default())
+  }
+
+  /// Encode, using a specific policy for framing each stream.
+  pub fn encode_with_policy(self, policy: FramingPolicy) -> impl ByteStream {
+    let header = self.header;
+    let limiter = self.limiter;
+    let header_stream = future::lazy(move || {
+      future::result(check_encoded_header(&header, &limiter).map(|_| header.encode()))
+    }).flatten_stream();
+    let streams_stream = self.streams.map(move |s| write_framed_stream_with_policy(s, policy)).flatten();
+    let tail_stream = stream_of(zint::END_OF_BOTTLE_BYTES.clone());
+
+    header_stream.chain(streams_stream).chain(tail_stream)
+  }
+
+  /// Encode, reporting each header, stream, and frame to an observer as
+  /// it's written. The encoded bytes are the same as `encode_with_policy`.
+  pub fn encode_observed(self, policy: FramingPolicy, observer: SharedObserver) -> impl ByteStream {
+    self.encode_observed_at(policy, observer, 0)
+  }
+
+  /// Encode a bottle that will be nested `depth` levels inside others,
+  /// reporting to an observer (which may be shared with the outer bottles)
+  /// at that depth. If it's nested, `nested_bottle` is reported first.
+  pub fn encode_observed_at(self, policy: FramingPolicy, observer: SharedObserver, depth: usize) -> impl ByteStream {
+    let header = self.header;
+    let limiter = self.limiter;
+    let header_observer = observer.clone();
+    let header_stream = future::lazy(move || {
+      check_encoded_header(&header, &limiter)?;
+      if depth > 0 { header_observer.nested_bottle(depth) }
+      header_observer.header(depth, &header);
+      Ok::<_, io::Error>(header.encode())
+    }).flatten_stream();
+
+    let mut next_index = 0;
+    let streams_stream = self.streams.map(move |s| {
+      let index = next_index;
+      next_index += 1;
+      write_observed_stream(s, policy, observer.clone(), depth, index)
+    }).flatten();
     let tail_stream = stream_of(zint::END_OF_BOTTLE_BYTES.clone());
 
     header_stream.chain(streams_stream).chain(tail_stream)
   }
 }
 
+/// One stream out of a bottle that was read, remembering the limits (and
+/// observer) of that bottle, so that a bottle nested inside it can be read
+/// with `read_nested_bottle`.
+pub struct BottleStream<S: ByteStream> {
+  stream: S,
+  limiter: Limiter
+}
+
+impl<S: ByteStream> BottleStream<S> {
+  /// The limiter for a bottle nested inside this stream.
+  pub fn limiter(&self) -> &Limiter {
+    &self.limiter
+  }
+}
+
+impl<S: ByteStream> Stream for BottleStream<S> {
+  type Item = S::Item;
+  type Error = S::Error;
+
+  fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+    self.stream.poll()
+  }
+}
+
 /// Read a bottle out of a byte stream, returning a future of the bottle, and
-/// any stream remaining after the end of the bottle.
+/// any stream remaining after the end of the bottle. It's held to the
+/// default `ReaderLimits`.
 pub fn read_bottle<S>(s: ReadableByteStream<S>)
-  -> impl IoFuture<( Bottle<impl ByteStreamStream<impl ByteStream>>, impl IoFuture<ReadableByteStream<S>> )>
+  -> impl IoFuture<( Bottle<impl ByteStreamStream<BottleStream<impl ByteStream>>>, impl IoFuture<ReadableByteStream<S>> )>
+  where S: ByteStream
+{
+  read_bottle_with_limits(s, Limiter::new(ReaderLimits::default()))
+}
+
+/// Read a bottle with no limits at all. Only use this for data you trust.
+pub fn read_bottle_unlimited<S>(s: ReadableByteStream<S>)
+  -> impl IoFuture<( Bottle<impl ByteStreamStream<BottleStream<impl ByteStream>>>, impl IoFuture<ReadableByteStream<S>> )>
+  where S: ByteStream
+{
+  read_bottle_with_limits(s, Limiter::unlimited())
+}
+
+/// Read a bottle, reporting each header, stream, and frame to an observer
+/// as it's read. Bottles nested inside this one will report to the same
+/// observer if they're read with `read_nested_bottle`.
+pub fn read_bottle_observed<S>(s: ReadableByteStream<S>, observer: SharedObserver)
+  -> impl IoFuture<( Bottle<impl ByteStreamStream<BottleStream<impl ByteStream>>>, impl IoFuture<ReadableByteStream<S>> )>
   where S: ByteStream
 {
-  Header::decode(s).map(|(header, s)| {
-    let (streams, future) = generate_stream(s, |s| {
-      read_framed_stream(s)
+  read_bottle_with_limits(s, Limiter::new(ReaderLimits::default()).with_observer(observer))
+}
+
+/// Read a bottle, failing with a `LimitExceeded` error if it goes over any
+/// of the limits. Bottles nested inside this one, read with
+/// `read_nested_bottle`, count against the same limits.
+pub fn read_bottle_with_limits<S>(s: ReadableByteStream<S>, limiter: Limiter)
+  -> impl IoFuture<( Bottle<impl ByteStreamStream<BottleStream<impl ByteStream>>>, impl IoFuture<ReadableByteStream<S>> )>
+  where S: ByteStream
+{
+  let depth = limiter.depth();
+  let header_limiter = limiter.clone();
+  Header::decode_with_limits(s, &limiter).and_then(move |( header, s )| {
+    let checked = header_limiter.registry().map(|registry| registry.check_decoded_header(&header)).unwrap_or(Ok(()));
+    future::result(checked.map(|_| ( header, s )))
+  }).map(move |(header, s)| {
+    // the outer bottle's frames holding this header have been reported by now.
+    if depth > 0 { limiter.notify(|o| o.nested_bottle(depth)) }
+    limiter.notify(|o| o.header(depth, &header));
+    let stream_limiter = limiter.clone();
+    let mut next_index = 0;
+    let (streams, future) = generate_stream(s, move |s| {
+      let index = next_index;
+      next_index += 1;
+      let limiter = stream_limiter.clone();
+      read_framed_stream_with_limits(s, stream_limiter.clone()).map(move |(stream, future)| {
+        let is_stream = stream.is_some();
+        if is_stream { limiter.notify(|o| o.stream_start(depth, index)) }
+        let future = future.map(move |s| {
+          if is_stream { limiter.notify(|o| o.stream_end(depth, index)) }
+          s
+        });
+        ( stream, future )
+      })
     });
 
-    let bottle = Bottle { header, streams: streams.map(|s| s.into_stream()) };
-    ( bottle, future )
+    let nested = limiter.nested();
+    let stream_nested = nested.clone();
+    let streams = streams.map(move |s| BottleStream { stream: s.into_stream(), limiter: stream_nested.clone() });
+    ( Bottle { header, streams, limiter: nested }, future )
   })
 }
 
+/// Read a bottle nested inside a stream of another bottle, using (and
+/// counting against) the outer bottle's limits.
+pub fn read_nested_bottle<S>(s: BottleStream<S>)
+  -> impl IoFuture<( Bottle<impl ByteStreamStream<BottleStream<impl ByteStream>>>, impl IoFuture<ReadableByteStream<BottleStream<S>>> )>
+  where S: ByteStream
+{
+  let limiter = s.limiter.clone();
+  read_bottle_with_limits(ReadableByteStream::from(s), limiter)
+}
+
 
+// check that a header can be written, and run its type's encode handler if
+// it's in the registry.
+fn check_encoded_header(header: &Header, limiter: &Limiter) -> io::Result<()> {
+  header.check_writable()?;
+  limiter.registry().map(|registry| registry.check_encoded_header(header)).unwrap_or(Ok(()))
+}
 
 /// Convert a byte stream into a stream with each chunk prefixed by a length
 /// marker, suitable for embedding in a bottle. Buffering converts clusters
@@ -75,33 +219,82 @@ pub fn read_bottle<S>(s: ReadableByteStream<S>)
 pub fn write_framed_stream<S>(s: S) -> impl ByteStream
   where S: ByteStream
 {
-  // prevent tiny packets by requiring it to buffer at least 1KB
-  BufferedByteStream::new(s, MIN_BUFFER, false).map(|frame| {
-    let prefix: Bytes = Bytes::from(zint::encode_length(frame.length));
-    // transform frame into Stream<Bytes>:
-    stream_of(prefix).chain(stream_of_vec(frame.vec))
+  write_framed_stream_with_policy(s, FramingPolicy::default())
+}
+
+/// Frame a byte stream using a specific policy. Buffers bigger than the
+/// maximum frame size are sliced into several frames.
+pub fn write_framed_stream_with_policy<S>(s: S, policy: FramingPolicy) -> impl ByteStream
+  where S: ByteStream
+{
+  BufferedByteStream::new(s, policy.min_buffer, false).map(move |frame| {
+    stream_of_vec(encode_frame(frame, &policy))
   }).flatten().chain(stream_of(zint::END_OF_STREAM_BYTES.clone()))
 }
 
+// like `write_framed_stream_with_policy`, but reporting to an observer.
+fn write_observed_stream<S>(s: S, policy: FramingPolicy, observer: SharedObserver, depth: usize, index: usize)
+  -> impl ByteStream
+  where S: ByteStream
+{
+  let ( start_observer, frame_observer ) = ( observer.clone(), observer.clone() );
+  let frames = BufferedByteStream::new(s, policy.min_buffer, false).map(move |frame| {
+    let pieces = frame.split(policy.max_frame);
+    for piece in &pieces { frame_observer.frame(depth, piece.length) }
+    stream_of_vec(pieces.into_iter().flat_map(|piece| encode_frame(piece, &policy)).collect::<Vec<_>>())
+  }).flatten();
+  let end = future::lazy(move || {
+    observer.stream_end(depth, index);
+    future::ok::<_, io::Error>(stream_of(zint::END_OF_STREAM_BYTES.clone()))
+  }).flatten_stream();
+
+  future::lazy(move || {
+    start_observer.stream_start(depth, index);
+    future::ok::<_, io::Error>(frames)
+  }).flatten_stream().chain(end)
+}
+
+/// Encode a buffered frame as a list of buffers, each piece prefixed by its
+/// length, splitting it if it's bigger than the policy allows.
+pub fn encode_frame(frame: ByteFrame, policy: &FramingPolicy) -> Vec<Bytes> {
+  let mut buffers = Vec::new();
+  for frame in frame.split(policy.max_frame) {
+    buffers.push(zint::encode_length(frame.length));
+    buffers.extend(frame.vec);
+  }
+  buffers
+}
+
 /// Read a framed stream and transform it back into a normal byte stream.
 /// If we hit the end-of-all-streams marker (signifying the end of the
 /// bottle), `None` is returned. Otherwise `Some(stream)` is returned.
 /// In either case, the original stream is returned as a future that will
-/// resolve once the inner stream has been drained.
+/// resolve once the inner stream has been drained. If the inner stream is
+/// dropped before then, the future skips over the rest of its frames.
+/// It's held to the default `ReaderLimits`.
 pub fn read_framed_stream<S>(s: ReadableByteStream<S>)
   -> impl IoFuture<( Option<ReadableByteStream<impl ByteStream>>, impl IoFuture<ReadableByteStream<S>> )>
   where S: ByteStream
 {
-  is_end_of_bottle(s).map(|(is_end, s)| {
-    let (stream, future) = generate_stream(s, |s| {
-      read_frame(s).map(|(length, frame, s)| {
-        if length == zint::FrameLength::EndOfStream || length == zint::FrameLength::EndOfBottle {
-          (None, future::ok(s))
-        } else {
-          (Some(frame), future::ok(s))
-        }
-      })
-    });
+  read_framed_stream_with_limits(s, Limiter::new(ReaderLimits::default()))
+}
+
+/// Read a framed stream, counting the stream and each of its frames
+/// against the limits.
+pub fn read_framed_stream_with_limits<S>(s: ReadableByteStream<S>, limiter: Limiter)
+  -> impl IoFuture<( Option<ReadableByteStream<impl ByteStream>>, impl IoFuture<ReadableByteStream<S>> )>
+  where S: ByteStream
+{
+  is_end_of_bottle(s).and_then(move |(is_end, s)| {
+    let counted = if is_end { Ok(()) } else { limiter.add_stream() };
+    future::result(counted).map(move |_| ( is_end, s, limiter ))
+  }).map(|(is_end, s, limiter)| {
+    let skip_limiter = limiter.clone();
+    let (stream, future) = generate_skippable_stream(
+      s,
+      move |s| read_frame(s, limiter.clone(), false),
+      move |s| read_frame(s, skip_limiter.clone(), true)
+    );
 
     let (possibly_drain, stream): (Option<stream::Collect<_>>, Option<ReadableByteStream<_>>) = if is_end {
       ( Some(stream.collect()), None )
@@ -112,43 +305,45 @@ pub fn read_framed_stream<S>(s: ReadableByteStream<S>)
   })
 }
 
-fn read_frame<S>(s: ReadableByteStream<S>)
-  -> impl IoFuture<( zint::FrameLength, ByteFrame, ReadableByteStream<S> )>
+// read the next frame, or `None` at the end of the stream. if `skipping`,
+// the frame's data is discarded as it arrives, and an empty frame is
+// returned instead.
+fn read_frame<S>(s: ReadableByteStream<S>, limiter: Limiter, skipping: bool)
+  -> impl IoFuture<( Option<ByteFrame>, future::FutureResult<ReadableByteStream<S>, io::Error> )>
   where S: ByteStream
 {
-  read_frame_length(s).and_then(|(length, s)| {
-    let count: usize = match length {
+  read_frame_length(s).and_then(move |(length, s)| {
+    let count = match length {
       zint::FrameLength::Length(n) => n,
-      _ => 0
+      _ => return future::Either::A(future::ok(( None, future::ok(s) )))
     };
-    s.read_exact(count).map(|(frame, s)| {
-      ( length, frame, s )
-    })
+    // only frames that pass the limits are reported.
+    let counted = limiter.add_frame(count).map(|_| {
+      let depth = limiter.depth();
+      limiter.notify(|o| o.frame(depth, count));
+    });
+    let frame = future::result(counted).and_then(move |_| {
+      if skipping {
+        future::Either::A(s.skip(count).map(|(_, s)| ( ByteFrame::new(Vec::new(), 0), s )))
+      } else {
+        future::Either::B(s.read_exact(count))
+      }
+    });
+    future::Either::B(frame.map(|(frame, s)| ( Some(frame), future::ok(s) )))
   })
 }
 
-fn read_frame_length<S>(s: ReadableByteStream<S>)
-  -> impl IoFuture<( zint::FrameLength, ReadableByteStream<S> )>
+/// Read a frame length (1 to 3 bytes, or an end-of-stream or
+/// end-of-bottle marker).
+pub fn read_frame_length<S>(s: ReadableByteStream<S>) -> impl IoFuture<( zint::FrameLength, ReadableByteStream<S> )>
   where S: ByteStream
 {
-  s.read_exact(1).and_then(|(frame, s)| {
-    let byte: u8 = frame.vec[0][0];
-    let ( count, accumulator ) = zint::decode_first_length_byte(byte);
-    s.read_exact(count).map(|(frame, s)| {
-      ( zint::decode_length(accumulator, frame.pack().as_ref()), s )
-    })
-  })
+  s.read_zint_length()
 }
 
 fn is_end_of_bottle<S>(s: ReadableByteStream<S>)
   -> impl IoFuture<( bool, ReadableByteStream<S> )>
   where S: ByteStream
 {
-  s.read_exact(1).map(|(frame, mut s)| {
-    let byte: u8 = frame.vec[0][0];
-    let ( _, accumulator ) = zint::decode_first_length_byte(byte);
-    let is_end = accumulator == zint::FrameLength::EndOfBottle;
-    s.unread(frame);
-    ( is_end, s )
-  })
+  s.peek(1).map(|(frame, s)| ( frame.pack()[0] == zint::END_OF_BOTTLE, s ))
 }
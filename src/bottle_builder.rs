@@ -0,0 +1,89 @@
+use futures::stream;
+use std::io;
+use std::sync::Arc;
+
+use compressed_bottle::{Codec, write_compressed_bottle};
+use encrypted_bottle::{Cipher, write_encrypted_bottle};
+use file_bottle::{FileMetadata, write_file_bottle};
+use hashed_bottle::{HashType, write_hashed_bottle};
+use stream_toolkit::{BoxedByteStream, ByteStream};
+
+/// A layer wrapped around the innermost bottle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Layer {
+  /// Compressed with the codec of this id.
+  Compressed(u64),
+  /// Encrypted with the cipher of this id.
+  Encrypted(u64),
+  Hashed(HashType)
+}
+
+/// Wrap a bottle in layers, from the inside out, like
+/// `BottleBuilder::file(&metadata, data).compress(codec).encrypt(cipher).hash(HashType::Sha256)`.
+/// Compressing anything that's already encrypted (or compressed) is
+/// pointless, since it won't get any smaller, so it's an error, reported
+/// when the encoded stream is read.
+pub struct BottleBuilder {
+  encoded: BoxedByteStream,
+  layers: Vec<Layer>,
+  error: Option<io::Error>
+}
+
+impl BottleBuilder {
+  /// Start with a file bottle.
+  pub fn file<S>(metadata: &FileMetadata, data: S) -> BottleBuilder where S: ByteStream + 'static {
+    BottleBuilder::bottle(write_file_bottle(metadata, data).encode())
+  }
+
+  /// Start with any encoded bottle, like a folder bottle.
+  pub fn bottle<S>(encoded: S) -> BottleBuilder where S: ByteStream + 'static {
+    BottleBuilder { encoded: Box::new(encoded), layers: Vec::new(), error: None }
+  }
+
+  /// The layers added so far, from the inside out.
+  pub fn layers(&self) -> &[Layer] {
+    &self.layers
+  }
+
+  pub fn compress(self, codec: Arc<Codec>) -> BottleBuilder {
+    let already = self.layers.iter().filter_map(|layer| match *layer {
+      Layer::Compressed(_) => Some("compressed"),
+      Layer::Encrypted(_) => Some("encrypted"),
+      Layer::Hashed(_) => None
+    }).next();
+    if let Some(already) = already {
+      return self.fail(format!("Can't compress data that's already {}", already));
+    }
+    let layer = Layer::Compressed(codec.id());
+    self.wrap(layer, |encoded| Box::new(write_compressed_bottle(encoded, codec).encode()))
+  }
+
+  pub fn encrypt(self, cipher: Arc<Cipher>) -> BottleBuilder {
+    let layer = Layer::Encrypted(cipher.id());
+    self.wrap(layer, |encoded| Box::new(write_encrypted_bottle(encoded, cipher).encode()))
+  }
+
+  pub fn hash(self, hash_type: HashType) -> BottleBuilder {
+    self.wrap(Layer::Hashed(hash_type), |encoded| Box::new(write_hashed_bottle(encoded, hash_type).encode()))
+  }
+
+  /// The fully wrapped bottle, or a stream of the first error.
+  pub fn encode(self) -> BoxedByteStream {
+    match self.error {
+      Some(error) => Box::new(stream::once(Err(error))),
+      None => self.encoded
+    }
+  }
+
+  fn wrap<F>(self, layer: Layer, f: F) -> BottleBuilder where F: FnOnce(BoxedByteStream) -> BoxedByteStream {
+    if self.error.is_some() { return self }
+    let mut layers = self.layers;
+    layers.push(layer);
+    BottleBuilder { encoded: f(self.encoded), layers, error: None }
+  }
+
+  fn fail(self, message: String) -> BottleBuilder {
+    if self.error.is_some() { return self }
+    BottleBuilder { error: Some(io::Error::new(io::ErrorKind::InvalidInput, message)), ..self }
+  }
+}
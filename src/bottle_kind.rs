@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use header::{BottleType, Header};
+use table::Table;
+
+/// What kind of value a header field holds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldKind {
+  Boolean,
+  Number,
+  String,
+  Bytes
+}
+
+/// A field that a bottle type's header table may have.
+#[derive(Clone, Debug)]
+pub struct FieldSpec {
+  pub name: &'static str,
+  pub kind: FieldKind,
+  pub id: u8,
+  pub required: bool
+}
+
+/// A bottle type defined by an application, in one of the spare type ids.
+/// Once it's in a `Registry`, every header of this type is checked by its
+/// handlers as it's encoded or read with that registry.
+pub trait BottleKind: Send + Sync {
+  /// Type id (0 - 15), which can't be one of the types built in.
+  fn id(&self) -> u8;
+
+  fn name(&self) -> &str;
+
+  /// The fields a header of this type may have.
+  fn schema(&self) -> Vec<FieldSpec> {
+    Vec::new()
+  }
+
+  /// Called with each header of this type before it's written. Returning
+  /// an error fails the bottle. By default, it checks for the required
+  /// fields in the schema.
+  fn encode_header(&self, header: &Header) -> io::Result<()> {
+    check_schema(self.name(), &self.schema(), &header.table)
+  }
+
+  /// Called with each header of this type after it's read.
+  fn decode_header(&self, header: &Header) -> io::Result<()> {
+    check_schema(self.name(), &self.schema(), &header.table)
+  }
+}
+
+/// A set of application-defined bottle types. A bottle is checked against
+/// a registry when it's encoded after `Bottle::with_registry`, or read with
+/// a `Limiter` from `Limiter::with_registry`. Without one, every `Other`
+/// type is left raw.
+#[derive(Clone, Default)]
+pub struct Registry {
+  kinds: HashMap<u8, Arc<BottleKind>>
+}
+
+impl Registry {
+  pub fn new() -> Registry {
+    Registry::default()
+  }
+
+  /// Add an application-defined bottle type, so it has a name, and its
+  /// headers are checked as they're written and read.
+  pub fn register(&mut self, kind: Arc<BottleKind>) -> io::Result<()> {
+    let id = kind.id();
+    if id > 15 || BottleType::from_id(id) != BottleType::Other(id) {
+      return Err(registry_error(format!("{} can't use type id {}", kind.name(), id)));
+    }
+    if let Some(existing) = self.kinds.get(&id) {
+      return Err(registry_error(format!("{} can't use type id {}: it's already {}", kind.name(), id, existing.name())));
+    }
+    self.kinds.insert(id, kind);
+    Ok(())
+  }
+
+  /// The registered kind for a bottle type, if there is one.
+  pub fn bottle_kind(&self, bottle_type: &BottleType) -> Option<Arc<BottleKind>> {
+    match *bottle_type {
+      BottleType::Other(id) => self.kinds.get(&id).cloned(),
+      _ => None
+    }
+  }
+
+  /// The name of a bottle type, or `None` if it's unknown.
+  pub fn bottle_type_name(&self, bottle_type: &BottleType) -> Option<String> {
+    match *bottle_type {
+      BottleType::Other(_) => self.bottle_kind(bottle_type).map(|kind| kind.name().to_string()),
+      ref t => Some(format!("{:?}", t))
+    }
+  }
+
+  /// Run the encode handler for a header's type, if it's registered.
+  pub fn check_encoded_header(&self, header: &Header) -> io::Result<()> {
+    self.bottle_kind(&header.bottle_type).map(|kind| kind.encode_header(header)).unwrap_or(Ok(()))
+  }
+
+  /// Run the decode handler for a header's type, if it's registered.
+  pub fn check_decoded_header(&self, header: &Header) -> io::Result<()> {
+    self.bottle_kind(&header.bottle_type).map(|kind| kind.decode_header(header)).unwrap_or(Ok(()))
+  }
+}
+
+/// Check that every required field in a schema is in the table.
+pub fn check_schema(name: &str, schema: &[FieldSpec], table: &Table) -> io::Result<()> {
+  for spec in schema.iter().filter(|spec| spec.required) {
+    let present = match spec.kind {
+      FieldKind::Boolean => table.get_bool(spec.id),
+      FieldKind::Number => table.get_number(spec.id).is_some(),
+      FieldKind::String => table.get_string(spec.id).is_some(),
+      FieldKind::Bytes => table.get_bytes(spec.id).is_some()
+    };
+    if !present {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Missing {} field in {} bottle header", spec.name, name)
+      ));
+    }
+  }
+  Ok(())
+}
+
+fn registry_error(message: String) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, message)
+}
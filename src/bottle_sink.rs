@@ -0,0 +1,139 @@
+use bytes::Bytes;
+use futures::{Async, AsyncSink, Poll, Sink, StartSend};
+use std::collections::VecDeque;
+use std::{io, mem};
+
+use bottle::{encode_frame, FramingPolicy};
+use header::{BottleType, Header};
+use stream_toolkit::ByteFrame;
+use table::Table;
+use zint;
+
+/// What a producer can push into a `BottleSink`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BottleSinkItem {
+  StartStream,
+  Data(Bytes),
+  EndStream
+}
+
+/// Write a bottle to a `Sink<Bytes>`, for producers that push data instead
+/// of handing over a stream of streams. Each stream is a `StartStream`,
+/// any number of `Data` buffers, and an `EndStream`. Data is buffered and
+/// framed with the same rules as `write_framed_stream`. Closing the sink
+/// finishes the bottle, and closes the inner sink.
+#[must_use = "sinks do nothing unless polled"]
+pub struct BottleSink<K> where K: Sink<SinkItem = Bytes, SinkError = io::Error> {
+  sink: K,
+  policy: FramingPolicy,
+
+  // encoded buffers that the inner sink hasn't accepted yet:
+  pending: VecDeque<Bytes>,
+
+  // data for the current stream, waiting to become a frame:
+  buffered: Vec<Bytes>,
+  buffered_length: usize,
+
+  in_stream: bool,
+  finished: bool,
+
+  // a header that can't be written fails every use of the sink.
+  error: Option<io::Error>
+}
+
+impl<K> BottleSink<K> where K: Sink<SinkItem = Bytes, SinkError = io::Error> {
+  pub fn new(bottle_type: BottleType, table: Table, sink: K) -> BottleSink<K> {
+    BottleSink::with_policy(bottle_type, table, sink, FramingPolicy::default())
+  }
+
+  pub fn with_policy(bottle_type: BottleType, table: Table, sink: K, policy: FramingPolicy) -> BottleSink<K> {
+    let ( pending, error ) = match Header::new(bottle_type, table).encode_buffers() {
+      Ok(buffers) => ( buffers.into_iter().collect(), None ),
+      Err(e) => ( VecDeque::new(), Some(e) )
+    };
+    BottleSink {
+      sink,
+      policy,
+      pending,
+      buffered: Vec::new(),
+      buffered_length: 0,
+      in_stream: false,
+      finished: false,
+      error
+    }
+  }
+
+  pub fn into_inner(self) -> K {
+    self.sink
+  }
+
+  // write out any buffered data as a frame.
+  fn flush_frame(&mut self) {
+    if self.buffered_length == 0 { return }
+    let frame = ByteFrame::new(mem::replace(&mut self.buffered, Vec::new()), self.buffered_length);
+    self.buffered_length = 0;
+    self.pending.extend(encode_frame(frame, &self.policy));
+  }
+
+  // push as much as we can into the inner sink. returns true if everything
+  // was accepted.
+  fn send_pending(&mut self) -> Result<bool, io::Error> {
+    if let Some(ref e) = self.error { return Err(io::Error::new(e.kind(), e.to_string())) }
+    while let Some(b) = self.pending.pop_front() {
+      if let AsyncSink::NotReady(b) = self.sink.start_send(b)? {
+        self.pending.push_front(b);
+        return Ok(false);
+      }
+    }
+    Ok(true)
+  }
+}
+
+impl<K> Sink for BottleSink<K> where K: Sink<SinkItem = Bytes, SinkError = io::Error> {
+  type SinkItem = BottleSinkItem;
+  type SinkError = io::Error;
+
+  fn start_send(&mut self, item: BottleSinkItem) -> StartSend<BottleSinkItem, io::Error> {
+    if self.finished { return Err(sink_error("bottle is already closed")) }
+    if !self.send_pending()? { return Ok(AsyncSink::NotReady(item)) }
+
+    match item {
+      BottleSinkItem::StartStream => {
+        if self.in_stream { return Err(sink_error("stream started inside another stream")) }
+        self.in_stream = true;
+      },
+      BottleSinkItem::Data(b) => {
+        if !self.in_stream { return Err(sink_error("data outside of a stream")) }
+        self.buffered_length += b.len();
+        self.buffered.push(b);
+        if self.buffered_length >= self.policy.min_buffer { self.flush_frame() };
+      },
+      BottleSinkItem::EndStream => {
+        if !self.in_stream { return Err(sink_error("stream ended without starting")) }
+        self.flush_frame();
+        self.pending.push_back(zint::END_OF_STREAM_BYTES.clone());
+        self.in_stream = false;
+      }
+    }
+    Ok(AsyncSink::Ready)
+  }
+
+  fn poll_complete(&mut self) -> Poll<(), io::Error> {
+    if !self.send_pending()? { return Ok(Async::NotReady) }
+    self.sink.poll_complete()
+  }
+
+  fn close(&mut self) -> Poll<(), io::Error> {
+    if !self.finished {
+      if self.in_stream { return Err(sink_error("bottle closed in the middle of a stream")) }
+      self.pending.push_back(zint::END_OF_BOTTLE_BYTES.clone());
+      self.finished = true;
+    }
+    if !self.send_pending()? { return Ok(Async::NotReady) }
+    self.sink.close()
+  }
+}
+
+fn sink_error(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid bottle sink item: {}", message))
+}
@@ -0,0 +1,100 @@
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::{fmt, fs, io};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use stream_toolkit::{ByteFrame, ToHex};
+
+pub const CHUNK_ID_SIZE: usize = 32;
+
+/// Identity of a chunk: the SHA-256 of its contents.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ChunkId(pub [u8; CHUNK_ID_SIZE]);
+
+impl ChunkId {
+  /// Hash the contents of a frame, without packing it.
+  pub fn of(frame: &ByteFrame) -> ChunkId {
+    let mut hasher = Sha256::default();
+    for b in &frame.vec { hasher.input(b.as_ref()) };
+    let mut id = [ 0u8; CHUNK_ID_SIZE ];
+    id.copy_from_slice(hasher.result().as_slice());
+    ChunkId(id)
+  }
+
+  pub fn from_slice(buffer: &[u8]) -> io::Result<ChunkId> {
+    if buffer.len() != CHUNK_ID_SIZE { return Err(bad_chunk_id_error()) }
+    let mut id = [ 0u8; CHUNK_ID_SIZE ];
+    id.copy_from_slice(buffer);
+    Ok(ChunkId(id))
+  }
+}
+
+impl fmt::Debug for ChunkId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ChunkId({})", self.0.to_hex())
+  }
+}
+
+/// Folder of chunks, each stored once in a file named after its hash.
+/// Storing a chunk that's already present is a no-op, which is what makes
+/// repeated backups of mostly-unchanged data cheap.
+pub struct ChunkStore {
+  root: PathBuf
+}
+
+impl ChunkStore {
+  /// Open a chunk store at `root`, creating the folder if necessary.
+  pub fn open<P: AsRef<Path>>(root: P) -> io::Result<ChunkStore> {
+    fs::create_dir_all(root.as_ref())?;
+    Ok(ChunkStore { root: root.as_ref().to_path_buf() })
+  }
+
+  // spread chunks across 256 subfolders so no one folder gets huge.
+  fn path_for(&self, id: &ChunkId) -> PathBuf {
+    let hex = id.0.to_hex();
+    self.root.join(&hex[0 .. 2]).join(&hex[2 ..])
+  }
+
+  pub fn contains(&self, id: &ChunkId) -> bool {
+    self.path_for(id).is_file()
+  }
+
+  /// Store a chunk (unless it's already here) and return its id.
+  pub fn put(&self, frame: &ByteFrame) -> io::Result<ChunkId> {
+    let id = ChunkId::of(frame);
+    let path = self.path_for(&id);
+    if path.is_file() { return Ok(id) }
+
+    // write to a temporary file first, so a crash can't leave a partial
+    // chunk under the real name.
+    fs::create_dir_all(path.parent().unwrap())?;
+    let nonce = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let temp_path = path.with_extension(format!("tmp{}", nonce));
+    {
+      let mut file = fs::File::create(&temp_path)?;
+      for b in &frame.vec { file.write_all(b.as_ref())? };
+      file.sync_all()?;
+    }
+    fs::rename(&temp_path, &path)?;
+    Ok(id)
+  }
+
+  /// Fetch a chunk, verifying that its contents still match the id.
+  pub fn get(&self, id: &ChunkId) -> io::Result<Bytes> {
+    let mut buffer = Vec::new();
+    fs::File::open(self.path_for(id))?.read_to_end(&mut buffer)?;
+    let data = Bytes::from(buffer);
+    if ChunkId::of(&ByteFrame::from(data.clone())) != *id { return Err(corrupted_chunk_error(id)) }
+    Ok(data)
+  }
+}
+
+fn bad_chunk_id_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "Truncated chunk id")
+}
+
+fn corrupted_chunk_error(id: &ChunkId) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, format!("Corrupted chunk: {}", id.0.to_hex()))
+}
@@ -0,0 +1,71 @@
+use bytes::Bytes;
+use futures::{Future, future, Stream};
+use std::io;
+use std::sync::Arc;
+
+use bottle::Bottle;
+use chunk_store::{CHUNK_ID_SIZE, ChunkId, ChunkStore};
+use header::BottleType;
+use stream_toolkit::{
+  BufferedByteStream,
+  ByteStream,
+  ByteStreamStream,
+  ChunkedByteStream,
+  ChunkSizes,
+  stream_of_streams
+};
+use table::Table;
+
+// table fields (numbers): the chunk sizes used, for reference.
+const MINIMUM_SIZE_ID: u8 = 0;
+const AVERAGE_SIZE_ID: u8 = 1;
+const MAXIMUM_SIZE_ID: u8 = 2;
+
+/// Cut a byte stream into content-defined chunks, save each one into the
+/// chunk store, and return a chunked bottle. The bottle has a single stream
+/// containing the id of each chunk in order, instead of the data itself.
+pub fn write_chunked_bottle<S>(s: S, store: Arc<ChunkStore>, sizes: ChunkSizes)
+  -> Bottle<impl ByteStreamStream<impl ByteStream>>
+  where S: ByteStream
+{
+  let mut table = Table::new();
+  table.add_number(MINIMUM_SIZE_ID, sizes.minimum as u64);
+  table.add_number(AVERAGE_SIZE_ID, sizes.average as u64);
+  table.add_number(MAXIMUM_SIZE_ID, sizes.maximum as u64);
+
+  let references = ChunkedByteStream::new(s, sizes).and_then(move |frame| {
+    store.put(&frame).map(|id| Bytes::from(&id.0[..]))
+  });
+  Bottle::new(BottleType::Chunked, table, stream_of_streams(vec![ references ]))
+}
+
+/// Turn a stream of chunk ids back into the original data, by fetching each
+/// chunk from the store.
+pub fn read_chunked_stream<S>(references: S, store: Arc<ChunkStore>) -> impl ByteStream
+  where S: ByteStream
+{
+  BufferedByteStream::new(references, CHUNK_ID_SIZE, true).and_then(move |frame| {
+    ChunkId::from_slice(frame.pack().as_ref()).and_then(|id| store.get(&id))
+  })
+}
+
+/// Reassemble the data inside a chunked bottle (from `read_bottle`).
+pub fn read_chunked_bottle<S>(bottle: Bottle<S>, store: Arc<ChunkStore>) -> impl ByteStream
+  where
+    S: Stream<Error = io::Error>,
+    S::Item: ByteStream,
+{
+  let check = if bottle.header.bottle_type == BottleType::Chunked {
+    Ok(bottle.streams)
+  } else {
+    Err(not_chunked_error(&bottle.header.bottle_type))
+  };
+
+  future::result(check).map(move |streams| {
+    streams.map(move |s| read_chunked_stream(s, store.clone())).flatten()
+  }).flatten_stream()
+}
+
+fn not_chunked_error(bottle_type: &BottleType) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Not a chunked bottle: {:?}", bottle_type))
+}
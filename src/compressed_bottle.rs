@@ -0,0 +1,59 @@
+use futures::{Future, future, Stream};
+use std::io;
+use std::sync::Arc;
+
+use bottle::Bottle;
+use header::BottleType;
+use stream_toolkit::{BoxedByteStream, ByteStream, ByteStreamStream, stream_of_streams};
+use table::Table;
+
+// table fields (numbers):
+const CODEC_ID: u8 = 0;
+
+/// A compression algorithm. None are built in: an application supplies the
+/// ones it wants (zstd, lzma, and so on) by implementing this.
+pub trait Codec: Send + Sync {
+  /// Identifies the codec in the bottle header, so it can be decompressed
+  /// with the same codec later.
+  fn id(&self) -> u64;
+
+  fn name(&self) -> &str;
+
+  fn compress(&self, data: BoxedByteStream) -> BoxedByteStream;
+
+  fn decompress(&self, data: BoxedByteStream) -> BoxedByteStream;
+}
+
+/// Wrap a byte stream (usually an encoded bottle) in a compressed bottle,
+/// which has the compressed data as its only stream.
+pub fn write_compressed_bottle<S>(s: S, codec: Arc<Codec>) -> Bottle<impl ByteStreamStream<BoxedByteStream>>
+  where S: ByteStream + 'static
+{
+  let mut table = Table::new();
+  table.add_number(CODEC_ID, codec.id());
+  Bottle::new(BottleType::Compressed, table, stream_of_streams(vec![ codec.compress(Box::new(s)) ]))
+}
+
+/// The id of the codec a compressed bottle was written with.
+pub fn codec_id(table: &Table) -> io::Result<u64> {
+  table.get_number(CODEC_ID).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Compressed bottle has no codec"))
+}
+
+/// Decompress the data inside a compressed bottle (from `read_bottle`).
+pub fn read_compressed_bottle<S>(bottle: Bottle<S>, codec: Arc<Codec>) -> impl ByteStream
+  where
+    S: Stream<Error = io::Error> + 'static,
+    S::Item: ByteStream + 'static,
+{
+  let check = if bottle.header.bottle_type == BottleType::Compressed {
+    Ok(bottle.streams)
+  } else {
+    Err(not_compressed_error(&bottle.header.bottle_type))
+  };
+
+  future::result(check).map(move |streams| codec.decompress(Box::new(streams.flatten()))).flatten_stream()
+}
+
+fn not_compressed_error(bottle_type: &BottleType) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Not a compressed bottle: {:?}", bottle_type))
+}
@@ -0,0 +1,313 @@
+use bytes::Bytes;
+use futures::Future;
+use std::{cmp, io};
+use std::ops::Range;
+
+use header::{BottleType, HEADER_PREFIX_SIZE};
+use parser::{BottleParser, Piece};
+use stream_toolkit::{ByteStream, ToHex};
+use table::{ExtendedKey, Field, FieldValue, Table};
+use zint;
+
+/// A field from a bottle's header table, and where it starts.
+#[derive(Clone, Debug)]
+pub struct FieldDump {
+  pub offset: u64,
+  pub field: Field
+}
+
+/// A frame of a stream: where its length prefix starts, and how much data
+/// follows it.
+#[derive(Clone, Debug)]
+pub struct FrameDump {
+  pub offset: u64,
+  pub length: usize
+}
+
+#[derive(Debug)]
+pub struct StreamDump {
+  pub offset: u64,
+  pub frames: Vec<FrameDump>,
+  /// Total data in the stream (not counting framing).
+  pub length: u64,
+  /// The bottle inside this stream, if it holds one.
+  pub bottle: Option<BottleDump>
+}
+
+#[derive(Debug)]
+pub struct BottleDump {
+  pub offset: u64,
+  pub bottle_type: BottleType,
+  pub fields: Vec<FieldDump>,
+  pub streams: Vec<StreamDump>
+}
+
+/// A run of bytes, and what they mean. `depth` is 0 for the outermost
+/// bottle, 1 for the contents of its streams, and so on.
+#[derive(Debug)]
+pub struct DumpLine {
+  pub offset: u64,
+  pub bytes: Bytes,
+  pub depth: usize,
+  pub meaning: String
+}
+
+/// The structure of a bottle, as a tree, and as a list of annotated byte
+/// ranges that covers every byte of the bottle, in order.
+#[derive(Debug)]
+pub struct Dump {
+  pub bottle: BottleDump,
+  pub lines: Vec<DumpLine>
+}
+
+impl Dump {
+  pub fn to_json(&self) -> String {
+    bottle_json(&self.bottle)
+  }
+
+  /// One line per run of up to 16 bytes: offset, bytes, and meaning,
+  /// indented by how deeply it's nested.
+  pub fn to_hex_dump(&self) -> String {
+    let mut out = String::new();
+    for line in &self.lines {
+      for (i, chunk) in line.bytes.chunks(16).enumerate() {
+        let hex = chunk.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ");
+        let meaning = if i == 0 { line.meaning.as_ref() } else { "" };
+        out.push_str(format!(
+          "{:08x}  {:<47}  {}{}", line.offset + (i * 16) as u64, hex, "  ".repeat(line.depth), meaning
+        ).trim_right());
+        out.push('\n');
+      }
+    }
+    out
+  }
+}
+
+/// Walk a bottle and every bottle nested inside it, recording the header,
+/// table fields, streams, and frames of each, and the offset (from the
+/// start of `buffer`) of everything. A stream is treated as a nested bottle
+/// if it starts with the bottle magic. Nesting deeper than the default
+/// `ReaderLimits` allows is an error.
+pub fn dump(buffer: &[u8]) -> io::Result<Dump> {
+  let mut dissector = Dissector::new();
+  dissector.feed(buffer)?;
+  dissector.finish()
+}
+
+/// Dump a bottle as it arrives. Only the dump itself is kept in memory.
+pub fn dump_stream<S>(s: S) -> impl Future<Item = Dump, Error = io::Error>
+  where S: ByteStream
+{
+  s.fold(Dissector::new(), |mut dissector, data| {
+    dissector.feed(&data).map(|_| dissector)
+  }).and_then(|dissector| dissector.finish())
+}
+
+
+// a bottle that hasn't ended yet, and its current stream.
+struct OpenBottle {
+  bottle: BottleDump,
+  stream: Option<StreamDump>,
+  // has the current stream had any data yet?
+  has_data: bool
+}
+
+// builds a dump from the pieces reported by the parser.
+struct Dissector {
+  parser: BottleParser,
+  // the bottle at each depth:
+  open: Vec<OpenBottle>,
+  bottle: Option<BottleDump>,
+  lines: Vec<DumpLine>
+}
+
+impl Dissector {
+  fn new() -> Dissector {
+    Dissector { parser: BottleParser::with_magic_nesting(), open: Vec::new(), bottle: None, lines: Vec::new() }
+  }
+
+  fn feed(&mut self, data: &[u8]) -> io::Result<()> {
+    let open = &mut self.open;
+    let bottle = &mut self.bottle;
+    let lines = &mut self.lines;
+    self.parser.feed_pieces(data, |depth, piece, runs| {
+      match piece {
+        Piece::Header(header, bytes) => {
+          annotate(lines, depth, bytes, runs, 0 .. 4, "magic".to_string());
+          annotate(lines, depth, bytes, runs, 4 .. 6, format!("version {}", header.version));
+          annotate(lines, depth, bytes, runs, 6 .. HEADER_PREFIX_SIZE, format!(
+            "type {:?}, table: {} bytes", header.bottle_type, bytes.len() - HEADER_PREFIX_SIZE
+          ));
+          // the parser already decoded this table, so it's valid.
+          let fields = Table::decode_fields(&bytes[HEADER_PREFIX_SIZE ..]).unwrap_or_else(|_| Vec::new());
+          let fields = fields.into_iter().map(|( range, field )| {
+            let range = range.start + HEADER_PREFIX_SIZE .. range.end + HEADER_PREFIX_SIZE;
+            let offset = position(runs, range.start);
+            annotate(lines, depth, bytes, runs, range, format!("field {:?}", field));
+            FieldDump { offset, field }
+          }).collect();
+          let bottle = BottleDump { offset: runs[0].start, bottle_type: header.bottle_type.clone(), fields, streams: Vec::new() };
+          open.push(OpenBottle { bottle, stream: None, has_data: false });
+        },
+
+        Piece::Length(length, bytes) => {
+          let meaning = match length {
+            zint::FrameLength::EndOfStream => "end of stream".to_string(),
+            zint::FrameLength::EndOfBottle => "end of bottle".to_string(),
+            zint::FrameLength::Length(n) => format!("frame: {} bytes", n)
+          };
+          annotate(lines, depth, bytes, runs, 0 .. bytes.len(), meaning);
+          let offset = runs[0].start;
+
+          if length == zint::FrameLength::EndOfBottle {
+            let finished = open.pop().map(|b| b.bottle);
+            match open.last_mut().and_then(|b| b.stream.as_mut()) {
+              Some(stream) => stream.bottle = finished,
+              None => *bottle = finished
+            }
+            return;
+          }
+          let current = &mut open[depth];
+          let mut stream = current.stream.take().unwrap_or_else(|| {
+            StreamDump { offset, frames: Vec::new(), length: 0, bottle: None }
+          });
+          if let zint::FrameLength::Length(n) = length {
+            stream.frames.push(FrameDump { offset, length: n });
+            stream.length += n as u64;
+            current.stream = Some(stream);
+          } else {
+            current.bottle.streams.push(stream);
+            current.has_data = false;
+          }
+        },
+
+        Piece::Data(data) => {
+          let current = &mut open[depth];
+          let after_bottle = current.stream.as_ref().map(|s| s.bottle.is_some()).unwrap_or(false);
+          let meaning = if after_bottle { "data after bottle" } else { "data" };
+          add_data(lines, depth + 1, data, runs[0].start, meaning, current.has_data);
+          current.has_data = true;
+        }
+      }
+    })?;
+    Ok(())
+  }
+
+  fn finish(self) -> io::Result<Dump> {
+    let offset = self.lines.last().map(|line| line.offset + line.bytes.len() as u64).unwrap_or(0);
+    let bottle = self.bottle.ok_or_else(|| truncated_error(offset))?;
+    let mut lines = self.lines;
+    lines.sort_by_key(|line| line.offset);
+    Ok(Dump { bottle, lines })
+  }
+}
+
+// where byte `index` of a piece is.
+fn position(runs: &[Range<u64>], index: usize) -> u64 {
+  let mut index = index as u64;
+  for run in runs {
+    if index < run.end - run.start { return run.start + index }
+    index -= run.end - run.start;
+  }
+  runs.last().map(|run| run.end).unwrap_or(0)
+}
+
+// add a line for each run that bytes `range` of a piece are spread across.
+fn annotate(lines: &mut Vec<DumpLine>, depth: usize, bytes: &[u8], runs: &[Range<u64>], range: Range<usize>, meaning: String) {
+  let mut start = 0;
+  let mut first = true;
+  for run in runs {
+    let end = start + (run.end - run.start) as usize;
+    let ( from, to ) = ( cmp::max(start, range.start), cmp::min(end, range.end) );
+    if from < to {
+      lines.push(DumpLine {
+        offset: run.start + (from - start) as u64,
+        bytes: Bytes::from(&bytes[from .. to]),
+        depth,
+        meaning: if first { meaning.clone() } else { format!("{} (continued)", meaning) }
+      });
+      first = false;
+    }
+    start = end;
+  }
+}
+
+// data arrives in pieces, so join it to the line before, if it's the same
+// data continuing.
+fn add_data(lines: &mut Vec<DumpLine>, depth: usize, data: &[u8], offset: u64, meaning: &str, continued: bool) {
+  if let Some(last) = lines.last_mut() {
+    let same = last.depth == depth && last.meaning.trim_right_matches(" (continued)") == meaning;
+    if same && last.offset + last.bytes.len() as u64 == offset {
+      last.bytes.extend_from_slice(data);
+      return;
+    }
+  }
+  lines.push(DumpLine {
+    offset,
+    bytes: Bytes::from(data),
+    depth,
+    meaning: if continued { format!("{} (continued)", meaning) } else { meaning.to_string() }
+  });
+}
+
+
+// ----- json
+
+fn bottle_json(bottle: &BottleDump) -> String {
+  format!(
+    "{{\"offset\":{},\"type\":{},\"fields\":[{}],\"streams\":[{}]}}",
+    bottle.offset,
+    json_string(&format!("{:?}", bottle.bottle_type)),
+    bottle.fields.iter().map(field_json).collect::<Vec<String>>().join(","),
+    bottle.streams.iter().map(stream_json).collect::<Vec<String>>().join(",")
+  )
+}
+
+fn field_json(f: &FieldDump) -> String {
+  format!("{{\"offset\":{},\"id\":{},{}}}", f.offset, f.field.id, value_json(&f.field.value))
+}
+
+fn value_json(value: &FieldValue) -> String {
+  match *value {
+    FieldValue::Boolean => "\"bool\":true".to_string(),
+    FieldValue::Number(n) => format!("\"number\":{}", n),
+    FieldValue::String(ref s) => format!("\"string\":{}", json_string(s)),
+    FieldValue::Bytes(ref b) => format!("\"bytes\":\"{}\"", b.to_hex()),
+    FieldValue::Extended(ref key, ref value) => {
+      let key = match *key {
+        ExtendedKey::Number(n) => format!("{}", n),
+        ExtendedKey::Name(ref name) => json_string(name)
+      };
+      format!("\"key\":{},{}", key, value_json(value))
+    }
+  }
+}
+
+fn stream_json(s: &StreamDump) -> String {
+  let frames = s.frames.iter().map(|f| {
+    format!("{{\"offset\":{},\"length\":{}}}", f.offset, f.length)
+  }).collect::<Vec<String>>().join(",");
+  let bottle = match s.bottle {
+    Some(ref b) => format!(",\"bottle\":{}", bottle_json(b)),
+    None => "".to_string()
+  };
+  format!("{{\"offset\":{},\"length\":{},\"frames\":[{}]{}}}", s.offset, s.length, frames, bottle)
+}
+
+fn json_string(s: &str) -> String {
+  let mut out = String::from("\"");
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c)
+    }
+  }
+  out.push('"');
+  out
+}
+
+fn truncated_error(offset: u64) -> io::Error {
+  io::Error::new(io::ErrorKind::UnexpectedEof, format!("Truncated bottle at {}", offset))
+}
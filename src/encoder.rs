@@ -0,0 +1,151 @@
+use core::mem;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use error::{self, Error, ErrorKind};
+use header::Header;
+use zint;
+
+/// How a byte stream is cut into frames when it's written into a bottle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FramingPolicy {
+  /// Buffer at least this many bytes before writing a frame (unless the
+  /// stream ends first), to prevent tiny frames.
+  pub min_buffer: usize,
+  /// Largest frame to write. Anything bigger is split, without copying.
+  pub max_frame: usize
+}
+
+impl FramingPolicy {
+  pub fn new(min_buffer: usize, max_frame: usize) -> FramingPolicy {
+    assert!(min_buffer > 0);
+    assert!(max_frame > 0 && max_frame <= zint::MAX_FRAME_LENGTH);
+    FramingPolicy { min_buffer, max_frame }
+  }
+}
+
+impl Default for FramingPolicy {
+  fn default() -> FramingPolicy {
+    FramingPolicy { min_buffer: 1024, max_frame: zint::MAX_FRAME_LENGTH }
+  }
+}
+
+/// A bottle encoder that doesn't do any I/O: describe the bottle one call
+/// at a time, and it appends the encoded bytes to a buffer provided by the
+/// caller. Data is buffered and framed with the same rules as
+/// `write_framed_stream`.
+///
+/// Starting a bottle inside a stream nests it: its bytes become the data
+/// of that stream, until `end_bottle` is called.
+pub struct BottleEncoder {
+  policy: FramingPolicy,
+  // each bottle that's still open, from the outermost in:
+  levels: Vec<Level>
+}
+
+struct Level {
+  in_stream: bool,
+  buffered: Vec<u8>
+}
+
+impl BottleEncoder {
+  pub fn new() -> BottleEncoder {
+    BottleEncoder::with_policy(FramingPolicy::default())
+  }
+
+  pub fn with_policy(policy: FramingPolicy) -> BottleEncoder {
+    BottleEncoder { policy, levels: Vec::new() }
+  }
+
+  /// How many bottles are open.
+  pub fn depth(&self) -> usize {
+    self.levels.len()
+  }
+
+  pub fn begin_bottle(&mut self, header: &Header, out: &mut Vec<u8>) -> error::Result<()> {
+    if self.levels.last().map(|level| !level.in_stream).unwrap_or(false) {
+      return Err(encoder_error("bottle started outside of a stream"));
+    }
+    let mut buffer = Vec::new();
+    header.encode_into(&mut buffer)?;
+    self.levels.push(Level { in_stream: false, buffered: Vec::new() });
+    let depth = self.levels.len() - 1;
+    self.emit(depth, &buffer, out);
+    Ok(())
+  }
+
+  /// Nothing is written until there's data, or the stream ends.
+  pub fn begin_stream(&mut self) -> error::Result<()> {
+    let depth = self.open_bottle()?;
+    if self.levels[depth].in_stream { return Err(encoder_error("stream started inside another stream")) }
+    self.levels[depth].in_stream = true;
+    Ok(())
+  }
+
+  pub fn write(&mut self, data: &[u8], out: &mut Vec<u8>) -> error::Result<()> {
+    let depth = self.open_stream()?;
+    self.levels[depth].buffered.extend_from_slice(data);
+    if self.levels[depth].buffered.len() >= self.policy.min_buffer { self.flush(depth, out) };
+    Ok(())
+  }
+
+  pub fn end_stream(&mut self, out: &mut Vec<u8>) -> error::Result<()> {
+    let depth = self.open_stream()?;
+    self.flush(depth, out);
+    self.emit(depth, &zint::END_OF_STREAM_ARRAY, out);
+    self.levels[depth].in_stream = false;
+    Ok(())
+  }
+
+  pub fn end_bottle(&mut self, out: &mut Vec<u8>) -> error::Result<()> {
+    let depth = self.open_bottle()?;
+    if self.levels[depth].in_stream { return Err(encoder_error("bottle ended in the middle of a stream")) }
+    self.emit(depth, &zint::END_OF_BOTTLE_ARRAY, out);
+    self.levels.pop();
+    Ok(())
+  }
+
+  fn open_bottle(&self) -> error::Result<usize> {
+    if self.levels.is_empty() { return Err(encoder_error("no bottle has been started")) }
+    Ok(self.levels.len() - 1)
+  }
+
+  fn open_stream(&self) -> error::Result<usize> {
+    let depth = self.open_bottle()?;
+    if !self.levels[depth].in_stream { return Err(encoder_error("no stream has been started")) }
+    Ok(depth)
+  }
+
+  // write bytes belonging to the bottle at `depth`: either the output, or
+  // the stream it's nested in.
+  fn emit(&mut self, depth: usize, data: &[u8], out: &mut Vec<u8>) {
+    if depth == 0 {
+      out.extend_from_slice(data);
+      return;
+    }
+    self.levels[depth - 1].buffered.extend_from_slice(data);
+    if self.levels[depth - 1].buffered.len() >= self.policy.min_buffer { self.flush(depth - 1, out) };
+  }
+
+  // write out the data buffered in the open stream of the bottle at
+  // `depth` as frames.
+  fn flush(&mut self, depth: usize, out: &mut Vec<u8>) {
+    let buffered = mem::replace(&mut self.levels[depth].buffered, Vec::new());
+    for chunk in buffered.chunks(self.policy.max_frame) {
+      let mut length = Vec::with_capacity(3);
+      zint::encode_length_into(chunk.len(), &mut length);
+      self.emit(depth, &length, out);
+      self.emit(depth, chunk, out);
+    }
+  }
+}
+
+impl Default for BottleEncoder {
+  fn default() -> BottleEncoder {
+    BottleEncoder::new()
+  }
+}
+
+fn encoder_error(message: &str) -> Error {
+  Error::new(ErrorKind::InvalidInput, format!("Invalid bottle encoder call: {}", message))
+}
@@ -0,0 +1,77 @@
+use futures::{Future, future, Stream};
+use std::io;
+use std::sync::Arc;
+
+use bottle::Bottle;
+use header::BottleType;
+use stream_toolkit::{BoxedByteStream, ByteStream, ByteStreamStream, stream_of_streams};
+use table::Table;
+
+// table fields (numbers):
+const CIPHER_ID: u8 = 0;
+
+// table fields (strings):
+const RECIPIENT_ID: u8 = 0;
+
+/// An encryption scheme, along with the keys to use. None are built in: an
+/// application supplies its own by implementing this.
+pub trait Cipher: Send + Sync {
+  /// Identifies the cipher in the bottle header.
+  fn id(&self) -> u64;
+
+  fn name(&self) -> &str;
+
+  /// Who (which keys) can decrypt the data, stored in the header so a
+  /// reader can find the right key.
+  fn recipients(&self) -> Vec<String>;
+
+  fn encrypt(&self, data: BoxedByteStream) -> BoxedByteStream;
+
+  fn decrypt(&self, data: BoxedByteStream) -> BoxedByteStream;
+}
+
+/// Finds the cipher (with keys) to decrypt an encrypted bottle, from the
+/// cipher id and recipients in its header.
+pub trait KeyProvider: Send + Sync {
+  fn cipher(&self, cipher_id: u64, recipients: &[String]) -> io::Result<Arc<Cipher>>;
+}
+
+/// Wrap a byte stream (usually an encoded bottle) in an encrypted bottle,
+/// which has the encrypted data as its only stream.
+pub fn write_encrypted_bottle<S>(s: S, cipher: Arc<Cipher>) -> Bottle<impl ByteStreamStream<BoxedByteStream>>
+  where S: ByteStream + 'static
+{
+  let mut table = Table::new();
+  table.add_number(CIPHER_ID, cipher.id());
+  for recipient in cipher.recipients() { table.add_string(RECIPIENT_ID, recipient) };
+  Bottle::new(BottleType::Encrypted, table, stream_of_streams(vec![ cipher.encrypt(Box::new(s)) ]))
+}
+
+/// The id of the cipher an encrypted bottle was written with.
+pub fn cipher_id(table: &Table) -> io::Result<u64> {
+  table.get_number(CIPHER_ID).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Encrypted bottle has no cipher"))
+}
+
+/// Who an encrypted bottle was encrypted for.
+pub fn recipients(table: &Table) -> Vec<String> {
+  table.get_strings(RECIPIENT_ID).iter().map(|r| r.to_string()).collect()
+}
+
+/// Decrypt the data inside an encrypted bottle (from `read_bottle`).
+pub fn read_encrypted_bottle<S>(bottle: Bottle<S>, cipher: Arc<Cipher>) -> impl ByteStream
+  where
+    S: Stream<Error = io::Error> + 'static,
+    S::Item: ByteStream + 'static,
+{
+  let check = if bottle.header.bottle_type == BottleType::Encrypted {
+    Ok(bottle.streams)
+  } else {
+    Err(not_encrypted_error(&bottle.header.bottle_type))
+  };
+
+  future::result(check).map(move |streams| cipher.decrypt(Box::new(streams.flatten()))).flatten_stream()
+}
+
+fn not_encrypted_error(bottle_type: &BottleType) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Not an encrypted bottle: {:?}", bottle_type))
+}
@@ -0,0 +1,49 @@
+// the error type for the format core (zint, table, header, and the sans-IO
+// parser and encoder). with `std`, it's `std::io::Error`, so the core's
+// errors mix with everything else in the crate. without `std`, it's a
+// stand-in that carries a kind and a message.
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, ErrorKind, Result};
+
+#[cfg(not(feature = "std"))]
+pub use self::core_error::{Error, ErrorKind, Result};
+
+#[cfg(not(feature = "std"))]
+mod core_error {
+  use core::{fmt, result};
+  use alloc::string::String;
+
+  /// The kinds of `std::io::ErrorKind` that the format core uses.
+  #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+  pub enum ErrorKind {
+    InvalidInput,
+    InvalidData,
+    UnexpectedEof
+  }
+
+  /// Stands in for `std::io::Error`: a kind, and a message.
+  #[derive(Debug)]
+  pub struct Error {
+    kind: ErrorKind,
+    message: String
+  }
+
+  impl Error {
+    pub fn new<M: Into<String>>(kind: ErrorKind, message: M) -> Error {
+      Error { kind, message: message.into() }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+      self.kind
+    }
+  }
+
+  impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", self.message)
+    }
+  }
+
+  pub type Result<T> = result::Result<T, Error>;
+}
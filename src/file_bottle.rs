@@ -0,0 +1,307 @@
+use futures::{Future, future, Stream, stream};
+use std::{fs, io};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bottle::{Bottle, read_bottle_with_limits};
+use header::BottleType;
+use reader_limits::{Limiter, ReaderLimits};
+use stream_toolkit::{
+  BoxedByteStream,
+  BoxedIoFuture,
+  ByteStream,
+  ByteStreamStream,
+  generate_stream,
+  ReadableByteStream,
+  stream_of_streams
+};
+use table::Table;
+
+// table fields (strings):
+const FILENAME_ID: u8 = 0;
+const MIME_TYPE_ID: u8 = 1;
+const POSIX_USERNAME_ID: u8 = 2;
+const POSIX_GROUPNAME_ID: u8 = 3;
+const SHA256_ID: u8 = 4;
+
+// table fields (numbers):
+const SIZE_ID: u8 = 0;
+const POSIX_MODE_ID: u8 = 1;
+const CREATED_NANOS_ID: u8 = 2;
+const MODIFIED_NANOS_ID: u8 = 3;
+const ACCESSED_NANOS_ID: u8 = 4;
+
+// table fields (booleans):
+const IS_FOLDER_ID: u8 = 0;
+const IS_DELETED_ID: u8 = 1;
+
+/// Metadata stored in the header of a file bottle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileMetadata {
+  pub filename: String,
+  pub mime_type: Option<String>,
+  pub posix_username: Option<String>,
+  pub posix_groupname: Option<String>,
+  /// SHA-256 of the contents (in hex), if it was computed.
+  pub sha256: Option<String>,
+  pub size: u64,
+  pub posix_mode: Option<u64>,
+  pub created_nanos: Option<u64>,
+  pub modified_nanos: Option<u64>,
+  pub accessed_nanos: Option<u64>,
+  /// A folder bottle holds a nested file bottle in each stream.
+  pub is_folder: bool,
+  /// A "tombstone" in an incremental archive: this file was deleted.
+  pub is_deleted: bool
+}
+
+impl FileMetadata {
+  pub fn new(filename: String) -> FileMetadata {
+    FileMetadata {
+      filename,
+      mime_type: None,
+      posix_username: None,
+      posix_groupname: None,
+      sha256: None,
+      size: 0,
+      posix_mode: None,
+      created_nanos: None,
+      modified_nanos: None,
+      accessed_nanos: None,
+      is_folder: false,
+      is_deleted: false
+    }
+  }
+
+  /// Fill in whatever metadata the filesystem has for a file or folder.
+  pub fn from_path(path: &Path) -> io::Result<FileMetadata> {
+    let stat = fs::metadata(path)?;
+    let filename = path.file_name().ok_or_else(|| missing_filename_error())?.to_string_lossy().into_owned();
+    let mut metadata = FileMetadata::new(filename);
+    metadata.is_folder = stat.is_dir();
+    metadata.size = if stat.is_dir() { 0 } else { stat.len() };
+    metadata.posix_mode = posix_mode(&stat);
+    metadata.created_nanos = stat.created().ok().and_then(nanos_since_epoch);
+    metadata.modified_nanos = stat.modified().ok().and_then(nanos_since_epoch);
+    metadata.accessed_nanos = stat.accessed().ok().and_then(nanos_since_epoch);
+    Ok(metadata)
+  }
+
+  pub fn to_table(&self) -> Table {
+    let mut table = Table::new();
+    table.add_string(FILENAME_ID, self.filename.clone());
+    for s in &self.mime_type { table.add_string(MIME_TYPE_ID, s.clone()) };
+    for s in &self.posix_username { table.add_string(POSIX_USERNAME_ID, s.clone()) };
+    for s in &self.posix_groupname { table.add_string(POSIX_GROUPNAME_ID, s.clone()) };
+    for s in &self.sha256 { table.add_string(SHA256_ID, s.clone()) };
+    table.add_number(SIZE_ID, self.size);
+    for n in &self.posix_mode { table.add_number(POSIX_MODE_ID, *n) };
+    for n in &self.created_nanos { table.add_number(CREATED_NANOS_ID, *n) };
+    for n in &self.modified_nanos { table.add_number(MODIFIED_NANOS_ID, *n) };
+    for n in &self.accessed_nanos { table.add_number(ACCESSED_NANOS_ID, *n) };
+    if self.is_folder { table.add_bool(IS_FOLDER_ID) };
+    if self.is_deleted { table.add_bool(IS_DELETED_ID) };
+    table
+  }
+
+  pub fn from_table(table: &Table) -> io::Result<FileMetadata> {
+    let filename = table.get_string(FILENAME_ID).ok_or_else(|| missing_filename_error())?;
+    let mut metadata = FileMetadata::new(filename.to_string());
+    metadata.mime_type = table.get_string(MIME_TYPE_ID).map(|s| s.to_string());
+    metadata.posix_username = table.get_string(POSIX_USERNAME_ID).map(|s| s.to_string());
+    metadata.posix_groupname = table.get_string(POSIX_GROUPNAME_ID).map(|s| s.to_string());
+    metadata.sha256 = table.get_string(SHA256_ID).map(|s| s.to_string());
+    metadata.size = table.get_number(SIZE_ID).unwrap_or(0);
+    metadata.posix_mode = table.get_number(POSIX_MODE_ID);
+    metadata.created_nanos = table.get_number(CREATED_NANOS_ID);
+    metadata.modified_nanos = table.get_number(MODIFIED_NANOS_ID);
+    metadata.accessed_nanos = table.get_number(ACCESSED_NANOS_ID);
+    metadata.is_folder = table.get_bool(IS_FOLDER_ID);
+    metadata.is_deleted = table.get_bool(IS_DELETED_ID);
+    Ok(metadata)
+  }
+}
+
+/// Build a file bottle: metadata in the header, and the file's contents as
+/// the only stream.
+pub fn write_file_bottle<S>(metadata: &FileMetadata, data: S) -> Bottle<impl ByteStreamStream<S>>
+  where S: ByteStream
+{
+  Bottle::new(BottleType::File, metadata.to_table(), stream_of_streams(vec![ data ]))
+}
+
+/// Build a folder bottle: a file bottle flagged as a folder, where each
+/// stream is an encoded file or folder bottle.
+pub fn write_folder_bottle<S, SS>(metadata: &FileMetadata, entries: SS) -> Bottle<SS>
+  where S: ByteStream, SS: ByteStreamStream<S>
+{
+  assert!(metadata.is_folder);
+  Bottle::new(BottleType::File, metadata.to_table(), entries)
+}
+
+
+// ----- reading
+
+/// One file or folder from a file bottle, or from any depth of a folder
+/// bottle.
+pub struct FileEntry {
+  /// Path from the top of the bottle, with each level separated by `/`.
+  pub path: String,
+  pub metadata: FileMetadata,
+  /// The contents of a file (`None` for folders). This must be drained
+  /// before asking for the next entry.
+  pub data: Option<BoxedByteStream>
+}
+
+/// Walk a file or folder bottle, returning a stream of every file and
+/// folder inside, depth-first, with each folder before its contents. It's
+/// held to the default `ReaderLimits`.
+pub fn read_file_entries<S>(s: ReadableByteStream<S>) -> impl Stream<Item = FileEntry, Error = io::Error>
+  where S: ByteStream + 'static
+{
+  read_file_entries_with_limits(s, Limiter::new(ReaderLimits::default()))
+}
+
+/// Walk a file or folder bottle, failing if it goes over any of the limits.
+pub fn read_file_entries_with_limits<S>(s: ReadableByteStream<S>, limiter: Limiter)
+  -> impl Stream<Item = FileEntry, Error = io::Error>
+  where S: ByteStream + 'static
+{
+  let walker = Walker {
+    folders: Vec::new(),
+    next: Some(( String::new(), Box::new(s.into_stream()), limiter )),
+    cleanup: None
+  };
+  let (entries, _) = generate_stream(walker, |walker| walker.step());
+  entries
+}
+
+struct Folder {
+  path: String,
+  entries: Box<Stream<Item = BoxedByteStream, Error = io::Error>>,
+  limiter: Limiter,
+  // resolves when the end of this folder's bottle has been read:
+  done: BoxedIoFuture<()>
+}
+
+// nested bottles have nested types, so everything in here is boxed.
+struct Walker {
+  folders: Vec<Folder>,
+  // a bottle to read next, the path of the folder it's in, and its limits:
+  next: Option<(String, BoxedByteStream, Limiter)>,
+  // finish reading the previous file's bottle:
+  cleanup: Option<BoxedIoFuture<()>>
+}
+
+impl Walker {
+  fn step(mut self) -> BoxedIoFuture<( Option<FileEntry>, future::FutureResult<Walker, io::Error> )> {
+    if let Some(cleanup) = self.cleanup.take() {
+      return Box::new(cleanup.and_then(move |_| self.step()));
+    }
+
+    if let Some(( prefix, s, limiter )) = self.next.take() {
+      return Box::new(read_bottle_with_limits(ReadableByteStream::from(s), limiter).and_then(move |(bottle, remainder)| {
+        self.open(prefix, bottle, Box::new(remainder))
+      }));
+    }
+
+    match self.folders.pop() {
+      None => Box::new(future::ok(( None, future::ok(self) ))),
+      Some(Folder { path, entries, limiter, done }) => {
+        Box::new(entries.into_future().map_err(|(e, _)| e).and_then(move |(item, entries)| {
+          match item {
+            Some(s) => {
+              self.next = Some(( path.clone(), s, limiter.clone() ));
+              self.folders.push(Folder { path, entries, limiter, done });
+            },
+            None => {
+              self.cleanup = Some(done);
+            }
+          }
+          self.step()
+        }))
+      }
+    }
+  }
+
+  fn open<S, SS>(
+    mut self,
+    prefix: String,
+    bottle: Bottle<SS>,
+    remainder: BoxedIoFuture<ReadableByteStream<S>>
+  ) -> BoxedIoFuture<( Option<FileEntry>, future::FutureResult<Walker, io::Error> )>
+    where
+      S: ByteStream + 'static,
+      SS: Stream<Error = io::Error> + 'static,
+      SS::Item: ByteStream + 'static
+  {
+    if bottle.header.bottle_type != BottleType::File {
+      return Box::new(future::err(not_a_file_error(&bottle.header.bottle_type)));
+    }
+    let metadata = match FileMetadata::from_table(&bottle.header.table) {
+      Ok(metadata) => metadata,
+      Err(e) => return Box::new(future::err(e))
+    };
+    if !is_safe_filename(&metadata.filename) {
+      return Box::new(future::err(bad_filename_error(&metadata.filename)));
+    }
+
+    // a nested bottle is followed by the end of its (outer) stream, but the
+    // outermost bottle may be followed by anything, so leave that alone.
+    let is_outermost = prefix.is_empty();
+    let path = if is_outermost { metadata.filename.clone() } else { format!("{}/{}", prefix, metadata.filename) };
+    let done: BoxedIoFuture<()> = if is_outermost {
+      Box::new(remainder.map(|_| ()))
+    } else {
+      Box::new(remainder.and_then(|s| s.into_stream().for_each(|_| Ok(()))))
+    };
+    let limiter = bottle.limiter.clone();
+    let streams: Box<Stream<Item = BoxedByteStream, Error = io::Error>> =
+      Box::new(bottle.streams.map(|s| Box::new(s) as BoxedByteStream));
+
+    if metadata.is_folder {
+      self.folders.push(Folder { path: path.clone(), entries: streams, limiter, done });
+      return Box::new(future::ok(( Some(FileEntry { path, metadata, data: None }), future::ok(self) )));
+    }
+
+    Box::new(streams.into_future().map_err(|(e, _)| e).map(move |(data, rest)| {
+      // skip any extra streams after the contents, then the end of the bottle.
+      self.cleanup = Some(Box::new(rest.for_each(|s| s.for_each(|_| Ok(()))).and_then(|_| done)));
+      let data: BoxedByteStream = data.unwrap_or_else(|| Box::new(stream::empty()));
+      ( Some(FileEntry { path, metadata, data: Some(data) }), future::ok(self) )
+    }))
+  }
+}
+
+/// Refuse anything that could escape the folder it's restored into.
+pub fn is_safe_filename(filename: &str) -> bool {
+  !filename.is_empty() && filename != "." && filename != ".." &&
+    !filename.contains('/') && !filename.contains('\\') && !filename.contains('\0')
+}
+
+fn nanos_since_epoch(time: SystemTime) -> Option<u64> {
+  time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64)
+}
+
+#[cfg(unix)]
+fn posix_mode(stat: &fs::Metadata) -> Option<u64> {
+  use std::os::unix::fs::MetadataExt;
+  Some((stat.mode() & 0o7777) as u64)
+}
+
+#[cfg(not(unix))]
+fn posix_mode(_stat: &fs::Metadata) -> Option<u64> {
+  None
+}
+
+fn missing_filename_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, "Missing filename")
+}
+
+fn bad_filename_error(filename: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, format!("Invalid filename: {:?}", filename))
+}
+
+fn not_a_file_error(bottle_type: &BottleType) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Not a file bottle: {:?}", bottle_type))
+}
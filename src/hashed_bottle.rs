@@ -0,0 +1,147 @@
+use bytes::Bytes;
+use futures::{Future, future, Stream, stream};
+use sha2::{Digest, Sha256};
+use std::{io, mem};
+use std::sync::{Arc, Mutex};
+
+use bottle::Bottle;
+use header::BottleType;
+use stream_toolkit::{BoxedByteStream, ByteFrame, ByteStream, ByteStreamStream, stream_of_streams};
+use table::Table;
+
+// table fields (numbers):
+const HASH_TYPE_ID: u8 = 0;
+
+// table fields (strings):
+const SIGNED_BY_ID: u8 = 0;
+
+/// Digest used to seal a hashed bottle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashType {
+  Sha256
+}
+
+impl HashType {
+  pub fn from_id(id: u64) -> io::Result<HashType> {
+    match id {
+      0 => Ok(HashType::Sha256),
+      _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown hash type: {}", id)))
+    }
+  }
+
+  pub fn id(&self) -> u64 {
+    match *self {
+      HashType::Sha256 => 0
+    }
+  }
+}
+
+/// Whether the digest of a hashed bottle has been checked yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DigestStatus {
+  /// The data hasn't all been read yet.
+  Unchecked,
+  Valid,
+  Invalid
+}
+
+/// Who signed a hashed bottle. Signatures aren't checked yet, so a signer
+/// named in the header is only a claim.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignerStatus {
+  Unsigned,
+  Unverified(String)
+}
+
+/// Shared view of a `DigestStatus`, which changes once the data in a hashed
+/// bottle has been read to the end.
+#[derive(Clone, Debug)]
+pub struct DigestCheck(Arc<Mutex<DigestStatus>>);
+
+impl DigestCheck {
+  pub fn status(&self) -> DigestStatus {
+    *self.0.lock().unwrap()
+  }
+}
+
+/// Wrap a byte stream (usually an encoded bottle) in a hashed bottle. It
+/// has two streams: the data, then the digest of the data, which is
+/// computed as the data is written.
+pub fn write_hashed_bottle<S>(s: S, hash_type: HashType) -> Bottle<impl ByteStreamStream<BoxedByteStream>>
+  where S: ByteStream + 'static
+{
+  let mut table = Table::new();
+  table.add_number(HASH_TYPE_ID, hash_type.id());
+
+  let hasher = Arc::new(Mutex::new(Sha256::default()));
+  let data_hasher = hasher.clone();
+  let data = s.inspect(move |buffer| data_hasher.lock().unwrap().input(buffer.as_ref()));
+  let digest = future::lazy(move || {
+    let digest = mem::replace(&mut *hasher.lock().unwrap(), Sha256::default()).result();
+    future::ok::<_, io::Error>(Bytes::from(digest.as_slice()))
+  }).into_stream();
+
+  let streams: Vec<BoxedByteStream> = vec![ Box::new(data), Box::new(digest) ];
+  Bottle::new(BottleType::Hashed, table, stream_of_streams(streams))
+}
+
+/// The hash type a hashed bottle was written with.
+pub fn hash_type(table: &Table) -> io::Result<HashType> {
+  let id = table.get_number(HASH_TYPE_ID).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Hashed bottle has no hash type"))?;
+  HashType::from_id(id)
+}
+
+/// Who a hashed bottle says it was signed by.
+pub fn signer(table: &Table) -> SignerStatus {
+  table.get_string(SIGNED_BY_ID).map(|name| SignerStatus::Unverified(name.to_string())).unwrap_or(SignerStatus::Unsigned)
+}
+
+/// Read the data out of a hashed bottle (from `read_bottle`), computing its
+/// digest as it goes. When the data ends, the digest is checked: if it
+/// doesn't match, the data stream ends with an error. The returned
+/// `DigestCheck` reports the result.
+pub fn read_hashed_bottle<S>(bottle: Bottle<S>) -> ( impl ByteStream, DigestCheck )
+  where
+    S: Stream<Error = io::Error> + 'static,
+    S::Item: ByteStream + 'static,
+{
+  let check = DigestCheck(Arc::new(Mutex::new(DigestStatus::Unchecked)));
+  let hash_type = if bottle.header.bottle_type == BottleType::Hashed {
+    hash_type(&bottle.header.table)
+  } else {
+    Err(not_hashed_error(&bottle.header.bottle_type))
+  };
+
+  let status = check.clone();
+  let streams = bottle.streams;
+  let data = future::result(hash_type).and_then(|_| {
+    streams.into_future().map_err(|(e, _)| e)
+  }).and_then(|(data, rest)| {
+    future::result(data.ok_or_else(missing_stream_error)).map(move |data| {
+      let hasher = Arc::new(Mutex::new(Sha256::default()));
+      let data_hasher = hasher.clone();
+      let digest = rest.into_future().map_err(|(e, _)| e).and_then(|(digest, _)| {
+        future::result(digest.ok_or_else(missing_stream_error)).and_then(|digest| digest.collect())
+      }).and_then(move |buffers| {
+        let expected = mem::replace(&mut *hasher.lock().unwrap(), Sha256::default()).result();
+        let valid = ByteFrame::from(buffers).pack().as_ref() == expected.as_slice();
+        *status.0.lock().unwrap() = if valid { DigestStatus::Valid } else { DigestStatus::Invalid };
+        if valid { Ok(stream::empty()) } else { Err(bad_digest_error()) }
+      }).flatten_stream();
+      data.inspect(move |buffer| data_hasher.lock().unwrap().input(buffer.as_ref())).chain(digest)
+    })
+  }).flatten_stream();
+  ( data, check )
+}
+
+fn not_hashed_error(bottle_type: &BottleType) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Not a hashed bottle: {:?}", bottle_type))
+}
+
+fn missing_stream_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "Hashed bottle is missing a stream")
+}
+
+fn bad_digest_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "Hashed bottle digest doesn't match")
+}
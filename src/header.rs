@@ -1,81 +1,254 @@
+#[cfg(feature = "std")]
 use bytes::{Bytes};
-use std::fmt;
-use std::io;
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use futures::{Future, future, Stream};
 
+use error::{self, Error, ErrorKind};
+#[cfg(feature = "std")]
+use reader_limits::{Limiter, ReaderLimits};
+#[cfg(feature = "std")]
 use stream_toolkit::{ReadableByteStream, stream_of_vec};
 use table::Table;
 
-static MAGIC: [u8; 4] = [ 0xf0, 0x9f, 0x8d, 0xbc ];
-const VERSION: u8 = 0;
+/// Every bottle starts with "🍼".
+pub static MAGIC: [u8; 4] = [ 0xf0, 0x9f, 0x8d, 0xbc ];
+
+/// Format version of a bottle: the major version is byte 4 of the header,
+/// and the minor version is byte 5. A minor revision may only add table
+/// fields that an older reader can safely ignore, so any minor version of a
+/// known major version can be read. A new major version is incompatible.
+///
+/// The header has no flags: the rest of the prefix is the bottle type and
+/// table length, so anything new goes in the table.
+///
+/// - 0.0: the original format.
+/// - 0.1: adds bytes fields to the table. A 0.0 reader rejects these.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+  pub major: u8,
+  pub minor: u8
+}
+
+/// The newest version this library writes. By default, a header is
+/// written as the oldest version that can hold its table, so older readers
+/// can read it too.
+pub const VERSION: Version = Version { major: 0, minor: 1 };
+
+/// The original format.
+pub const FIRST_VERSION: Version = Version { major: 0, minor: 0 };
+
+/// The first version that can hold bytes fields.
+pub const BYTES_FIELDS_VERSION: Version = Version { major: 0, minor: 1 };
+
+impl Version {
+  /// Can a bottle of this version be read?
+  pub fn is_readable(&self) -> bool {
+    self.major == VERSION.major
+  }
+
+  /// Can a bottle be written as this version? Only this version and older
+  /// minor versions of it can be written.
+  pub fn is_writable(&self) -> bool {
+    self.major == VERSION.major && *self <= VERSION
+  }
+}
+
+impl fmt::Display for Version {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}.{}", self.major, self.minor)
+  }
+}
+
+/// Size of the fixed start of a header, before the table.
+pub const HEADER_PREFIX_SIZE: usize = 8;
 
 const MAX_TABLE_SIZE: usize = 4095;
 
-/// Bottle type (0 - 15) as defined in the spec.
+/// Bottle type (0 - 15) as defined in the spec. Any other type is read as
+/// `Other`, which an application can give meaning to by registering a
+/// `BottleKind`.
 #[derive(Clone, Debug, PartialEq)]
 pub enum BottleType {
-  File = 0,
-  Hashed = 1,
-  Encrypted = 3,
-  Compressed = 4,
+  File,
+  Hashed,
+  Encrypted,
+  Compressed,
+  Chunked,
+  Parity,
   // for tests:
-  Test = 10,
-  Test2 = 11
+  Test,
+  Test2,
+  Other(u8)
 }
 
-fn decode_bottle_type(btype: u8) -> Result<BottleType, io::Error> {
-  match btype {
-    0 => Ok(BottleType::File),
-    1 => Ok(BottleType::Hashed),
-    3 => Ok(BottleType::Encrypted),
-    4 => Ok(BottleType::Compressed),
-    10 => Ok(BottleType::Test),
-    11 => Ok(BottleType::Test2),
-    _ => Err(unknown_bottle_type_error(btype))
+impl BottleType {
+  pub fn from_id(id: u8) -> BottleType {
+    match id {
+      0 => BottleType::File,
+      1 => BottleType::Hashed,
+      3 => BottleType::Encrypted,
+      4 => BottleType::Compressed,
+      5 => BottleType::Chunked,
+      6 => BottleType::Parity,
+      10 => BottleType::Test,
+      11 => BottleType::Test2,
+      _ => BottleType::Other(id)
+    }
+  }
+
+  pub fn id(&self) -> u8 {
+    match *self {
+      BottleType::File => 0,
+      BottleType::Hashed => 1,
+      BottleType::Encrypted => 3,
+      BottleType::Compressed => 4,
+      BottleType::Chunked => 5,
+      BottleType::Parity => 6,
+      BottleType::Test => 10,
+      BottleType::Test2 => 11,
+      BottleType::Other(id) => id
+    }
   }
 }
 
 /// The header (magic bytes, bottle type, and key/value table) for a bottle.
 pub struct Header {
   pub bottle_type: BottleType,
-  pub table: Table
+  pub table: Table,
+  /// The version this header was read as, or will be written as.
+  pub version: Version
 }
 
 impl Header {
+  /// A header written as the oldest version that can hold `table`.
   pub fn new(bottle_type: BottleType, table: Table) -> Header {
-    Header { bottle_type: bottle_type, table: table }
+    let version = if table.has_bytes_fields() { BYTES_FIELDS_VERSION } else { FIRST_VERSION };
+    Header { bottle_type: bottle_type, table: table, version }
+  }
+
+  /// Write this header as an older version, for readers that don't
+  /// understand anything newer. The table must only use fields that
+  /// version has, or encoding will fail.
+  pub fn with_version(self, version: Version) -> Header {
+    assert!(version.is_writable(), "Can't write version {}", version);
+    Header { version, ..self }
+  }
+
+  /// Check that this header can be written: its bottle type must fit in
+  /// 4 bits, and its table must fit its version.
+  pub fn check_writable(&self) -> error::Result<()> {
+    let id = self.bottle_type.id();
+    if id > 15 {
+      return Err(Error::new(ErrorKind::InvalidInput, format!("Bottle type {} is out of range (0 - 15)", id)));
+    }
+    self.check_version()
+  }
+
+  /// Check that this header can be written as its version.
+  pub fn check_version(&self) -> error::Result<()> {
+    if !self.version.is_writable() { return Err(bad_version_error(self.version)) }
+    if self.version < BYTES_FIELDS_VERSION && self.table.has_bytes_fields() {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("Version {} can't hold bytes fields", self.version)
+      ));
+    }
+    Ok(())
+  }
+
+  /// Generate a stream of the serialized format of this header. If the
+  /// header can't be written, the stream is just the error.
+  #[cfg(feature = "std")]
+  pub fn encode(&self) -> impl Stream<Item = Bytes, Error = Error> {
+    future::result(self.encode_buffers()).map(stream_of_vec).flatten_stream()
   }
 
-  /// Generate a stream of the serialized format of this header.
-  pub fn encode(&self) -> impl Stream<Item = Bytes, Error = io::Error> {
+  /// Serialize this header into a list of buffers.
+  #[cfg(feature = "std")]
+  pub fn encode_buffers(&self) -> error::Result<Vec<Bytes>> {
     let table_bytes = self.table.encode();
-    let bottle_type_u8 = self.bottle_type.clone() as u8;
-    assert!(table_bytes.len() <= MAX_TABLE_SIZE);
-    let version: [u8; 4] = [
-      VERSION,
-      0,
-      (bottle_type_u8 << 4) | ((table_bytes.len() >> 8) & 0xf) as u8,
-      (table_bytes.len() & 0xff) as u8
-    ];
-    stream_of_vec(vec![ Bytes::from_static(&MAGIC), Bytes::from(&version[..]), Bytes::from(table_bytes) ])
+    let version = self.encode_version(table_bytes.len())?;
+    Ok(vec![ Bytes::from_static(&MAGIC), Bytes::from(&version[..]), table_bytes ])
+  }
+
+  /// Append the serialized header to a buffer. Nothing is appended if the
+  /// header can't be written.
+  pub fn encode_into(&self, out: &mut Vec<u8>) -> error::Result<()> {
+    let mut table_bytes = Vec::new();
+    self.table.encode_into(&mut table_bytes);
+    let version = self.encode_version(table_bytes.len())?;
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&version);
+    out.extend_from_slice(&table_bytes);
+    Ok(())
+  }
+
+  // the rest of the fixed prefix, after the magic.
+  fn encode_version(&self, table_length: usize) -> error::Result<[u8; 4]> {
+    self.check_writable()?;
+    if table_length > MAX_TABLE_SIZE {
+      return Err(Error::new(ErrorKind::InvalidInput, format!("Header table is too large ({} bytes)", table_length)));
+    }
+    Ok([
+      self.version.major,
+      self.version.minor,
+      (self.bottle_type.id() << 4) | ((table_length >> 8) & 0xf) as u8,
+      (table_length & 0xff) as u8
+    ])
   }
 
   /// Read a bottle header from a `Stream<Bytes>`, and return the header and
-  /// the remainder of the stream.
+  /// the remainder of the stream. It's held to the default `ReaderLimits`.
+  #[cfg(feature = "std")]
   pub fn decode<S>(s: ReadableByteStream<S>)
-    -> impl Future<Item = (Header, ReadableByteStream<S>), Error = io::Error>
-    where S: Stream<Item = Bytes, Error = io::Error>
+    -> impl Future<Item = (Header, ReadableByteStream<S>), Error = Error>
+    where S: Stream<Item = Bytes, Error = Error>
+  {
+    Header::decode_with_limits(s, &Limiter::new(ReaderLimits::default()))
+  }
+
+  /// Read a bottle header, failing if it's nested too deeply, or its table
+  /// is too large.
+  #[cfg(feature = "std")]
+  pub fn decode_with_limits<S>(s: ReadableByteStream<S>, limiter: &Limiter)
+    -> impl Future<Item = (Header, ReadableByteStream<S>), Error = Error>
+    where S: Stream<Item = Bytes, Error = Error>
   {
-    s.read_exact(8).and_then(|(frame, s)| {
-      future::result(check_magic(frame.pack())).and_then(|( bottle_type, header_length )| {
-        s.read_exact(header_length).and_then(|(frame, s)| {
-          future::result(Table::decode(frame.pack())).map(|header| {
-            ( Header::new(bottle_type, header), s )
+    let limiter = limiter.clone();
+    future::result(limiter.check_depth()).and_then(move |_| s.read_exact(HEADER_PREFIX_SIZE)).and_then(move |(frame, s)| {
+      future::result(Header::decode_prefix(frame.pack().as_ref()).and_then(|( version, bottle_type, header_length )| {
+        limiter.check_table_size(header_length).map(|_| ( version, bottle_type, header_length ))
+      })).and_then(|( version, bottle_type, header_length )| {
+        s.read_exact(header_length).and_then(move |(frame, s)| {
+          future::result(Table::decode(frame.pack())).map(move |header| {
+            ( Header { version, ..Header::new(bottle_type, header) }, s )
           })
         })
       })
     })
   }
+
+  /// Check the fixed start of a header (magic, version, and type), and
+  /// return the version, the bottle type, and the length of the table that
+  /// follows.
+  pub fn decode_prefix(buffer: &[u8]) -> error::Result<(Version, BottleType, usize)> {
+    if buffer.len() < HEADER_PREFIX_SIZE {
+      return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated header"));
+    }
+    if buffer[0 .. 4] != MAGIC[..] {
+      return Err(bad_magic_error());
+    }
+    let version = Version { major: buffer[4], minor: buffer[5] };
+    if !version.is_readable() {
+      return Err(bad_version_error(version));
+    }
+    let btype = BottleType::from_id((buffer[6] >> 4) & 0xf);
+    let header_length = (((buffer[6] & 0xf) as usize) << 8) + (buffer[7] as usize);
+    Ok((version, btype, header_length))
+  }
 }
 
 impl fmt::Debug for Header {
@@ -84,26 +257,10 @@ impl fmt::Debug for Header {
   }
 }
 
-fn check_magic(buffer: Bytes) -> Result<(BottleType, usize), io::Error> {
-  if buffer.slice(0, 4) != &MAGIC[..] {
-    return Err(bad_magic_error());
-  }
-  if buffer[4] != VERSION || buffer[5] != 0 {
-    return Err(bad_version_error(buffer[4], buffer[5]));
-  }
-  let btype = decode_bottle_type((buffer[6] >> 4) & 0xf)?;
-  let header_length = (((buffer[6] & 0xf) as usize) << 8) + (buffer[7] as usize);
-  Ok((btype, header_length))
-}
-
-fn bad_magic_error() -> io::Error {
-  io::Error::new(io::ErrorKind::InvalidInput, "Incorrect magic (not a 4bottle archive)")
-}
-
-fn bad_version_error(version: u8, extra: u8) -> io::Error {
-  io::Error::new(io::ErrorKind::InvalidInput, format!("Incompatible version: {}, {}", version, extra))
+fn bad_magic_error() -> Error {
+  Error::new(ErrorKind::InvalidInput, "Incorrect magic (not a 4bottle archive)")
 }
 
-fn unknown_bottle_type_error(bottle_type: u8) -> io::Error {
-  io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown bottle type: {}", bottle_type))
+fn bad_version_error(version: Version) -> Error {
+  Error::new(ErrorKind::InvalidInput, format!("Incompatible version: {}", version))
 }
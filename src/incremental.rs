@@ -0,0 +1,298 @@
+use futures::{Future, future, Stream, stream};
+use sha2::{Digest, Sha256};
+use std::{fs, io};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use bottle::Bottle;
+use file_bottle::{FileEntry, FileMetadata, read_file_entries, write_file_bottle, write_folder_bottle};
+use header::BottleType;
+use stream_toolkit::{BoxedByteStream, BoxedIoFuture, ByteStream, ReadableByteStream, ReaderByteStream, ToHex};
+
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Metadata for every file and folder in an archive (or on disk), by path.
+/// Paths start with the name of the top folder, and each level is separated
+/// by `/`, just like `FileEntry`.
+pub struct ArchiveIndex {
+  pub entries: BTreeMap<String, FileMetadata>
+}
+
+/// What changed between two indexes.
+#[derive(Debug, PartialEq)]
+pub struct Changes {
+  /// New or modified files and folders.
+  pub changed: Vec<String>,
+  /// Deleted files and folders. (When a folder is deleted, only the folder
+  /// itself is listed.)
+  pub deleted: Vec<String>
+}
+
+impl ArchiveIndex {
+  pub fn new() -> ArchiveIndex {
+    ArchiveIndex { entries: BTreeMap::new() }
+  }
+
+  /// Scan a folder on disk. If `hash` is set, every file is read to compute
+  /// its SHA-256, which catches changes that kept the same size and mtime.
+  pub fn scan(root: &Path, hash: bool) -> io::Result<ArchiveIndex> {
+    let mut index = ArchiveIndex::new();
+    let root = root.canonicalize()?;
+    let metadata = FileMetadata::from_path(&root)?;
+    let path = metadata.filename.clone();
+    index.scan_entry(&root, path, metadata, hash)?;
+    Ok(index)
+  }
+
+  fn scan_entry(&mut self, disk_path: &Path, path: String, mut metadata: FileMetadata, hash: bool) -> io::Result<()> {
+    if metadata.is_folder {
+      let mut children = fs::read_dir(disk_path)?.collect::<io::Result<Vec<fs::DirEntry>>>()?;
+      children.sort_by_key(|entry| entry.file_name());
+      for child in children {
+        // only plain files and folders can be archived.
+        let file_type = child.file_type()?;
+        if !file_type.is_file() && !file_type.is_dir() { continue }
+        let child_metadata = FileMetadata::from_path(&child.path())?;
+        let child_path = format!("{}/{}", path, child_metadata.filename);
+        self.scan_entry(&child.path(), child_path, child_metadata, hash)?;
+      }
+    } else if hash {
+      metadata.sha256 = Some(hash_file(disk_path)?);
+    }
+    self.entries.insert(path, metadata);
+    Ok(())
+  }
+
+  /// Read the index of an archive (full or incremental), skipping over the
+  /// file contents.
+  pub fn read<S>(s: ReadableByteStream<S>) -> impl Future<Item = ArchiveIndex, Error = io::Error>
+    where S: ByteStream + 'static
+  {
+    read_file_entries(s).fold(ArchiveIndex::new(), |mut index, entry| {
+      let FileEntry { path, metadata, data } = entry;
+      drain(data).map(move |_| {
+        index.entries.insert(path, metadata);
+        index
+      })
+    })
+  }
+
+  /// Apply the index of an incremental archive on top of this one, so that
+  /// it describes the state after the incremental.
+  pub fn update(&mut self, incremental: ArchiveIndex) {
+    for (path, metadata) in incremental.entries {
+      let prefix = format!("{}/", path);
+      let inside = self.entries.keys().filter(|p| p.starts_with(&prefix)).cloned().collect::<Vec<String>>();
+      if metadata.is_deleted {
+        for p in inside { self.entries.remove(&p); }
+        self.entries.remove(&path);
+      } else {
+        // a folder replaced by a file takes its contents with it.
+        if !metadata.is_folder { for p in inside { self.entries.remove(&p); } }
+        self.entries.insert(path, metadata);
+      }
+    }
+  }
+
+  /// Find everything that changed between this (previous) index and a
+  /// current one. A file has changed if its size, mtime, or hash (when both
+  /// sides have one) is different.
+  pub fn changes(&self, current: &ArchiveIndex) -> Changes {
+    let changed = current.entries.iter().filter(|&(path, metadata)| {
+      match self.entries.get(path) {
+        None => true,
+        Some(old) => {
+          old.is_deleted || old.is_folder != metadata.is_folder || (!metadata.is_folder && is_modified(old, metadata))
+        }
+      }
+    }).map(|(path, _)| path.clone()).collect();
+
+    let deleted = self.entries.iter().filter(|&(path, old)| {
+      !old.is_deleted && !current.entries.contains_key(path) &&
+        parent_of(path).map(|parent| current.entries.contains_key(parent)).unwrap_or(false)
+    }).map(|(path, _)| path.clone()).collect();
+
+    Changes { changed, deleted }
+  }
+}
+
+fn is_modified(old: &FileMetadata, new: &FileMetadata) -> bool {
+  let hash_changed = match (&old.sha256, &new.sha256) {
+    (&Some(ref a), &Some(ref b)) => a != b,
+    _ => false
+  };
+  old.size != new.size || old.modified_nanos != new.modified_nanos || hash_changed
+}
+
+fn parent_of(path: &str) -> Option<&str> {
+  path.rfind('/').map(|i| &path[0 .. i])
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+  let mut file = fs::File::open(path)?;
+  let mut hasher = Sha256::default();
+  let mut buffer = vec![ 0u8; BLOCK_SIZE ];
+  loop {
+    let n = file.read(&mut buffer)?;
+    if n == 0 { break }
+    hasher.input(&buffer[0 .. n]);
+  }
+  Ok(hasher.result().as_slice().to_hex())
+}
+
+fn drain(data: Option<BoxedByteStream>) -> BoxedIoFuture<()> {
+  match data {
+    None => Box::new(future::ok(())),
+    Some(s) => Box::new(s.for_each(|_| Ok(())))
+  }
+}
+
+
+// ----- writing
+
+/// Archive the folder at `root`, but only the files that changed since the
+/// `previous` index, plus a tombstone for each deleted file. Folders leading
+/// to a change are included so the nesting is intact, and the top folder is
+/// always included. Against an empty index, this is a full archive.
+///
+/// Returns the archive's byte stream, and the index of `root` as scanned,
+/// to use as the `previous` for the next incremental.
+pub fn write_incremental_archive(root: &Path, previous: &ArchiveIndex, hash: bool)
+  -> io::Result<( BoxedByteStream, ArchiveIndex )>
+{
+  let root = root.canonicalize()?;
+  let base = root.parent().map(|p| p.to_path_buf()).unwrap_or_else(|| PathBuf::from("/"));
+  let current = ArchiveIndex::scan(&root, hash)?;
+  let changes = previous.changes(&current);
+
+  let mut selected: BTreeMap<String, FileMetadata> = BTreeMap::new();
+  for path in changes.changed {
+    selected.insert(path.clone(), current.entries[&path].clone());
+  }
+  for path in changes.deleted {
+    let mut tombstone = FileMetadata::new(path[path.rfind('/').map(|i| i + 1).unwrap_or(0) ..].to_string());
+    tombstone.is_deleted = true;
+    selected.insert(path, tombstone);
+  }
+  let mut parents = Vec::new();
+  for path in selected.keys() {
+    let mut p: &str = path;
+    while let Some(parent) = parent_of(p) {
+      parents.push(parent.to_string());
+      p = parent;
+    }
+  }
+  let top = current.entries.keys().next().cloned().ok_or_else(|| empty_folder_error())?;
+  parents.push(top.clone());
+  for path in parents {
+    let metadata = current.entries[&path].clone();
+    selected.entry(path).or_insert(metadata);
+  }
+
+  let mut children: BTreeMap<String, Vec<String>> = BTreeMap::new();
+  for path in selected.keys() {
+    for parent in parent_of(path) {
+      children.entry(parent.to_string()).or_insert_with(Vec::new).push(path.clone());
+    }
+  }
+
+  let archive = encode_entry(&top, &selected, &mut children, &base);
+  Ok(( archive, current ))
+}
+
+// nested bottles have nested types, so box it.
+fn encode_entry(
+  path: &str,
+  selected: &BTreeMap<String, FileMetadata>,
+  children: &mut BTreeMap<String, Vec<String>>,
+  base: &Path
+) -> BoxedByteStream {
+  let metadata = &selected[path];
+  if metadata.is_deleted {
+    let empty = stream::empty::<BoxedByteStream, io::Error>();
+    return Box::new(Bottle::new(BottleType::File, metadata.to_table(), empty).encode());
+  }
+
+  if metadata.is_folder {
+    let entries = children.remove(path).unwrap_or_else(Vec::new).iter().map(|child| {
+      encode_entry(child, selected, children, base)
+    }).collect::<Vec<BoxedByteStream>>();
+    let entries = stream::iter(entries.into_iter().map(Ok));
+    return Box::new(write_folder_bottle(metadata, entries).encode());
+  }
+
+  // don't open the file until it's time to read it.
+  let disk_path = path.split('/').fold(base.to_path_buf(), |p, name| p.join(name));
+  let data = future::lazy(move || fs::File::open(disk_path)).map(|file| {
+    ReaderByteStream::new(file, BLOCK_SIZE)
+  }).flatten_stream();
+  Box::new(write_file_bottle(metadata, data).encode())
+}
+
+
+// ----- restoring
+
+/// Rebuild a folder inside `dest` by restoring a full archive, then each
+/// incremental archive in order: changed files are overwritten, and files
+/// with tombstones are removed.
+pub fn restore_archives<S>(archives: Vec<ReadableByteStream<S>>, dest: &Path) -> impl Future<Item = (), Error = io::Error>
+  where S: ByteStream + 'static
+{
+  let dest = dest.to_path_buf();
+  stream::iter(archives.into_iter().map(Ok)).for_each(move |s| {
+    let dest = dest.clone();
+    read_file_entries(s).for_each(move |entry| restore_entry(entry, &dest))
+  })
+}
+
+fn restore_entry(entry: FileEntry, dest: &Path) -> BoxedIoFuture<()> {
+  let FileEntry { path, metadata, data } = entry;
+  let disk_path = path.split('/').fold(dest.to_path_buf(), |p, name| p.join(name));
+  let prepared = if metadata.is_deleted {
+    remove_path(&disk_path)
+  } else if metadata.is_folder {
+    // a file may be replaced by a folder, or vice versa.
+    let removed = if disk_path.is_file() { fs::remove_file(&disk_path) } else { Ok(()) };
+    removed.and_then(|_| fs::create_dir_all(&disk_path))
+  } else {
+    if disk_path.is_dir() { fs::remove_dir_all(&disk_path) } else { Ok(()) }
+  };
+  if let Err(e) = prepared { return Box::new(future::err(e)) }
+
+  if metadata.is_deleted || data.is_none() { return drain(data) }
+  let data = data.unwrap();
+  let file = match fs::File::create(&disk_path) {
+    Ok(file) => file,
+    Err(e) => return Box::new(future::err(e))
+  };
+  Box::new(data.fold(file, |mut file, b| file.write_all(&b).map(|_| file)).and_then(move |_| {
+    set_posix_mode(&disk_path, metadata.posix_mode)
+  }))
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+  let result = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+  match result {
+    Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+    other => other
+  }
+}
+
+#[cfg(unix)]
+fn set_posix_mode(path: &Path, mode: Option<u64>) -> io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+  match mode {
+    None => Ok(()),
+    Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode as u32))
+  }
+}
+
+#[cfg(not(unix))]
+fn set_posix_mode(_path: &Path, _mode: Option<u64>) -> io::Result<()> {
+  Ok(())
+}
+
+fn empty_folder_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, "Nothing to archive")
+}
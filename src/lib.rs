@@ -1,16 +1,77 @@
 #![feature(conservative_impl_trait)]
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(not(feature = "std"), feature(alloc))]
 
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+
+#[cfg(feature = "std")]
 extern crate bytes;
+#[cfg(feature = "std")]
 extern crate futures;
+#[cfg(feature = "std")]
+extern crate iovec;
+#[cfg(feature = "std")]
+extern crate sha2;
+#[cfg(feature = "std")]
+extern crate tokio_io;
 
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate lazy_static;
 
+// the format core uses `core` (and `alloc`, when there's no `std`) directly.
+#[cfg(feature = "std")]
+extern crate core;
+
 // these could really be in a shared library somewhere:
+#[cfg(feature = "std")]
 pub mod stream_toolkit;
 
 // intrinsic to 4bottle format:
+#[cfg(feature = "std")]
 pub mod bottle;
+#[cfg(feature = "std")]
+pub mod bottle_sink;
+#[cfg(feature = "std")]
+pub mod bottle_kind;
+pub mod encoder;
+pub mod error;
 pub mod header;
+#[cfg(feature = "std")]
+pub mod observer;
+pub mod parser;
+pub mod reader_limits;
 pub mod table;
 pub mod zint;
+
+// bottle types & their support:
+#[cfg(feature = "std")]
+pub mod bottle_builder;
+#[cfg(feature = "std")]
+pub mod chunk_store;
+#[cfg(feature = "std")]
+pub mod chunked_bottle;
+#[cfg(feature = "std")]
+pub mod compressed_bottle;
+#[cfg(feature = "std")]
+pub mod dump;
+#[cfg(feature = "std")]
+pub mod encrypted_bottle;
+#[cfg(feature = "std")]
+pub mod file_bottle;
+#[cfg(feature = "std")]
+pub mod hashed_bottle;
+#[cfg(feature = "std")]
+pub mod incremental;
+#[cfg(feature = "std")]
+pub mod parity_bottle;
+#[cfg(feature = "std")]
+pub mod reed_solomon;
+#[cfg(feature = "std")]
+pub mod salvage;
+#[cfg(feature = "std")]
+pub mod unwrapper;
+#[cfg(feature = "std")]
+pub mod volume;
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use header::Header;
+
+/// Hooks for watching a bottle as it's written or read, for progress bars
+/// or metrics. Every method does nothing by default, so an observer only
+/// needs to implement the events it cares about.
+///
+/// `depth` is 0 for the outermost bottle, 1 for a bottle nested inside it,
+/// and so on. Streams are numbered from 0 within each bottle.
+pub trait BottleObserver {
+  /// A bottle's header was written or read.
+  fn header(&self, _depth: usize, _header: &Header) {}
+
+  /// A bottle nested inside another one was found, at `depth`. This is
+  /// reported just before its header.
+  fn nested_bottle(&self, _depth: usize) {}
+
+  fn stream_start(&self, _depth: usize, _index: usize) {}
+
+  fn stream_end(&self, _depth: usize, _index: usize) {}
+
+  /// A frame of `length` bytes of data was written or read (or skipped).
+  fn frame(&self, _depth: usize, _length: usize) {}
+}
+
+/// An observer that can be shared by every stream in a bottle, and every
+/// bottle nested inside it.
+pub type SharedObserver = Arc<BottleObserver + Send + Sync>;
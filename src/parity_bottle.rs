@@ -0,0 +1,285 @@
+use bytes::Bytes;
+use futures::{Future, future, Stream};
+use std::io;
+use std::sync::Arc;
+
+use header::{BottleType, Header};
+use reed_solomon::ReedSolomon;
+use stream_toolkit::{BufferedByteStream, ByteFrame, ByteStream, generate_stream, IoFuture, ReadableByteStream, stream_of, stream_of_vec};
+use table::Table;
+use zint;
+
+// table fields (numbers):
+const BLOCK_SIZE_ID: u8 = 0;
+const DATA_BLOCKS_ID: u8 = 1;
+const PARITY_BLOCKS_ID: u8 = 2;
+
+// each group starts with the length of its data (u32) and a checksum of
+// that, three times over, so one damaged copy doesn't lose the group.
+const GROUP_HEADER_COPY_SIZE: usize = 8;
+const GROUP_HEADER_COPIES: usize = 3;
+const GROUP_HEADER_SIZE: usize = GROUP_HEADER_COPY_SIZE * GROUP_HEADER_COPIES;
+const CHECKSUM_SIZE: usize = 4;
+
+/// Largest block size allowed, so a damaged or hostile header can't make a
+/// reader allocate huge groups.
+pub const MAX_BLOCK_SIZE: usize = 1 << 20;
+
+/// Largest group allowed, counting every data and parity record, since a
+/// reader holds a whole group at once.
+pub const MAX_GROUP_SIZE: usize = 64 << 20;
+
+lazy_static! {
+  static ref CRC32_TABLE: [u32; 256] = {
+    let mut table = [ 0u32; 256 ];
+    for i in 0 .. 256 {
+      let mut n = i as u32;
+      for _ in 0 .. 8 { n = if n & 1 != 0 { 0xedb88320 ^ (n >> 1) } else { n >> 1 } };
+      table[i] = n;
+    }
+    table
+  };
+}
+
+/// CRC-32 (the zlib one) of a buffer.
+pub fn crc32(buffer: &[u8]) -> u32 {
+  !buffer.iter().fold(!0u32, |crc, &b| CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// How data is cut into blocks and protected: each group of `data_blocks`
+/// blocks of `block_size` bytes gets `parity_blocks` blocks of parity, so
+/// up to `parity_blocks` damaged blocks per group can be rebuilt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParityParameters {
+  pub block_size: usize,
+  pub data_blocks: usize,
+  pub parity_blocks: usize
+}
+
+impl ParityParameters {
+  pub fn new(block_size: usize, data_blocks: usize, parity_blocks: usize) -> ParityParameters {
+    let parameters = ParityParameters { block_size, data_blocks, parity_blocks };
+    assert!(parameters.is_valid(), "Invalid parity parameters: {:?}", parameters);
+    parameters
+  }
+
+  pub fn to_table(&self) -> Table {
+    let mut table = Table::new();
+    table.add_number(BLOCK_SIZE_ID, self.block_size as u64);
+    table.add_number(DATA_BLOCKS_ID, self.data_blocks as u64);
+    table.add_number(PARITY_BLOCKS_ID, self.parity_blocks as u64);
+    table
+  }
+
+  pub fn from_table(table: &Table) -> io::Result<ParityParameters> {
+    let number = |id: u8| -> io::Result<usize> {
+      let n = table.get_number(id).unwrap_or(0);
+      // anything bigger can't pass `is_valid`; this just keeps it from wrapping.
+      if n > MAX_BLOCK_SIZE as u64 { Err(bad_parameters_error()) } else { Ok(n as usize) }
+    };
+    let parameters = ParityParameters {
+      block_size: number(BLOCK_SIZE_ID)?,
+      data_blocks: number(DATA_BLOCKS_ID)?,
+      parity_blocks: number(PARITY_BLOCKS_ID)?
+    };
+    if parameters.is_valid() { Ok(parameters) } else { Err(bad_parameters_error()) }
+  }
+
+  fn is_valid(&self) -> bool {
+    self.block_size > 0 && self.block_size <= MAX_BLOCK_SIZE &&
+      self.data_blocks > 0 && self.parity_blocks > 0 &&
+      self.data_blocks.checked_add(self.parity_blocks).map_or(false, |n| n <= 256) &&
+      (self.data_blocks + self.parity_blocks).checked_mul(self.record_size()).map_or(false, |n| n <= MAX_GROUP_SIZE)
+  }
+
+  // only called on valid parameters, so it can't overflow.
+  fn group_size(&self) -> usize {
+    self.block_size * self.data_blocks
+  }
+
+  fn record_size(&self) -> usize {
+    CHECKSUM_SIZE + self.block_size
+  }
+}
+
+/// Wrap a byte stream (usually an encoded bottle) in a parity bottle, and
+/// encode it. The data is cut into groups of blocks, and each group is
+/// written as one stream:
+///   - data length (u32, LSB) and its CRC-32, three times, as one frame
+///   - each data block, then each parity block, preceded by its CRC-32, as
+///     one frame each
+///
+/// The last block of the last group is padded with zeros.
+///
+/// Because every frame has a length known from the header, a reader can
+/// skip over the frame lengths (and end-of-stream markers) by position,
+/// without trusting them, so damage there is harmless too.
+pub fn encode_parity_bottle<S>(s: S, parameters: ParityParameters) -> impl ByteStream
+  where S: ByteStream
+{
+  let codec = ReedSolomon::new(parameters.data_blocks, parameters.parity_blocks);
+  let header = Header::new(BottleType::Parity, parameters.to_table());
+  let groups = BufferedByteStream::new(s, parameters.group_size(), true).map(move |frame| {
+    stream_of_vec(encode_group(frame, &parameters, &codec))
+  }).flatten();
+  header.encode().chain(groups).chain(stream_of(zint::END_OF_BOTTLE_BYTES.clone()))
+}
+
+fn encode_group(frame: ByteFrame, parameters: &ParityParameters, codec: &ReedSolomon) -> Vec<Bytes> {
+  let length = frame.length;
+  let block_count = (length + parameters.block_size - 1) / parameters.block_size;
+  let mut data = frame.pack().to_vec();
+  data.resize(parameters.group_size(), 0);
+  let parity = {
+    let blocks = data.chunks(parameters.block_size).collect::<Vec<&[u8]>>();
+    codec.encode(&blocks)
+  };
+
+  let mut out = Vec::with_capacity(2 * (block_count + parameters.parity_blocks) + 3);
+  let length_bytes = encode_u32(length as u32);
+  let mut group_header = Vec::with_capacity(GROUP_HEADER_SIZE);
+  for _ in 0 .. GROUP_HEADER_COPIES {
+    group_header.extend_from_slice(&length_bytes);
+    group_header.extend_from_slice(&encode_u32(crc32(&length_bytes)));
+  }
+  out.push(zint::encode_length(GROUP_HEADER_SIZE));
+  out.push(Bytes::from(group_header));
+  for block in data.chunks(parameters.block_size).take(block_count).chain(parity.iter().map(|p| p.as_ref())) {
+    let mut record = Vec::with_capacity(parameters.record_size());
+    record.extend_from_slice(&encode_u32(crc32(block)));
+    record.extend_from_slice(block);
+    out.push(zint::encode_length(record.len()));
+    out.push(Bytes::from(record));
+  }
+  out.push(zint::END_OF_STREAM_BYTES.clone());
+  out
+}
+
+/// One group of data from a parity bottle, after checking each block and
+/// rebuilding any damaged ones.
+pub struct ParityGroup {
+  pub data: Bytes,
+  /// How many blocks failed their checksum and were rebuilt.
+  pub repaired_blocks: usize
+}
+
+/// Read the groups of an encoded parity bottle, repairing damaged blocks
+/// from the parity blocks when possible. If a group has more damaged blocks
+/// than parity blocks, it can't be repaired, and the stream ends with an
+/// error.
+///
+/// This reads the encoded bottle directly, instead of a `Bottle` from
+/// `read_bottle`, so that damaged frame lengths can be skipped.
+pub fn read_parity_groups<S>(s: ReadableByteStream<S>) -> impl Stream<Item = ParityGroup, Error = io::Error>
+  where S: ByteStream
+{
+  Header::decode(s).and_then(|(header, s)| {
+    let parameters = if header.bottle_type == BottleType::Parity {
+      ParityParameters::from_table(&header.table)
+    } else {
+      Err(not_parity_error(&header.bottle_type))
+    };
+    future::result(parameters).map(|parameters| {
+      let codec = Arc::new(ReedSolomon::new(parameters.data_blocks, parameters.parity_blocks));
+      let (groups, _) = generate_stream(s, move |s| read_group(s, parameters, codec.clone()));
+      groups
+    })
+  }).flatten_stream()
+}
+
+/// Read the original data out of an encoded parity bottle, repairing it as
+/// needed.
+pub fn read_parity_bottle<S>(s: ReadableByteStream<S>) -> impl ByteStream
+  where S: ByteStream
+{
+  read_parity_groups(s).map(|group| group.data)
+}
+
+// bytes used by a frame holding `size` bytes, including its length prefix.
+fn framed_size(size: usize) -> usize {
+  zint::encode_length(size).len() + size
+}
+
+fn read_group<S>(s: ReadableByteStream<S>, parameters: ParityParameters, codec: Arc<ReedSolomon>)
+  -> impl IoFuture<( Option<ParityGroup>, future::FutureResult<ReadableByteStream<S>, io::Error> )>
+  where S: ByteStream
+{
+  let header_size = framed_size(GROUP_HEADER_SIZE);
+  s.read_at_most(header_size).and_then(move |(frame, s)| {
+    let buffer = frame.pack();
+    let length = if buffer.len() == header_size {
+      decode_group_header(&buffer[header_size - GROUP_HEADER_SIZE ..], &parameters)
+    } else {
+      None
+    };
+
+    match length {
+      Some(length) => {
+        let block_count = (length + parameters.block_size - 1) / parameters.block_size;
+        // records, then the end of the stream.
+        let size = (block_count + parameters.parity_blocks) * framed_size(parameters.record_size()) + 1;
+        future::Either::A(s.read_exact(size).and_then(move |(frame, s)| {
+          decode_group(length, frame.pack().as_ref(), &parameters, &codec).map(|group| ( Some(group), future::ok(s) ))
+        }))
+      },
+      None => {
+        // if it isn't a group, it should be the end of the bottle.
+        let end = buffer.len() == 0 || buffer[0] == zint::END_OF_BOTTLE;
+        future::Either::B(future::result(if end { Ok(( None, future::ok(s) )) } else { Err(damaged_group_error()) }))
+      }
+    }
+  })
+}
+
+// the length from the first intact copy of a group header.
+fn decode_group_header(buffer: &[u8], parameters: &ParityParameters) -> Option<usize> {
+  buffer.chunks(GROUP_HEADER_COPY_SIZE).filter(|copy| decode_u32(&copy[4 .. 8]) == crc32(&copy[0 .. 4])).map(|copy| {
+    decode_u32(&copy[0 .. 4]) as usize
+  }).find(|&length| length <= parameters.group_size())
+}
+
+// check each block of a group's (framed) records, and rebuild any that are
+// damaged. the frame lengths are skipped without looking at them.
+fn decode_group(length: usize, buffer: &[u8], parameters: &ParityParameters, codec: &ReedSolomon) -> io::Result<ParityGroup> {
+  let block_count = (length + parameters.block_size - 1) / parameters.block_size;
+  let framed_record_size = framed_size(parameters.record_size());
+  let prefix_size = framed_record_size - parameters.record_size();
+
+  let records = buffer.chunks(framed_record_size).take(block_count + parameters.parity_blocks).map(|record| {
+    let block = &record[prefix_size + CHECKSUM_SIZE ..];
+    if decode_u32(&record[prefix_size .. prefix_size + CHECKSUM_SIZE]) == crc32(block) { Some(block.to_vec()) } else { None }
+  }).collect::<Vec<Option<Vec<u8>>>>();
+  let repaired_blocks = records.iter().filter(|r| r.is_none()).count();
+
+  // blocks missing from a short (final) group are zeros.
+  let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity(parameters.data_blocks + parameters.parity_blocks);
+  shards.extend(records[0 .. block_count].iter().cloned());
+  for _ in block_count .. parameters.data_blocks { shards.push(Some(vec![ 0u8; parameters.block_size ])) };
+  shards.extend(records[block_count ..].iter().cloned());
+  codec.reconstruct(&mut shards)?;
+
+  let mut data = Vec::with_capacity(block_count * parameters.block_size);
+  for shard in shards.into_iter().take(block_count) { data.extend(shard.unwrap()) };
+  data.truncate(length);
+  Ok(ParityGroup { data: Bytes::from(data), repaired_blocks })
+}
+
+fn encode_u32(n: u32) -> [u8; 4] {
+  [ n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8 ]
+}
+
+fn decode_u32(buffer: &[u8]) -> u32 {
+  (buffer[0] as u32) | ((buffer[1] as u32) << 8) | ((buffer[2] as u32) << 16) | ((buffer[3] as u32) << 24)
+}
+
+fn bad_parameters_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "Invalid parity parameters")
+}
+
+fn damaged_group_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "Damaged parity group")
+}
+
+fn not_parity_error(bottle_type: &BottleType) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Not a parity bottle: {:?}", bottle_type))
+}
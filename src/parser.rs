@@ -0,0 +1,417 @@
+use core::{cmp, mem};
+use core::cell::Cell;
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use error::{self, Error, ErrorKind};
+use header::{Header, HEADER_PREFIX_SIZE, MAGIC};
+use reader_limits::ReaderLimits;
+use table::Table;
+use zint;
+
+/// Something found while parsing a bottle.
+#[derive(Debug)]
+pub enum ParseEvent<'a> {
+  BottleStart(Header),
+  StreamStart,
+  /// Some (not necessarily all) of the data from a frame.
+  Data(&'a [u8]),
+  StreamEnd,
+  BottleEnd
+}
+
+/// The raw pieces of a bottle, for tools (like `dump`) that account for
+/// every byte: together, the pieces cover the whole bottle.
+#[derive(Debug)]
+pub enum Piece<'a> {
+  /// A bottle's header, and its encoded bytes (prefix and table).
+  Header(&'a Header, &'a [u8]),
+  /// A frame length, or the end of a stream or bottle, and its encoded
+  /// bytes.
+  Length(zint::FrameLength, &'a [u8]),
+  /// Some of the data from a frame.
+  Data(&'a [u8])
+}
+
+/// A bottle parser that doesn't do any I/O: feed it buffers as they
+/// arrive, and it reports what it finds. Each event comes with the depth
+/// of the bottle it belongs to: 0 for the outermost bottle, 1 for a bottle
+/// nested inside one of its streams, and so on.
+///
+/// A stream can only be recognized as a nested bottle by what its bottle
+/// is, so `with_nesting` takes a function that decides, from a bottle's
+/// header, whether each of its streams holds a nested bottle. The data of
+/// those streams is parsed, instead of being reported as `Data`.
+///
+/// It's held to a set of `ReaderLimits` (the defaults, unless `with_limits`
+/// is used), like any other reader: streams and frames are counted across
+/// every level, and bytes across the frames of the outermost bottle, since
+/// nested bottles are inside them.
+pub struct BottleParser {
+  root: Level,
+  rules: Rules,
+  offset: u64
+}
+
+impl BottleParser {
+  /// A parser that treats every stream as data.
+  pub fn new() -> BottleParser {
+    BottleParser::with_nesting(|_| false)
+  }
+
+  pub fn with_nesting<F>(holds_bottles: F) -> BottleParser where F: Fn(&Header) -> bool + 'static {
+    BottleParser::build(Nesting::ByHeader(Box::new(holds_bottles)))
+  }
+
+  /// A parser that treats a stream as a nested bottle whenever it starts
+  /// with the bottle magic, for tools that don't know what each bottle
+  /// type holds. Anything after the nested bottle, in the same stream, is
+  /// reported as data.
+  pub fn with_magic_nesting() -> BottleParser {
+    BottleParser::build(Nesting::ByMagic)
+  }
+
+  fn build(nesting: Nesting) -> BottleParser {
+    let rules = Rules { nesting, limits: ReaderLimits::default(), streams: Cell::new(0), frames: Cell::new(0), bytes: Cell::new(0) };
+    BottleParser { root: Level::new(), rules, offset: 0 }
+  }
+
+  pub fn with_limits(self, limits: ReaderLimits) -> BottleParser {
+    BottleParser { rules: Rules { limits, ..self.rules }, ..self }
+  }
+
+  /// Parse as much of `data` as belongs to the bottle, calling `f` with
+  /// each event. Returns the number of bytes used, which is all of them
+  /// unless the bottle ended partway through.
+  pub fn feed<F>(&mut self, data: &[u8], f: F) -> error::Result<usize>
+    where F: FnMut(usize, ParseEvent)
+  {
+    self.push(data, &mut Events(f))
+  }
+
+  /// Parse like `feed`, but call `f` with each raw piece of the bottle
+  /// instead, along with where its bytes are, counting from the first byte
+  /// fed to this parser. A piece of a nested bottle can be split across
+  /// frames of the stream holding it, so it may be in several runs.
+  pub fn feed_pieces<F>(&mut self, data: &[u8], f: F) -> error::Result<usize>
+    where F: FnMut(usize, Piece, &[Range<u64>])
+  {
+    self.push(data, &mut Pieces(f))
+  }
+
+  /// Has the end of the bottle been reached?
+  pub fn is_done(&self) -> bool {
+    self.root.is_done()
+  }
+
+  fn push<S: Sink>(&mut self, data: &[u8], sink: &mut S) -> error::Result<usize> {
+    let used = self.root.push(0, data, self.offset, &self.rules, sink)?;
+    self.offset += used as u64;
+    Ok(used)
+  }
+}
+
+impl Default for BottleParser {
+  fn default() -> BottleParser {
+    BottleParser::new()
+  }
+}
+
+
+// where each event or piece goes: `feed` and `feed_pieces` each only want
+// one kind.
+trait Sink {
+  fn event(&mut self, depth: usize, event: ParseEvent);
+  fn piece(&mut self, depth: usize, piece: Piece, runs: &[Range<u64>]);
+}
+
+struct Events<F>(F);
+
+impl<F> Sink for Events<F> where F: FnMut(usize, ParseEvent) {
+  fn event(&mut self, depth: usize, event: ParseEvent) {
+    (self.0)(depth, event)
+  }
+
+  fn piece(&mut self, _depth: usize, _piece: Piece, _runs: &[Range<u64>]) {
+  }
+}
+
+struct Pieces<F>(F);
+
+impl<F> Sink for Pieces<F> where F: FnMut(usize, Piece, &[Range<u64>]) {
+  fn event(&mut self, _depth: usize, _event: ParseEvent) {
+  }
+
+  fn piece(&mut self, depth: usize, piece: Piece, runs: &[Range<u64>]) {
+    (self.0)(depth, piece, runs)
+  }
+}
+
+enum Nesting {
+  ByHeader(Box<Fn(&Header) -> bool>),
+  ByMagic
+}
+
+struct Rules {
+  nesting: Nesting,
+  limits: ReaderLimits,
+  // what's been read so far, at every level:
+  streams: Cell<u64>,
+  frames: Cell<u64>,
+  bytes: Cell<u64>
+}
+
+impl Rules {
+  fn add_stream(&self) -> error::Result<()> {
+    self.streams.set(self.streams.get() + 1);
+    self.limits.check_streams(self.streams.get())
+  }
+
+  // a nested bottle's frames are inside its parent's, so only the outermost
+  // bottle's frames add to the byte count.
+  fn add_frame(&self, depth: usize, length: usize) -> error::Result<()> {
+    self.frames.set(self.frames.get() + 1);
+    if depth == 0 { self.bytes.set(self.bytes.get() + length as u64) }
+    self.limits.check_frames(self.frames.get())?;
+    self.limits.check_bytes(self.bytes.get())
+  }
+}
+
+enum State {
+  // collecting the prefix, then the table:
+  Header(Vec<u8>, Vec<Range<u64>>),
+  // collecting a frame length (1 to 3 bytes):
+  Length(Vec<u8>, Vec<Range<u64>>),
+  // this much data is left in the current frame:
+  Frame(usize),
+  Done
+}
+
+// what the current stream holds.
+enum Contents {
+  Data,
+  // (nesting by magic) the start of the stream, until it's clear whether
+  // it's a bottle:
+  Sniffing(Vec<u8>, Vec<Range<u64>>),
+  Bottle(Box<Level>)
+}
+
+// one bottle, and the bottle nested inside its current stream (if any).
+struct Level {
+  state: State,
+  holds_bottles: bool,
+  in_stream: bool,
+  contents: Contents
+}
+
+impl Level {
+  fn new() -> Level {
+    Level {
+      state: State::Header(Vec::new(), Vec::new()),
+      holds_bottles: false,
+      in_stream: false,
+      contents: Contents::Data
+    }
+  }
+
+  fn is_done(&self) -> bool {
+    match self.state {
+      State::Done => true,
+      _ => false
+    }
+  }
+
+  // `offset` is where `data` starts, counting from the first byte fed to
+  // the parser.
+  fn push<S: Sink>(&mut self, depth: usize, data: &[u8], offset: u64, rules: &Rules, sink: &mut S) -> error::Result<usize> {
+    let mut i = 0;
+    while i < data.len() {
+      let at = offset + i as u64;
+      self.state = match mem::replace(&mut self.state, State::Done) {
+        State::Header(mut buffer, mut runs) => {
+          let mut wanted = HEADER_PREFIX_SIZE;
+          if buffer.len() >= HEADER_PREFIX_SIZE {
+            wanted += Header::decode_prefix(&buffer[0 .. HEADER_PREFIX_SIZE])?.2;
+          }
+          let n = cmp::min(wanted - buffer.len(), data.len() - i);
+          buffer.extend_from_slice(&data[i .. i + n]);
+          add_run(&mut runs, at, n);
+          i += n;
+          if buffer.len() < HEADER_PREFIX_SIZE { State::Header(buffer, runs) } else {
+            let ( version, bottle_type, table_length ) = Header::decode_prefix(&buffer[0 .. HEADER_PREFIX_SIZE])?;
+            rules.limits.check_table_size(table_length)?;
+            if buffer.len() < HEADER_PREFIX_SIZE + table_length { State::Header(buffer, runs) } else {
+              let table = Table::decode_slice(&buffer[HEADER_PREFIX_SIZE ..])?;
+              let header = Header { version, ..Header::new(bottle_type, table) };
+              self.holds_bottles = match rules.nesting {
+                Nesting::ByHeader(ref holds_bottles) => holds_bottles(&header),
+                Nesting::ByMagic => false
+              };
+              sink.piece(depth, Piece::Header(&header, &buffer), &runs);
+              sink.event(depth, ParseEvent::BottleStart(header));
+              State::Length(Vec::new(), Vec::new())
+            }
+          }
+        },
+
+        State::Length(mut buffer, mut runs) => {
+          buffer.push(data[i]);
+          add_run(&mut runs, at, 1);
+          i += 1;
+          let ( count, accumulator ) = zint::decode_first_length_byte(buffer[0]);
+          if buffer.len() < count + 1 { State::Length(buffer, runs) } else {
+            let length = zint::decode_length(accumulator, &buffer[1 ..]);
+            if length == zint::FrameLength::EndOfStream { self.flush_sniffed(depth, sink) }
+            sink.piece(depth, Piece::Length(length.clone(), &buffer), &runs);
+            match length {
+              zint::FrameLength::EndOfBottle => {
+                if self.in_stream { return Err(parse_error("End of bottle inside a stream")) }
+                sink.event(depth, ParseEvent::BottleEnd);
+                return Ok(i);
+              },
+              zint::FrameLength::EndOfStream => {
+                if !self.in_stream { self.start_stream(depth, rules, sink)? }
+                self.end_stream(depth, sink)?;
+                State::Length(Vec::new(), Vec::new())
+              },
+              zint::FrameLength::Length(n) => {
+                if !self.in_stream { self.start_stream(depth, rules, sink)? }
+                rules.add_frame(depth, n)?;
+                State::Frame(n)
+              }
+            }
+          }
+        },
+
+        State::Frame(remaining) => {
+          let n = cmp::min(remaining, data.len() - i);
+          self.push_contents(depth, &data[i .. i + n], at, rules, sink)?;
+          i += n;
+          if remaining > n { State::Frame(remaining - n) } else { State::Length(Vec::new(), Vec::new()) }
+        },
+
+        State::Done => return Ok(i)
+      };
+    }
+    Ok(i)
+  }
+
+  fn start_stream<S: Sink>(&mut self, depth: usize, rules: &Rules, sink: &mut S) -> error::Result<()> {
+    rules.add_stream()?;
+    self.in_stream = true;
+    sink.event(depth, ParseEvent::StreamStart);
+    self.contents = match rules.nesting {
+      Nesting::ByHeader(_) if self.holds_bottles => Contents::Bottle(nested_level(depth, rules)?),
+      Nesting::ByMagic => Contents::Sniffing(Vec::new(), Vec::new()),
+      _ => Contents::Data
+    };
+    Ok(())
+  }
+
+  fn end_stream<S: Sink>(&mut self, depth: usize, sink: &mut S) -> error::Result<()> {
+    if let Contents::Bottle(ref nested) = self.contents {
+      if !nested.is_done() { return Err(parse_error("Stream ended in the middle of a nested bottle")) }
+    }
+    self.contents = Contents::Data;
+    self.in_stream = false;
+    sink.event(depth, ParseEvent::StreamEnd);
+    Ok(())
+  }
+
+  // data from a frame of the current stream.
+  fn push_contents<S: Sink>(&mut self, depth: usize, data: &[u8], offset: u64, rules: &Rules, sink: &mut S) -> error::Result<()> {
+    let mut data = data;
+    let mut offset = offset;
+    let mut is_bottle = None;
+    if let Contents::Sniffing(ref mut buffer, ref mut runs) = self.contents {
+      let n = cmp::min(MAGIC.len() - buffer.len(), data.len());
+      buffer.extend_from_slice(&data[0 .. n]);
+      add_run(runs, offset, n);
+      data = &data[n ..];
+      offset += n as u64;
+      if buffer[..] != MAGIC[0 .. buffer.len()] {
+        is_bottle = Some(false);
+      } else if buffer.len() == MAGIC.len() {
+        is_bottle = Some(true);
+      }
+    }
+
+    match is_bottle {
+      Some(false) => self.flush_sniffed(depth, sink),
+      Some(true) => {
+        if let Contents::Sniffing(buffer, runs) = mem::replace(&mut self.contents, Contents::Data) {
+          let mut nested = nested_level(depth, rules)?;
+          for ( run, bytes ) in split_runs(&buffer, &runs) {
+            nested.push(depth + 1, bytes, run.start, rules, sink)?;
+          }
+          self.contents = Contents::Bottle(nested);
+        }
+      },
+      None => ()
+    }
+    if data.is_empty() { return Ok(()) }
+
+    let used = match self.contents {
+      Contents::Bottle(ref mut nested) => nested.push(depth + 1, data, offset, rules, sink)?,
+      _ => 0
+    };
+    if used < data.len() {
+      if let Contents::Bottle(_) = self.contents {
+        if let Nesting::ByHeader(_) = rules.nesting { return Err(parse_error("Data after the end of a nested bottle")) }
+      }
+      report_data(depth, &data[used ..], offset + used as u64, sink);
+    }
+    Ok(())
+  }
+
+  // a stream being sniffed for magic turned out to be data.
+  fn flush_sniffed<S: Sink>(&mut self, depth: usize, sink: &mut S) {
+    let sniffed = match self.contents {
+      Contents::Sniffing(..) => mem::replace(&mut self.contents, Contents::Data),
+      _ => return
+    };
+    if let Contents::Sniffing(buffer, runs) = sniffed {
+      for ( run, bytes ) in split_runs(&buffer, &runs) { report_data(depth, bytes, run.start, sink) };
+    }
+  }
+}
+
+fn nested_level(depth: usize, rules: &Rules) -> error::Result<Box<Level>> {
+  rules.limits.check_depth(depth + 1)?;
+  Ok(Box::new(Level::new()))
+}
+
+fn report_data<S: Sink>(depth: usize, data: &[u8], offset: u64, sink: &mut S) {
+  let run = offset .. offset + data.len() as u64;
+  sink.piece(depth, Piece::Data(data), &[ run ]);
+  sink.event(depth, ParseEvent::Data(data));
+}
+
+fn add_run(runs: &mut Vec<Range<u64>>, offset: u64, count: usize) {
+  if count == 0 { return }
+  let end = offset + count as u64;
+  if let Some(last) = runs.last_mut() {
+    if last.end == offset {
+      last.end = end;
+      return;
+    }
+  }
+  runs.push(offset .. end);
+}
+
+// pair each run with the bytes (collected in `buffer`) that came from it.
+fn split_runs<'a>(buffer: &'a [u8], runs: &'a [Range<u64>]) -> Vec<( &'a Range<u64>, &'a [u8] )> {
+  let mut start = 0;
+  runs.iter().map(|run| {
+    let n = (run.end - run.start) as usize;
+    start += n;
+    ( run, &buffer[start - n .. start] )
+  }).collect()
+}
+
+fn parse_error(message: &str) -> Error {
+  Error::new(ErrorKind::InvalidData, message)
+}
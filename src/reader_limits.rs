@@ -0,0 +1,223 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error as StdError;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+use error::{self, Error, ErrorKind};
+#[cfg(feature = "std")]
+use bottle_kind::Registry;
+#[cfg(feature = "std")]
+use observer::{BottleObserver, SharedObserver};
+
+/// Ceilings on what a reader will accept, to protect against hostile or
+/// broken bottles. Streams, frames, and bytes are counted across a bottle
+/// and every bottle nested inside it. Plain reads use the defaults; a
+/// reader with no limits has to be asked for by name (`Limiter::unlimited`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReaderLimits {
+  /// How deep bottles may be nested (the outermost bottle is depth 0).
+  pub max_depth: usize,
+  /// Largest header table, in bytes.
+  pub max_table_size: usize,
+  pub max_streams: u64,
+  pub max_frames: u64,
+  /// Total data in all frames. A nested bottle's frames are already inside
+  /// its parent's frames, so they aren't counted again, unless the nested
+  /// bottle is inside decoded (decrypted or decompressed) data.
+  pub max_bytes: u64
+}
+
+impl Default for ReaderLimits {
+  fn default() -> ReaderLimits {
+    ReaderLimits {
+      max_depth: 32,
+      max_table_size: 4095,
+      max_streams: 1 << 20,
+      max_frames: 1 << 26,
+      max_bytes: 1 << 36
+    }
+  }
+}
+
+impl ReaderLimits {
+  /// No limits at all.
+  pub fn unlimited() -> ReaderLimits {
+    ReaderLimits {
+      max_depth: usize::max_value(),
+      max_table_size: usize::max_value(),
+      max_streams: u64::max_value(),
+      max_frames: u64::max_value(),
+      max_bytes: u64::max_value()
+    }
+  }
+
+  pub fn check_depth(&self, depth: usize) -> error::Result<()> {
+    check("nesting depth", depth as u64, self.max_depth as u64)
+  }
+
+  pub fn check_table_size(&self, size: usize) -> error::Result<()> {
+    check("table size", size as u64, self.max_table_size as u64)
+  }
+
+  pub fn check_streams(&self, count: u64) -> error::Result<()> {
+    check("stream count", count, self.max_streams)
+  }
+
+  pub fn check_frames(&self, count: u64) -> error::Result<()> {
+    check("frame count", count, self.max_frames)
+  }
+
+  pub fn check_bytes(&self, count: u64) -> error::Result<()> {
+    check("total bytes", count, self.max_bytes)
+  }
+}
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Usage {
+  streams: u64,
+  frames: u64,
+  bytes: u64
+}
+
+/// Tracks usage against a set of `ReaderLimits`, shared by a bottle and all
+/// the bottles nested inside it. It also carries the `BottleObserver` (if
+/// any) that's watching them, and the `Registry` (if any) of application
+/// bottle types to check them against.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct Limiter {
+  limits: Arc<ReaderLimits>,
+  depth: usize,
+  // frames of a nested bottle were already counted by the outer bottle.
+  counts_bytes: bool,
+  usage: Arc<Mutex<Usage>>,
+  observer: Option<SharedObserver>,
+  registry: Option<Arc<Registry>>
+}
+
+#[cfg(feature = "std")]
+impl Limiter {
+  pub fn new(limits: ReaderLimits) -> Limiter {
+    Limiter {
+      limits: Arc::new(limits),
+      depth: 0,
+      counts_bytes: true,
+      usage: Arc::new(Mutex::new(Usage::default())),
+      observer: None,
+      registry: None
+    }
+  }
+
+  /// A limiter that never fails, for plain reads.
+  pub fn unlimited() -> Limiter {
+    Limiter::new(ReaderLimits::unlimited())
+  }
+
+  /// The limiter to use for a bottle nested one level deeper, inside the
+  /// frames of this one.
+  pub fn nested(&self) -> Limiter {
+    Limiter { depth: self.depth + 1, counts_bytes: false, ..self.clone() }
+  }
+
+  /// The limiter to use for a nested bottle whose data was decoded
+  /// (decrypted or decompressed) from its parent's, so its bytes are new.
+  pub fn decoded(self) -> Limiter {
+    Limiter { counts_bytes: true, ..self }
+  }
+
+  /// Report events for this bottle and its nested bottles to an observer.
+  pub fn with_observer(self, observer: SharedObserver) -> Limiter {
+    Limiter { observer: Some(observer), ..self }
+  }
+
+  /// Check headers of application bottle types against a registry.
+  pub fn with_registry(self, registry: Arc<Registry>) -> Limiter {
+    Limiter { registry: Some(registry), ..self }
+  }
+
+  pub fn registry(&self) -> Option<&Registry> {
+    self.registry.as_ref().map(|registry| &**registry)
+  }
+
+  /// Call `f` with the observer, if there is one.
+  pub fn notify<F>(&self, f: F) where F: FnOnce(&BottleObserver) {
+    if let Some(ref observer) = self.observer { f(&**observer) }
+  }
+
+  pub fn depth(&self) -> usize {
+    self.depth
+  }
+
+  pub fn check_depth(&self) -> error::Result<()> {
+    self.limits.check_depth(self.depth)
+  }
+
+  pub fn check_table_size(&self, size: usize) -> error::Result<()> {
+    self.limits.check_table_size(size)
+  }
+
+  pub fn add_stream(&self) -> error::Result<()> {
+    let mut usage = self.usage.lock().unwrap();
+    usage.streams += 1;
+    self.limits.check_streams(usage.streams)
+  }
+
+  pub fn add_frame(&self, length: usize) -> error::Result<()> {
+    let mut usage = self.usage.lock().unwrap();
+    usage.frames += 1;
+    if self.counts_bytes { usage.bytes += length as u64 }
+    self.limits.check_frames(usage.frames)?;
+    self.limits.check_bytes(usage.bytes)
+  }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for Limiter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Limiter(depth={}, {:?})", self.depth, self.limits)
+  }
+}
+
+/// The error inside an `Error` when a bottle goes over one of its
+/// `ReaderLimits`.
+#[derive(Debug)]
+pub struct LimitExceeded {
+  pub limit: &'static str,
+  pub maximum: u64
+}
+
+impl fmt::Display for LimitExceeded {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Reader limit exceeded: {} (maximum {})", self.limit, self.maximum)
+  }
+}
+
+#[cfg(feature = "std")]
+impl StdError for LimitExceeded {
+  fn description(&self) -> &str {
+    "reader limit exceeded"
+  }
+}
+
+/// Was this error caused by a bottle going over its `ReaderLimits`?
+#[cfg(feature = "std")]
+pub fn is_limit_exceeded(e: &Error) -> bool {
+  e.get_ref().map(|inner| inner.is::<LimitExceeded>()).unwrap_or(false)
+}
+
+fn check(limit: &'static str, value: u64, maximum: u64) -> error::Result<()> {
+  if value > maximum { Err(limit_exceeded_error(limit, maximum)) } else { Ok(()) }
+}
+
+#[cfg(feature = "std")]
+fn limit_exceeded_error(limit: &'static str, maximum: u64) -> Error {
+  Error::new(ErrorKind::InvalidData, LimitExceeded { limit, maximum })
+}
+
+// without `std`, errors only carry a message.
+#[cfg(not(feature = "std"))]
+fn limit_exceeded_error(limit: &'static str, maximum: u64) -> Error {
+  Error::new(ErrorKind::InvalidData, format!("{}", LimitExceeded { limit, maximum }))
+}
@@ -0,0 +1,168 @@
+use std::io;
+
+// GF(2^8) with the usual polynomial x^8 + x^4 + x^3 + x^2 + 1.
+const POLYNOMIAL: usize = 0x11d;
+
+lazy_static! {
+  static ref EXP: [u8; 512] = {
+    let mut table = [ 0u8; 512 ];
+    let mut n: usize = 1;
+    for i in 0 .. 255 {
+      table[i] = n as u8;
+      table[i + 255] = n as u8;
+      n <<= 1;
+      if n >= 256 { n ^= POLYNOMIAL; }
+    }
+    table
+  };
+
+  static ref LOG: [u8; 256] = {
+    let mut table = [ 0u8; 256 ];
+    for i in 0 .. 255 { table[EXP[i] as usize] = i as u8 };
+    table
+  };
+}
+
+fn multiply(a: u8, b: u8) -> u8 {
+  if a == 0 || b == 0 { return 0 }
+  EXP[LOG[a as usize] as usize + LOG[b as usize] as usize]
+}
+
+fn inverse(a: u8) -> u8 {
+  assert!(a != 0);
+  EXP[255 - LOG[a as usize] as usize]
+}
+
+/// Reed-Solomon erasure code over GF(2^8): `data_shards` shards of data
+/// are protected by `parity_shards` shards of parity, and any
+/// `parity_shards` of them can be lost and rebuilt, as long as we know
+/// which ones are missing.
+///
+/// The code is systematic (data shards are stored as-is), with parity rows
+/// from a Cauchy matrix, so every combination of surviving shards can be
+/// solved.
+pub struct ReedSolomon {
+  data_shards: usize,
+  parity_shards: usize,
+  // one row per parity shard, one column per data shard:
+  parity_matrix: Vec<Vec<u8>>
+}
+
+impl ReedSolomon {
+  pub fn new(data_shards: usize, parity_shards: usize) -> ReedSolomon {
+    assert!(data_shards > 0 && parity_shards > 0 && data_shards + parity_shards <= 256);
+    let parity_matrix = (0 .. parity_shards).map(|i| {
+      (0 .. data_shards).map(|j| inverse(((data_shards + i) ^ j) as u8)).collect()
+    }).collect();
+    ReedSolomon { data_shards, parity_shards, parity_matrix }
+  }
+
+  pub fn data_shards(&self) -> usize {
+    self.data_shards
+  }
+
+  pub fn parity_shards(&self) -> usize {
+    self.parity_shards
+  }
+
+  /// Compute the parity shards for a set of equal-length data shards.
+  pub fn encode(&self, data: &[&[u8]]) -> Vec<Vec<u8>> {
+    assert_eq!(data.len(), self.data_shards);
+    let size = data[0].len();
+    assert!(data.iter().all(|shard| shard.len() == size));
+    self.parity_matrix.iter().map(|row| combine(row, data, size)).collect()
+  }
+
+  /// Rebuild any missing (`None`) shards, data or parity, in place. This
+  /// fails if more than `parity_shards` are missing.
+  pub fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> io::Result<()> {
+    assert_eq!(shards.len(), self.data_shards + self.parity_shards);
+    let present = (0 .. shards.len()).filter(|&i| shards[i].is_some()).collect::<Vec<usize>>();
+    if present.len() == shards.len() { return Ok(()) }
+    if present.len() < self.data_shards { return Err(too_damaged_error(shards.len() - present.len())) }
+
+    // each shard is a row of the encoding matrix (identity on top, parity
+    // below) times the data. take the rows of the first surviving shards,
+    // invert, and multiply to get the data back.
+    let rows = &present[0 .. self.data_shards];
+    let matrix = rows.iter().map(|&r| self.encoding_row(r)).collect::<Vec<Vec<u8>>>();
+    let decode = invert(matrix)?;
+    let size = shards[present[0]].as_ref().unwrap().len();
+
+    let data = {
+      let inputs = rows.iter().map(|&r| shards[r].as_ref().unwrap().as_ref()).collect::<Vec<&[u8]>>();
+      (0 .. self.data_shards).map(|i| {
+        if shards[i].is_some() { None } else { Some(combine(&decode[i], &inputs, size)) }
+      }).collect::<Vec<Option<Vec<u8>>>>()
+    };
+    for (i, shard) in data.into_iter().enumerate() {
+      if shard.is_some() { shards[i] = shard; }
+    }
+
+    if shards[self.data_shards ..].iter().any(|s| s.is_none()) {
+      let parity = {
+        let inputs = shards[0 .. self.data_shards].iter().map(|s| s.as_ref().unwrap().as_ref()).collect::<Vec<&[u8]>>();
+        self.encode(&inputs)
+      };
+      for (i, shard) in parity.into_iter().enumerate() {
+        if shards[self.data_shards + i].is_none() { shards[self.data_shards + i] = Some(shard); }
+      }
+    }
+    Ok(())
+  }
+
+  fn encoding_row(&self, row: usize) -> Vec<u8> {
+    if row < self.data_shards {
+      (0 .. self.data_shards).map(|j| if j == row { 1 } else { 0 }).collect()
+    } else {
+      self.parity_matrix[row - self.data_shards].clone()
+    }
+  }
+}
+
+// multiply one matrix row by a set of shards.
+fn combine(row: &[u8], shards: &[&[u8]], size: usize) -> Vec<u8> {
+  let mut out = vec![ 0u8; size ];
+  for (&coefficient, shard) in row.iter().zip(shards.iter()) {
+    if coefficient == 0 { continue }
+    for (o, &b) in out.iter_mut().zip(shard.iter()) { *o ^= multiply(coefficient, b) };
+  }
+  out
+}
+
+// gauss-jordan elimination. the matrix should always be invertible,
+// because of how the parity rows are built, but a bad one is an error
+// rather than a panic.
+fn invert(mut matrix: Vec<Vec<u8>>) -> io::Result<Vec<Vec<u8>>> {
+  let n = matrix.len();
+  let mut result = (0 .. n).map(|i| (0 .. n).map(|j| if i == j { 1 } else { 0 }).collect()).collect::<Vec<Vec<u8>>>();
+  for column in 0 .. n {
+    let pivot = (column .. n).find(|&r| matrix[r][column] != 0).ok_or_else(singular_matrix_error)?;
+    matrix.swap(column, pivot);
+    result.swap(column, pivot);
+    let scale = inverse(matrix[column][column]);
+    for j in 0 .. n {
+      matrix[column][j] = multiply(matrix[column][j], scale);
+      result[column][j] = multiply(result[column][j], scale);
+    }
+    for r in 0 .. n {
+      let factor = matrix[r][column];
+      if r == column || factor == 0 { continue }
+      for j in 0 .. n {
+        let m = multiply(factor, matrix[column][j]);
+        let x = multiply(factor, result[column][j]);
+        matrix[r][j] ^= m;
+        result[r][j] ^= x;
+      }
+    }
+  }
+  Ok(result)
+}
+
+fn singular_matrix_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "Parity blocks can't be inverted")
+}
+
+fn too_damaged_error(missing: usize) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, format!("Too many damaged blocks to repair: {}", missing))
+}
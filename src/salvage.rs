@@ -0,0 +1,310 @@
+use bytes::Bytes;
+use futures::Future;
+use sha2::{Digest, Sha256};
+use std::{cmp, io};
+use std::ops::Range;
+
+use file_bottle::{FileMetadata, is_safe_filename};
+use header::{BottleType, Header, MAGIC};
+use parser::{BottleParser, Piece};
+use reader_limits::ReaderLimits;
+use stream_toolkit::{ByteStream, ToHex};
+use zint;
+
+/// A file or folder recovered intact from a damaged archive.
+#[derive(Debug)]
+pub struct SalvagedEntry {
+  /// Path from the top of the bottle it was found in, with each level
+  /// separated by `/`. A bottle found by scanning past damage has lost the
+  /// folders around it, so its path starts with its own filename.
+  pub path: String,
+  pub metadata: FileMetadata,
+  /// The contents of a file (`None` for folders).
+  pub data: Option<Bytes>,
+  /// Where this entry's bottle starts in the archive.
+  pub offset: u64
+}
+
+/// Everything that could be recovered from an archive, and the byte ranges
+/// that had to be skipped to get it.
+#[derive(Debug)]
+pub struct SalvageReport {
+  pub entries: Vec<SalvagedEntry>,
+  pub skipped: Vec<Range<u64>>
+}
+
+/// Recover what we can from a damaged or truncated archive. It's read like
+/// `read_file_entries`, but when the framing is damaged, we scan forward for
+/// the magic of the next (possibly nested) bottle and resume there. Files
+/// are only returned if their bottle was read completely, and their SHA-256
+/// matches, if they have one.
+pub fn salvage(buffer: &[u8]) -> SalvageReport {
+  let mut salvager = Salvager::new();
+  salvager.feed(buffer);
+  salvager.finish()
+}
+
+/// Salvage a (damaged) archive as it arrives.
+pub fn salvage_stream<S>(s: S) -> impl Future<Item = SalvageReport, Error = io::Error>
+  where S: ByteStream
+{
+  s.fold(Salvager::new(), |mut salvager, data| {
+    salvager.feed(&data);
+    Ok::<Salvager, io::Error>(salvager)
+  }).map(|salvager| salvager.finish())
+}
+
+/// Salvages an archive a buffer at a time: call `feed` as data arrives,
+/// then `finish`. The only data it holds on to is what it may have to scan
+/// again if it finds damage, which is at most the bottle it's reading.
+/// Nesting is limited by `ReaderLimits`, like any other reader.
+pub struct Salvager {
+  limits: ReaderLimits,
+  // data from offset `base` on:
+  pending: Vec<u8>,
+  base: u64,
+  // where to look for the next bottle, when not reading one:
+  scan: u64,
+  attempt: Option<Attempt>,
+  found: Found,
+  skipped: Vec<Range<u64>>,
+  skip_start: Option<u64>
+}
+
+impl Salvager {
+  pub fn new() -> Salvager {
+    Salvager {
+      limits: ReaderLimits::default(),
+      pending: Vec::new(),
+      base: 0,
+      scan: 0,
+      attempt: None,
+      found: Found { entries: Vec::new(), open: Vec::new(), high_water: 0, damaged: None },
+      skipped: Vec::new(),
+      skip_start: None
+    }
+  }
+
+  pub fn with_limits(self, limits: ReaderLimits) -> Salvager {
+    Salvager { limits, ..self }
+  }
+
+  pub fn feed(&mut self, data: &[u8]) {
+    self.pending.extend_from_slice(data);
+    self.run(false);
+  }
+
+  /// How many bytes are held, in case they have to be scanned again.
+  pub fn buffered(&self) -> usize {
+    self.pending.len()
+  }
+
+  pub fn finish(mut self) -> SalvageReport {
+    self.run(true);
+    let end = self.base + self.pending.len() as u64;
+    if self.scan < end && self.skip_start.is_none() { self.skip_start = Some(self.scan) }
+    if let Some(s) = self.skip_start {
+      if s < end { self.skipped.push(s .. end) }
+    }
+    SalvageReport { entries: self.found.entries, skipped: self.skipped }
+  }
+
+  // read as far as the data goes (or, at `eof`, to the end).
+  fn run(&mut self, eof: bool) {
+    loop {
+      if self.attempt.is_none() {
+        match find_magic(&self.pending, (self.scan - self.base) as usize) {
+          Some(i) => {
+            let start = self.base + i as u64;
+            if start > self.scan && self.skip_start.is_none() { self.skip_start = Some(self.scan) }
+            self.found.open.clear();
+            self.found.damaged = None;
+            let parser = BottleParser::with_nesting(holds_entries).with_limits(self.limits.clone());
+            self.attempt = Some(Attempt { parser, start, fed: start, entry_count: self.found.entries.len() });
+          },
+          None => {
+            // the last few bytes could be the start of the next magic.
+            let end = self.base + self.pending.len() as u64;
+            let keep = cmp::max(self.scan, end.saturating_sub(MAGIC.len() as u64 - 1));
+            if keep > self.scan && self.skip_start.is_none() { self.skip_start = Some(self.scan) }
+            self.scan = keep;
+            self.discard(keep);
+            return;
+          }
+        }
+      }
+
+      let result = {
+        let attempt = self.attempt.as_mut().unwrap();
+        let found = &mut self.found;
+        let data = &self.pending[(attempt.fed - self.base) as usize ..];
+        let start = attempt.start;
+        attempt.parser.feed_pieces(data, |depth, piece, runs| found.piece(start, depth, piece, runs)).map(|used| {
+          attempt.fed += used as u64;
+          attempt.parser.is_done()
+        })
+      };
+      let ( start, fed, entry_count ) = {
+        let attempt = self.attempt.as_ref().unwrap();
+        ( attempt.start, attempt.fed, attempt.entry_count )
+      };
+
+      let damaged = match result {
+        _ if self.found.damaged.is_some() => self.found.damaged,
+        Ok(true) => None,
+        // the parser wants more. if it fails later, we never look before
+        // where we'd resume now, so the rest can go.
+        Ok(false) if !eof => {
+          let keep = self.found.resume(start);
+          self.discard(keep);
+          return;
+        },
+        _ => Some(self.found.open.last().map(|b| b.start).unwrap_or(start))
+      };
+
+      if damaged.is_none() || self.found.entries.len() > entry_count {
+        if let Some(s) = self.skip_start.take() {
+          if s < start { self.skipped.push(s .. start) }
+        }
+      }
+      self.attempt = None;
+      match damaged {
+        None => self.scan = fed,
+        Some(damaged) => {
+          // resume after the last thing we read successfully.
+          let resume = cmp::max(damaged, self.found.high_water);
+          if self.skip_start.is_none() { self.skip_start = Some(resume) }
+          self.scan = if resume > start { resume } else { start + 1 };
+        }
+      }
+    }
+  }
+
+  // forget the data before `offset`.
+  fn discard(&mut self, offset: u64) {
+    if offset > self.base {
+      self.pending.drain(0 .. (offset - self.base) as usize);
+      self.base = offset;
+    }
+  }
+}
+
+impl Default for Salvager {
+  fn default() -> Salvager {
+    Salvager::new()
+  }
+}
+
+fn find_magic(buffer: &[u8], from: usize) -> Option<usize> {
+  if from >= buffer.len() { return None }
+  buffer[from ..].windows(MAGIC.len()).position(|w| w == &MAGIC[..]).map(|i| i + from)
+}
+
+// only folders hold nested bottles that we can salvage.
+fn holds_entries(header: &Header) -> bool {
+  header.bottle_type == BottleType::File &&
+    FileMetadata::from_table(&header.table).map(|metadata| metadata.is_folder).unwrap_or(false)
+}
+
+
+// reading one bottle (and the bottles nested in it), from `start`.
+struct Attempt {
+  parser: BottleParser,
+  start: u64,
+  fed: u64,
+  entry_count: usize
+}
+
+enum Kind {
+  // not something we can salvage from, but read past it.
+  Other,
+  Folder,
+  File { metadata: FileMetadata, data: Vec<u8>, streams: usize }
+}
+
+struct OpenBottle {
+  start: u64,
+  path: String,
+  kind: Kind
+}
+
+// what's been found, from the pieces reported by the parser.
+struct Found {
+  entries: Vec<SalvagedEntry>,
+  // the bottle at each depth:
+  open: Vec<OpenBottle>,
+  // the end of the last bottle (or folder header) read successfully:
+  high_water: u64,
+  // the start of the innermost bottle that was damaged:
+  damaged: Option<u64>
+}
+
+impl Found {
+  // where we'd resume if the attempt that started at `start` failed now.
+  fn resume(&self, start: u64) -> u64 {
+    cmp::max(self.open.last().map(|b| b.start).unwrap_or(start), self.high_water)
+  }
+
+  // the parser counts from `base`, where it started.
+  fn piece(&mut self, base: u64, depth: usize, piece: Piece, runs: &[Range<u64>]) {
+    if self.damaged.is_some() { return }
+    let ( start, end ) = ( base + runs[0].start, base + runs[runs.len() - 1].end );
+    match piece {
+      Piece::Header(header, _) => {
+        if header.bottle_type != BottleType::File {
+          self.open.push(OpenBottle { start, path: String::new(), kind: Kind::Other });
+          return;
+        }
+        let metadata = match FileMetadata::from_table(&header.table) {
+          Ok(ref metadata) if is_safe_filename(&metadata.filename) => metadata.clone(),
+          _ => {
+            self.damaged = Some(start);
+            return;
+          }
+        };
+        let path = if depth == 0 {
+          metadata.filename.clone()
+        } else {
+          format!("{}/{}", self.open[depth - 1].path, metadata.filename)
+        };
+        if metadata.is_folder {
+          self.entries.push(SalvagedEntry { path: path.clone(), metadata, data: None, offset: start });
+          self.high_water = end;
+          self.open.push(OpenBottle { start, path, kind: Kind::Folder });
+        } else {
+          self.open.push(OpenBottle { start, path, kind: Kind::File { metadata, data: Vec::new(), streams: 0 } });
+        }
+      },
+
+      Piece::Data(bytes) => {
+        // only the first stream of a file is its contents.
+        if let Kind::File { ref mut data, streams: 0, .. } = self.open[depth].kind { data.extend_from_slice(bytes) }
+      },
+
+      Piece::Length(zint::FrameLength::EndOfStream, _) => {
+        if let Kind::File { ref mut streams, .. } = self.open[depth].kind { *streams += 1 }
+      },
+
+      Piece::Length(zint::FrameLength::EndOfBottle, _) => {
+        let bottle = match self.open.pop() {
+          Some(bottle) => bottle,
+          None => return
+        };
+        if let Kind::File { metadata, data, .. } = bottle.kind {
+          if let Some(ref sha256) = metadata.sha256 {
+            let mut hasher = Sha256::default();
+            hasher.input(&data);
+            if hasher.result().as_slice().to_hex() != *sha256 {
+              self.damaged = Some(bottle.start);
+              return;
+            }
+          }
+          self.entries.push(SalvagedEntry { path: bottle.path, metadata, data: Some(Bytes::from(data)), offset: bottle.start });
+        }
+        self.high_water = end;
+      },
+
+      Piece::Length(..) => ()
+    }
+  }
+}
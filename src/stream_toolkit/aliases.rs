@@ -16,3 +16,10 @@ impl<S: ByteStream, T: Stream<Item = S, Error = io::Error>> ByteStreamStream<S>
 /// Alias for `Future<A>` with an error type of `io::Error`
 pub trait IoFuture<A>: Future<Item = A, Error = io::Error> {}
 impl<A, T: Future<Item = A, Error = io::Error>> IoFuture<A> for T {}
+
+/// Boxed `ByteStream`, for when the type can't be named, like in recursive
+/// code walking nested bottles.
+pub type BoxedByteStream = Box<Stream<Item = Bytes, Error = io::Error>>;
+
+/// Boxed `IoFuture`, for the same reasons.
+pub type BoxedIoFuture<A> = Box<Future<Item = A, Error = io::Error>>;
@@ -2,7 +2,7 @@ use bytes::Bytes;
 use std::io;
 use futures::{Async, Future, Poll, Stream};
 
-use super::{ByteFrame, ReadableByteStream, ReadableByteStreamFuture, ReadMode};
+use super::{ByteFrame, MemoryLimit, ReadableByteStream, ReadableByteStreamFuture, ReadMode};
 
 /// `Stream<Bytes>` that buffers data until it reaches a desired block size,
 /// then emits a single `ByteFrame` (a vector of `Bytes`). If `exact` is set,
@@ -20,10 +20,20 @@ impl<S> BufferedByteStream<S>
   where S: Stream<Item = Bytes, Error = io::Error>
 {
   pub fn new(s: S, block_size: usize, exact: bool) -> BufferedByteStream<S> {
+    BufferedByteStream::buffer(ReadableByteStream::from(s), block_size, exact)
+  }
+
+  /// Buffer with a cap on memory: blocks are never bigger than the limit's
+  /// `max_bytes`, even when `exact` isn't set.
+  pub fn with_memory_limit(s: S, block_size: usize, exact: bool, limit: MemoryLimit) -> BufferedByteStream<S> {
+    BufferedByteStream::buffer(ReadableByteStream::from(s).with_memory_limit(limit), block_size, exact)
+  }
+
+  fn buffer(s: ReadableByteStream<S>, block_size: usize, exact: bool) -> BufferedByteStream<S> {
     assert!(block_size > 0);
     let mode = if exact { ReadMode::AtMost } else { ReadMode::Lazy };
     BufferedByteStream {
-      future: Some(ReadableByteStream::from(s).read(block_size, mode)),
+      future: Some(s.read(block_size, mode)),
       block_size: block_size,
       mode: mode
     }
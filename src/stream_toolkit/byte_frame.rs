@@ -1,8 +1,10 @@
 use bytes::{Bytes};
 use futures::{Stream, stream};
+use std::mem;
 
 /// A "frame" of bytes, consisting of a vector of `Bytes` objects and a
 /// pre-calculated count of the total size.
+#[derive(Clone)]
 pub struct ByteFrame {
   pub vec: Vec<Bytes>,
   pub length: usize
@@ -24,6 +26,30 @@ impl ByteFrame {
     Bytes::from(rv)
   }
 
+  /// Split into frames of at most `max_length` bytes, slicing any `Bytes`
+  /// that crosses a boundary (without copying).
+  pub fn split(self, max_length: usize) -> Vec<ByteFrame> {
+    assert!(max_length > 0);
+    if self.length <= max_length { return vec![ self ] }
+    let mut frames = Vec::with_capacity(self.length / max_length + 1);
+    let mut current: Vec<Bytes> = Vec::new();
+    let mut current_length = 0;
+    for mut b in self.vec {
+      while current_length + b.len() > max_length {
+        let n = max_length - current_length;
+        if n > 0 { current.push(b.split_to(n)) };
+        frames.push(ByteFrame::new(mem::replace(&mut current, Vec::new()), max_length));
+        current_length = 0;
+      }
+      if b.len() > 0 {
+        current_length += b.len();
+        current.push(b);
+      }
+    }
+    if current_length > 0 { frames.push(ByteFrame::new(current, current_length)) };
+    frames
+  }
+
   /// Convert a stream of `ByteFrame` into a stream of `Bytes` _without_ copying. 🎉
   pub fn flatten_stream<S, E>(s: S) -> impl Stream<Item = Bytes, Error = E>
     where S: Stream<Item = ByteFrame, Error = E>
@@ -0,0 +1,157 @@
+use bytes::Bytes;
+use futures::{Async, Poll, Stream};
+use futures::stream::{Fuse};
+use std::io;
+
+use super::{ByteFrame, ByteStream};
+
+lazy_static! {
+  // "gear" table for the rolling hash: one pseudo-random u64 per byte value.
+  // it's generated from a fixed seed, so chunk boundaries are stable across
+  // builds and machines.
+  static ref GEAR: [u64; 256] = {
+    let mut table = [ 0u64; 256 ];
+    let mut seed: u64 = 0x4b0771e;
+    for entry in table.iter_mut() {
+      // splitmix64
+      seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+      let mut z = seed;
+      z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+      z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+      *entry = z ^ (z >> 31);
+    }
+    table
+  };
+}
+
+/// Chunk size limits for content-defined chunking. `average` must be a
+/// power of two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkSizes {
+  pub minimum: usize,
+  pub average: usize,
+  pub maximum: usize
+}
+
+impl ChunkSizes {
+  pub fn new(minimum: usize, average: usize, maximum: usize) -> ChunkSizes {
+    assert!(average.is_power_of_two() && average >= 64);
+    assert!(minimum > 0 && minimum <= average && average <= maximum);
+    ChunkSizes { minimum, average, maximum }
+  }
+}
+
+impl Default for ChunkSizes {
+  /// 2KB / 8KB / 64KB, the sizes suggested by the FastCDC paper.
+  fn default() -> ChunkSizes {
+    ChunkSizes::new(2 * 1024, 8 * 1024, 64 * 1024)
+  }
+}
+
+/// `Stream<Bytes>` that cuts data into content-defined chunks, emitting each
+/// as a `ByteFrame`, using FastCDC's gear hash with normalized chunking.
+///
+/// Boundaries depend only on the nearby content, so an insert or delete
+/// early in the stream only changes the chunks around the edit. Buffers
+/// are sliced at chunk boundaries, never copied.
+#[must_use = "streams do nothing unless polled"]
+pub struct ChunkedByteStream<S> where S: ByteStream {
+  stream: Fuse<S>,
+  sizes: ChunkSizes,
+  // stricter mask used until the chunk reaches the average size, then
+  // the looser one: this pulls chunk sizes toward the average.
+  mask_small: u64,
+  mask_large: u64,
+
+  // the unscanned part of the most recent buffer:
+  input: Option<Bytes>,
+
+  // the chunk in progress:
+  saved: Vec<Bytes>,
+  saved_count: usize,
+  hash: u64
+}
+
+impl<S> ChunkedByteStream<S> where S: ByteStream {
+  pub fn new(s: S, sizes: ChunkSizes) -> ChunkedByteStream<S> {
+    let bits = sizes.average.trailing_zeros();
+    ChunkedByteStream {
+      stream: s.fuse(),
+      sizes,
+      mask_small: high_bits_mask(bits + 1),
+      mask_large: high_bits_mask(bits - 1),
+      input: None,
+      saved: Vec::new(),
+      saved_count: 0,
+      hash: 0
+    }
+  }
+
+  // find the offset in `buffer` where the current chunk should end, if any.
+  fn find_boundary(&mut self, buffer: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    // the first `minimum` bytes of a chunk can never be a boundary, so
+    // don't bother hashing them.
+    if self.saved_count < self.sizes.minimum {
+      i = self.sizes.minimum - self.saved_count;
+      if i >= buffer.len() { return None; }
+    }
+
+    while i < buffer.len() {
+      let position = self.saved_count + i;
+      if position >= self.sizes.maximum { return Some(i); }
+      self.hash = (self.hash << 1).wrapping_add(GEAR[buffer[i] as usize]);
+      i += 1;
+      let mask = if position < self.sizes.average { self.mask_small } else { self.mask_large };
+      if self.hash & mask == 0 { return Some(i); }
+    }
+    if self.saved_count + i >= self.sizes.maximum { Some(i) } else { None }
+  }
+
+  fn complete(&mut self) -> ByteFrame {
+    let vec = self.saved.drain(..).collect::<Vec<Bytes>>();
+    let frame = ByteFrame::new(vec, self.saved_count);
+    self.saved_count = 0;
+    self.hash = 0;
+    frame
+  }
+}
+
+impl<S> Stream for ChunkedByteStream<S> where S: ByteStream {
+  type Item = ByteFrame;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    loop {
+      if let Some(mut buffer) = self.input.take() {
+        match self.find_boundary(buffer.as_ref()) {
+          Some(n) => {
+            if n < buffer.len() { self.input = Some(buffer.split_off(n)); }
+            self.saved_count += buffer.len();
+            self.saved.push(buffer);
+            return Ok(Async::Ready(Some(self.complete())));
+          },
+          None => {
+            self.saved_count += buffer.len();
+            self.saved.push(buffer);
+          }
+        }
+      }
+
+      match self.stream.poll() {
+        Err(e) => return Err(e),
+        Ok(Async::NotReady) => return Ok(Async::NotReady),
+        Ok(Async::Ready(None)) => {
+          return Ok(Async::Ready(if self.saved_count > 0 { Some(self.complete()) } else { None }));
+        },
+        Ok(Async::Ready(Some(buffer))) => {
+          if buffer.len() > 0 { self.input = Some(buffer); }
+        }
+      }
+    }
+  }
+}
+
+fn high_bits_mask(count: u32) -> u64 {
+  ((1u64 << count) - 1) << (64 - count)
+}
@@ -0,0 +1,35 @@
+use std::{error, fmt, io};
+
+/// Why a future waiting for a stream to finish (the completion from
+/// `generate_stream`, or the remainder from `split_until`) can't resolve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompletionError {
+  /// The stream ended with an error, which was returned from the stream.
+  Failed,
+  /// The stream was dropped before it finished.
+  Abandoned
+}
+
+impl fmt::Display for CompletionError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      CompletionError::Failed => write!(f, "Stream failed before it finished"),
+      CompletionError::Abandoned => write!(f, "Stream was abandoned before it finished")
+    }
+  }
+}
+
+impl error::Error for CompletionError {
+  fn description(&self) -> &str {
+    match *self {
+      CompletionError::Failed => "stream failed",
+      CompletionError::Abandoned => "stream abandoned"
+    }
+  }
+}
+
+impl From<CompletionError> for io::Error {
+  fn from(e: CompletionError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+  }
+}
@@ -0,0 +1,195 @@
+use bytes::{Buf, Bytes};
+use futures::{Async, Future, Poll, Stream};
+use futures::stream::{Fuse};
+use iovec::IoVec;
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+use tokio_io::AsyncWrite;
+
+use super::{ByteFrame, ByteStream};
+
+// most buffers to collect from the stream before writing.
+const MAX_BUFFERS: usize = 64;
+
+// small buffers are packed together up to this size, so a frame's length
+// prefix and its data go out in one call. bigger ones are written alone.
+const BATCH_SIZE: usize = 64 * 1024;
+
+/// Write a byte stream into a synchronous `io::Write`, like a file or
+/// socket. Each time it's polled, every buffer the stream has ready (up to
+/// a limit) is gathered, and small buffers are packed into one write, so a
+/// frame's length prefix and its data go out in a single call. Big buffers
+/// are written as they are.
+/// Resolves to the writer, after flushing it.
+///
+/// Like `ReaderByteStream`, each write blocks.
+pub fn drain_into_writer<S, W>(s: S, writer: W) -> DrainIntoWriter<S, W>
+  where S: ByteStream, W: Write
+{
+  DrainIntoWriter { drain: Drain::new(s), writer: Some(writer) }
+}
+
+/// Like `drain_into_writer`, but for an `AsyncWrite`: the gathered buffers
+/// are handed to `write_buf` as they are, without copying, so a writer
+/// that supports vectored writes (like a socket) sends them in one call.
+/// When the writer would block, the future is `NotReady` until the writer
+/// wakes it up.
+pub fn drain_into_async_writer<S, W>(s: S, writer: W) -> DrainIntoAsyncWriter<S, W>
+  where S: ByteStream, W: AsyncWrite
+{
+  DrainIntoAsyncWriter { drain: Drain::new(s), writer: Some(writer) }
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct DrainIntoWriter<S, W> where S: ByteStream, W: Write {
+  drain: Drain<S>,
+  writer: Option<W>
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct DrainIntoAsyncWriter<S, W> where S: ByteStream, W: AsyncWrite {
+  drain: Drain<S>,
+  writer: Option<W>
+}
+
+// buffers collected from the stream, waiting to be written.
+struct Drain<S> where S: ByteStream {
+  stream: Fuse<S>,
+  pending: VecDeque<Bytes>,
+  ended: bool
+}
+
+impl<S> Drain<S> where S: ByteStream {
+  fn new(s: S) -> Drain<S> {
+    Drain { stream: s.fuse(), pending: VecDeque::new(), ended: false }
+  }
+
+  // collect whatever the stream has ready. returns true if it's not ready
+  // for more.
+  fn gather(&mut self) -> Result<bool, io::Error> {
+    while !self.ended && self.pending.len() < MAX_BUFFERS {
+      match self.stream.poll()? {
+        Async::NotReady => return Ok(true),
+        Async::Ready(None) => self.ended = true,
+        Async::Ready(Some(b)) => if b.len() > 0 { self.pending.push_back(b) }
+      }
+    }
+    Ok(false)
+  }
+
+  // the next thing to write: a big buffer by itself, or small ones packed
+  // together.
+  fn next_batch(&mut self) -> Bytes {
+    let first = self.pending.pop_front().unwrap();
+    if first.len() >= BATCH_SIZE { return first }
+    let mut length = first.len();
+    let mut batch = vec![ first ];
+    while let Some(b) = self.pending.pop_front() {
+      if length + b.len() > BATCH_SIZE {
+        self.pending.push_front(b);
+        break;
+      }
+      length += b.len();
+      batch.push(b);
+    }
+    ByteFrame::new(batch, length).pack()
+  }
+
+  // forget the first `count` bytes, after they were written.
+  fn consume(&mut self, mut count: usize) {
+    while count > 0 {
+      let b = self.pending.pop_front().expect("wrote more than was pending");
+      if count < b.len() {
+        self.pending.push_front(b.slice_from(count));
+        return;
+      }
+      count -= b.len();
+    }
+  }
+}
+
+impl<S> Buf for Drain<S> where S: ByteStream {
+  fn remaining(&self) -> usize {
+    self.pending.iter().fold(0, |sum, b| sum + b.len())
+  }
+
+  fn bytes(&self) -> &[u8] {
+    self.pending.front().map(|b| b.as_ref()).unwrap_or(&[])
+  }
+
+  // pending buffers are never empty, so each one is a valid `IoVec`.
+  fn bytes_vec<'a>(&'a self, dst: &mut [&'a IoVec]) -> usize {
+    let mut count = 0;
+    for ( slot, b ) in dst.iter_mut().zip(self.pending.iter()) {
+      *slot = b.as_ref().into();
+      count += 1;
+    }
+    count
+  }
+
+  fn advance(&mut self, count: usize) {
+    self.consume(count);
+  }
+}
+
+impl<S, W> Future for DrainIntoWriter<S, W> where S: ByteStream, W: Write {
+  type Item = W;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<W, io::Error> {
+    loop {
+      let waiting = self.drain.gather()?;
+      let writer = self.writer.as_mut().expect("polling future twice");
+      if self.drain.pending.len() == 0 {
+        if waiting { return Ok(Async::NotReady) }
+        writer.flush()?;
+        return Ok(Async::Ready(self.writer.take().unwrap()));
+      }
+
+      let buffer = self.drain.next_batch();
+      let n = loop {
+        match writer.write(&buffer) {
+          Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+          Err(e) => return Err(e),
+          Ok(0) => return Err(write_zero_error()),
+          Ok(n) => break n
+        }
+      };
+      // save the rest of a partial write for next time.
+      if n < buffer.len() { self.drain.pending.push_front(buffer.slice_from(n)) }
+    }
+  }
+}
+
+impl<S, W> Future for DrainIntoAsyncWriter<S, W> where S: ByteStream, W: AsyncWrite {
+  type Item = W;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<W, io::Error> {
+    loop {
+      let waiting = self.drain.gather()?;
+      let writer = self.writer.as_mut().expect("polling future twice");
+      if self.drain.pending.len() == 0 {
+        if waiting { return Ok(Async::NotReady) }
+        match writer.poll_flush()? {
+          Async::NotReady => return Ok(Async::NotReady),
+          Async::Ready(()) => return Ok(Async::Ready(self.writer.take().unwrap()))
+        }
+      }
+
+      // `write_buf` advances past whatever was written.
+      match writer.write_buf(&mut self.drain) {
+        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) => return Err(e),
+        Ok(Async::NotReady) => return Ok(Async::NotReady),
+        Ok(Async::Ready(0)) => return Err(write_zero_error()),
+        Ok(Async::Ready(_)) => ()
+      }
+    }
+  }
+}
+
+fn write_zero_error() -> io::Error {
+  io::Error::new(io::ErrorKind::WriteZero, "Writer refused more data")
+}
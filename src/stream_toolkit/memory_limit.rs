@@ -0,0 +1,54 @@
+use std::{fmt, io};
+use std::sync::{Arc, Mutex};
+
+/// A cap on how many bytes a `ReadableByteStream` will collect for a single
+/// read, and a record of the most it has held at once. Clones share the
+/// record, so one `MemoryLimit` can track every stream on a connection.
+///
+/// With a limit, a stream never buffers more than `max_bytes`: `AtMost`
+/// and `Lazy` reads are trimmed to fit, and an `Exact` read that's too big
+/// fails. The source isn't polled again until there's room, and a buffer
+/// from the source that doesn't fit is fed in a piece at a time (copying
+/// the pieces if it's bigger than the whole limit).
+#[derive(Clone)]
+pub struct MemoryLimit {
+  max_bytes: usize,
+  peak: Arc<Mutex<usize>>
+}
+
+impl MemoryLimit {
+  pub fn new(max_bytes: usize) -> MemoryLimit {
+    assert!(max_bytes > 0);
+    MemoryLimit { max_bytes, peak: Arc::new(Mutex::new(0)) }
+  }
+
+  pub fn max_bytes(&self) -> usize {
+    self.max_bytes
+  }
+
+  /// The most bytes that were buffered at once, so far. This is never more
+  /// than `max_bytes`, unless a stream already held more when the limit was
+  /// added.
+  pub fn peak_buffered(&self) -> usize {
+    *self.peak.lock().unwrap()
+  }
+
+  pub fn record(&self, buffered: usize) {
+    let mut peak = self.peak.lock().unwrap();
+    if buffered > *peak { *peak = buffered }
+  }
+
+  pub fn check(&self, count: usize) -> io::Result<()> {
+    if count <= self.max_bytes { return Ok(()) }
+    Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("Read of {} bytes is over the memory limit ({})", count, self.max_bytes)
+    ))
+  }
+}
+
+impl fmt::Debug for MemoryLimit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "MemoryLimit(max_bytes={}, peak_buffered={})", self.max_bytes, self.peak_buffered())
+  }
+}
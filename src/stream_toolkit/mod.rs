@@ -2,20 +2,32 @@
 pub mod aliases;
 pub mod buffered_byte_stream;
 pub mod byte_frame;
+pub mod chunked_byte_stream;
+pub mod completion_error;
+pub mod drain_into_writer;
 pub mod helpers;
 pub mod hex;
+pub mod memory_limit;
 pub mod optional_future;
 pub mod readable_byte_stream;
+pub mod reader_byte_stream;
 pub mod split_until;
 pub mod stream_generator;
 
 // exports
-pub use self::aliases::{ByteStream, ByteStreamStream, IoFuture};
+pub use self::aliases::{BoxedByteStream, BoxedIoFuture, ByteStream, ByteStreamStream, IoFuture};
 pub use self::buffered_byte_stream::{BufferedByteStream};
 pub use self::byte_frame::{ByteFrame};
+pub use self::chunked_byte_stream::{ChunkedByteStream, ChunkSizes};
+pub use self::completion_error::{CompletionError};
+pub use self::drain_into_writer::{drain_into_async_writer, drain_into_writer, DrainIntoAsyncWriter, DrainIntoWriter};
 pub use self::helpers::{stream_of, stream_of_hex, stream_of_streams, stream_of_vec, stream_to_string_vec};
 pub use self::hex::{FromHex, ToHex};
+pub use self::memory_limit::{MemoryLimit};
 pub use self::optional_future::{OptionFuture, OptionToFuture};
-pub use self::readable_byte_stream::{ReadableByteStream, ReadableByteStreamFuture, ReadMode};
+pub use self::readable_byte_stream::{
+  ReadableByteStream, ReadableByteStreamFuture, ReadableByteStreamSkipFuture, ReadMode, SeekableByteStream
+};
+pub use self::reader_byte_stream::{ReaderByteStream};
 pub use self::split_until::{SplitUntil};
-pub use self::stream_generator::{generate_stream};
+pub use self::stream_generator::{generate_skippable_stream, generate_stream};
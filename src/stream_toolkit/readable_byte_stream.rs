@@ -1,10 +1,12 @@
 use bytes::{Bytes};
-use futures::{Async, Future, Poll, Stream, stream};
+use futures::{Async, Future, future, Poll, Stream, stream};
+use futures::future::{Loop};
 use futures::stream::{Fuse};
 use std::collections::VecDeque;
-use std::{fmt, io};
+use std::{cmp, fmt, io, usize};
 
-use super::{ByteFrame, ByteStream};
+use super::{ByteFrame, ByteStream, IoFuture, MemoryLimit};
+use zint;
 
 /// Behaviors for `ReadableByteStream::read`
 #[derive(Clone, Copy, PartialEq)]
@@ -23,6 +25,14 @@ pub enum ReadMode {
   Lazy
 }
 
+/// A byte stream that can jump ahead without reading the bytes in between,
+/// like a file.
+pub trait SeekableByteStream: ByteStream {
+  /// Move ahead `count` bytes (or to the end, if there are fewer than that
+  /// left), returning how many bytes were skipped.
+  fn skip_ahead(&mut self, count: usize) -> io::Result<usize>;
+}
+
 /// Wrap a `Stream<Bytes>` so that it has a few `read()` method variants,
 /// each returning a future.
 ///
@@ -36,30 +46,54 @@ pub enum ReadMode {
 /// into perfectly-sized chunks, the object keeps pre-read data around to use
 /// for subsequent requests. You may use `into_stream()` to create a `Stream`
 /// object that combines the leftover buffers with the remaining stream.
+///
+/// Use `seekable()` to wrap a `SeekableByteStream`, so that skips move
+/// ahead in the underlying source instead of reading, and
+/// `with_memory_limit()` to cap how much each read will buffer.
 pub struct ReadableByteStream<S> where S: ByteStream {
   stream: Fuse<S>,
   saved: VecDeque<Bytes>,
-  saved_count: usize
+  saved_count: usize,
+  // the rest of a buffer from upstream that didn't fit under the memory
+  // limit. it isn't counted as saved until it's moved there.
+  overflow: Option<Bytes>,
+  seeker: Option<Seeker<S>>,
+  limit: Option<MemoryLimit>
 }
 
+type Seeker<S> = fn(&mut S, usize) -> io::Result<usize>;
+
 impl<S> fmt::Debug for ReadableByteStream<S> where S: ByteStream {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "ReadableByteStream(saved_count={:?})", self.saved_count)
   }
 }
 
+impl<S> ReadableByteStream<S> where S: SeekableByteStream {
+  pub fn seekable(s: S) -> ReadableByteStream<S> {
+    ReadableByteStream { seeker: Some(S::skip_ahead), ..ReadableByteStream::from(s) }
+  }
+}
+
 impl<S> ReadableByteStream<S> where S: ByteStream {
   /// Read `count` bytes from a stream, returning a `Future<ByteFrame>` with
   /// a `Vec<Bytes>` of the cumulative buffers.
   pub fn read(self, count: usize, mode: ReadMode)
     -> ReadableByteStreamFuture<S>
   {
+    let count = match self.limit {
+      Some(ref limit) if mode != ReadMode::Exact => cmp::min(count, limit.max_bytes()),
+      _ => count
+    };
     ReadableByteStreamFuture {
       stream: Some(self.stream),
       count,
       mode,
       saved: self.saved,
-      saved_count: self.saved_count
+      saved_count: self.saved_count,
+      overflow: self.overflow,
+      seeker: self.seeker,
+      limit: self.limit
     }
   }
 
@@ -83,30 +117,141 @@ impl<S> ReadableByteStream<S> where S: ByteStream {
     self.read(count, ReadMode::AtMost)
   }
 
+  /// Look at the next `count` bytes without consuming them. If not enough
+  /// bytes are available before EOF, an EOF error is returned.
+  pub fn peek(self, count: usize) -> impl IoFuture<(ByteFrame, ReadableByteStream<S>)> {
+    self.read_exact(count).map(|(frame, mut s)| {
+      s.unread(frame.clone());
+      ( frame, s )
+    })
+  }
+
+  /// Read up to and including the first occurrence of `byte`, slicing
+  /// buffers instead of copying. If the stream ends before `byte` shows
+  /// up, everything up to the end is returned.
+  pub fn read_until(self, byte: u8) -> impl IoFuture<(ByteFrame, ReadableByteStream<S>)> {
+    future::loop_fn(( Vec::new(), 0, self ), move |(mut vec, mut length, s): (Vec<Bytes>, usize, ReadableByteStream<S>)| {
+      s.read(1, ReadMode::Lazy).map(move |(frame, mut s)| {
+        if frame.length == 0 { return Loop::Break(( ByteFrame::new(vec, length), s )) }
+        let mut buffers = frame.vec.into_iter();
+        while let Some(b) = buffers.next() {
+          if let Some(i) = b.iter().position(|&x| x == byte) {
+            length += i + 1;
+            vec.push(b.slice(0, i + 1));
+            let rest: Vec<Bytes> = Some(b.slice_from(i + 1)).into_iter().chain(buffers).filter(|b| b.len() > 0).collect();
+            s.unread(ByteFrame::from(rest));
+            return Loop::Break(( ByteFrame::new(vec, length), s ));
+          }
+          length += b.len();
+          vec.push(b);
+        }
+        Loop::Continue(( vec, length, s ))
+      })
+    })
+  }
+
+  pub fn read_u8(self) -> impl IoFuture<(u8, ReadableByteStream<S>)> {
+    self.read_exact(1).map(|(frame, s)| ( decode_be(&frame) as u8, s ))
+  }
+
+  pub fn read_u16_le(self) -> impl IoFuture<(u16, ReadableByteStream<S>)> {
+    self.read_exact(2).map(|(frame, s)| ( decode_le(&frame) as u16, s ))
+  }
+
+  pub fn read_u16_be(self) -> impl IoFuture<(u16, ReadableByteStream<S>)> {
+    self.read_exact(2).map(|(frame, s)| ( decode_be(&frame) as u16, s ))
+  }
+
+  pub fn read_u32_le(self) -> impl IoFuture<(u32, ReadableByteStream<S>)> {
+    self.read_exact(4).map(|(frame, s)| ( decode_le(&frame) as u32, s ))
+  }
+
+  pub fn read_u32_be(self) -> impl IoFuture<(u32, ReadableByteStream<S>)> {
+    self.read_exact(4).map(|(frame, s)| ( decode_be(&frame) as u32, s ))
+  }
+
+  pub fn read_u64_le(self) -> impl IoFuture<(u64, ReadableByteStream<S>)> {
+    self.read_exact(8).map(|(frame, s)| ( decode_le(&frame), s ))
+  }
+
+  pub fn read_u64_be(self) -> impl IoFuture<(u64, ReadableByteStream<S>)> {
+    self.read_exact(8).map(|(frame, s)| ( decode_be(&frame), s ))
+  }
+
+  /// Read a frame length (1 to 3 bytes, or an end-of-stream or
+  /// end-of-bottle marker).
+  pub fn read_zint_length(self) -> impl IoFuture<(zint::FrameLength, ReadableByteStream<S>)> {
+    self.read_u8().and_then(|(byte, s)| {
+      let ( count, accumulator ) = zint::decode_first_length_byte(byte);
+      s.read_exact(count).map(|(frame, s)| {
+        ( zint::decode_length(accumulator, frame.pack().as_ref()), s )
+      })
+    })
+  }
+
+  /// Cap the memory used by each read from now on. (Anything already
+  /// buffered stays.)
+  pub fn with_memory_limit(self, limit: MemoryLimit) -> ReadableByteStream<S> {
+    limit.record(self.saved_count);
+    ReadableByteStream { limit: Some(limit), ..self }
+  }
+
+  /// Skip exactly `count` bytes, dropping each buffer as it arrives. If the
+  /// stream ends first, an EOF error is returned.
+  pub fn skip(self, count: usize) -> ReadableByteStreamSkipFuture<S> {
+    ReadableByteStreamSkipFuture { stream: Some(self), remaining: count, skipped: 0, to_end: false }
+  }
+
+  /// Skip everything left in the stream, returning the number of bytes
+  /// skipped.
+  pub fn skip_to_end(self) -> ReadableByteStreamSkipFuture<S> {
+    ReadableByteStreamSkipFuture { stream: Some(self), remaining: usize::MAX, skipped: 0, to_end: true }
+  }
+
   pub fn unread(&mut self, frame: ByteFrame) {
     self.saved_count += frame.length;
     for b in frame.vec.into_iter().rev() { self.saved.push_front(b) };
   }
 
-  /// Decompose back into an optional buffer (anything that has been pre-read)
-  /// and the original stream.
+  /// Decompose back into an optional buffer (anything that has been pre-read
+  /// or unread, packed together) and the original stream.
   pub fn into_inner(self) -> (Option<Bytes>, S) {
-    assert!(self.saved.len() <= 1);
-    let mut saved = self.saved;
-    ( saved.pop_front(), self.stream.into_inner() )
+    let mut buffers: Vec<Bytes> = self.saved.into_iter().chain(self.overflow).collect();
+    let saved = match buffers.len() {
+      0 => None,
+      1 => buffers.pop(),
+      _ => Some(ByteFrame::from(buffers).pack())
+    };
+    ( saved, self.stream.into_inner() )
   }
 
   /// Merge any remainder buffer back into the stream as if it had been
   /// "un-read". This consumes `self`, returning the new combined stream.
   pub fn into_stream(self) -> impl Stream<Item = Bytes, Error = io::Error> {
     let stream = self.stream;
-    stream::iter(self.saved.into_iter().map(|b| Ok(b))).chain(stream)
+    stream::iter(self.saved.into_iter().chain(self.overflow).map(|b| Ok(b))).chain(stream)
   }
 }
 
+// decode an integer from each byte of a frame, without packing it first.
+fn decode_le(frame: &ByteFrame) -> u64 {
+  frame.vec.iter().flat_map(|b| b.iter()).enumerate().fold(0, |n, (i, &b)| n | ((b as u64) << (8 * i)))
+}
+
+fn decode_be(frame: &ByteFrame) -> u64 {
+  frame.vec.iter().flat_map(|b| b.iter()).fold(0, |n, &b| (n << 8) | (b as u64))
+}
+
 impl<S> From<S> for ReadableByteStream<S> where S: ByteStream {
   fn from(s: S) -> ReadableByteStream<S> {
-    ReadableByteStream { stream: s.fuse(), saved: VecDeque::new(), saved_count: 0 }
+    ReadableByteStream {
+      stream: s.fuse(),
+      saved: VecDeque::new(),
+      saved_count: 0,
+      overflow: None,
+      seeker: None,
+      limit: None
+    }
   }
 }
 
@@ -121,7 +266,10 @@ pub struct ReadableByteStreamFuture<S> where S: ByteStream {
 
   // internal state:
   saved: VecDeque<Bytes>,
-  saved_count: usize
+  saved_count: usize,
+  overflow: Option<Bytes>,
+  seeker: Option<Seeker<S>>,
+  limit: Option<MemoryLimit>
 }
 
 impl<S> ReadableByteStreamFuture<S> where S: ByteStream {
@@ -130,20 +278,26 @@ impl<S> ReadableByteStreamFuture<S> where S: ByteStream {
   ///
   /// - If `mode` is `Exact` or `AtMost`, a `Bytes` may be split to return
   ///   exactly `count` bytes.
+  /// - If `mode` is `Lazy`, a `Bytes` is only split to stay under the
+  ///   memory limit.
   /// - If there aren't `count` bytes buffered, you'll get less than you
   ///   asked for. To prevent this, check `total_saved` before calling.
   fn drain(&mut self) -> ByteFrame {
     let mut vec: Vec<Bytes> = Vec::new();
     let mut length = 0;
+    let max_length = match self.mode {
+      ReadMode::Lazy => self.limit.as_ref().map(|limit| limit.max_bytes()).unwrap_or(usize::MAX),
+      _ => self.count
+    };
 
     while self.saved.len() > 0 && length < self.count {
       let chunk = self.saved.pop_front().unwrap();
-      if (length + chunk.len() <= self.count) || self.mode == ReadMode::Lazy {
+      if chunk.len() <= max_length - length {
         length += chunk.len();
         self.saved_count -= chunk.len();
         vec.push(chunk);
       } else {
-        let n = self.count - length;
+        let n = max_length - length;
         length += n;
         self.saved_count -= n;
         vec.push(chunk.slice(0, n));
@@ -162,8 +316,38 @@ impl<S> ReadableByteStreamFuture<S> where S: ByteStream {
    */
   fn complete(&mut self, stream: Fuse<S>) -> (ByteFrame, ReadableByteStream<S>) {
     let frame = self.drain();
-    assert!(self.saved.len() <= 1);
-    ( frame, ReadableByteStream { stream, saved: self.saved.clone(), saved_count: self.saved_count } )
+    let s = ReadableByteStream {
+      stream,
+      saved: self.saved.clone(),
+      saved_count: self.saved_count,
+      overflow: self.overflow.take(),
+      seeker: self.seeker,
+      limit: self.limit.clone()
+    };
+    ( frame, s )
+  }
+
+  /*
+   * save a buffer from upstream. with a memory limit, only as much as fits
+   * is saved, and the rest is held as overflow until there's room. pieces
+   * of a buffer that's bigger than the whole limit are copied out, so they
+   * don't keep it alive after it's been used up.
+   */
+  fn save(&mut self, buffer: Bytes) {
+    let max_bytes = self.limit.as_ref().map(|limit| limit.max_bytes()).unwrap_or(usize::MAX);
+    let room = max_bytes.saturating_sub(self.saved_count);
+    let piece = if buffer.len() <= room {
+      buffer
+    } else {
+      let piece = if buffer.len() > max_bytes { Bytes::from(&buffer[.. room]) } else { buffer.slice(0, room) };
+      self.overflow = Some(buffer.slice_from(room));
+      piece
+    };
+    if !piece.is_empty() {
+      self.saved_count += piece.len();
+      self.saved.push_back(piece);
+    }
+    for limit in self.limit.iter() { limit.record(self.saved_count) };
   }
 }
 
@@ -172,12 +356,23 @@ impl<S> Future for ReadableByteStreamFuture<S> where S: ByteStream {
   type Error = io::Error;
 
   fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    if let Some(ref limit) = self.limit {
+      if self.mode == ReadMode::Exact { limit.check(self.count)? }
+    }
+
     loop {
       let mut stream = self.stream.take().expect("stream in use");
       if self.saved_count >= self.count {
         return Ok(Async::Ready(self.complete(stream)))
       }
 
+      // use up any overflow before asking upstream for more.
+      if let Some(buffer) = self.overflow.take() {
+        self.stream = Some(stream);
+        self.save(buffer);
+        continue;
+      }
+
       match stream.poll() {
         Ok(Async::NotReady) => {
           self.stream = Some(stream);
@@ -195,8 +390,7 @@ impl<S> Future for ReadableByteStreamFuture<S> where S: ByteStream {
 
         Ok(Async::Ready(Some(buffer))) => {
           self.stream = Some(stream);
-          self.saved_count += buffer.len();
-          self.saved.push_back(buffer);
+          self.save(buffer);
           // fall through to check if we have enough buffered to exit.
         }
 
@@ -210,3 +404,92 @@ impl<S> Future for ReadableByteStreamFuture<S> where S: ByteStream {
     }
   }
 }
+
+
+// ----- ReadableByteStreamSkipFuture
+
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadableByteStreamSkipFuture<S> where S: ByteStream {
+  stream: Option<ReadableByteStream<S>>,
+  remaining: usize,
+  skipped: usize,
+  to_end: bool
+}
+
+impl<S> ReadableByteStreamSkipFuture<S> where S: ByteStream {
+  fn advance(&mut self, count: usize) {
+    self.remaining -= count;
+    self.skipped += count;
+  }
+
+  // skip as much as we can from the saved buffers, then the overflow.
+  fn skip_saved(&mut self, s: &mut ReadableByteStream<S>) {
+    while self.remaining > 0 && s.saved.len() > 0 {
+      let chunk = s.saved.pop_front().unwrap();
+      if chunk.len() <= self.remaining {
+        s.saved_count -= chunk.len();
+        self.advance(chunk.len());
+      } else {
+        let n = self.remaining;
+        s.saved_count -= n;
+        self.advance(n);
+        s.saved.push_front(chunk.slice_from(n));
+      }
+    }
+    if self.remaining == 0 { return }
+    if let Some(chunk) = s.overflow.take() {
+      let n = cmp::min(chunk.len(), self.remaining);
+      self.advance(n);
+      if n < chunk.len() { s.overflow = Some(chunk.slice_from(n)) }
+    }
+  }
+
+  fn finish(&mut self, s: ReadableByteStream<S>) -> Poll<(usize, ReadableByteStream<S>), io::Error> {
+    if self.remaining > 0 && !self.to_end {
+      return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF"));
+    }
+    Ok(Async::Ready(( self.skipped, s )))
+  }
+}
+
+impl<S> Future for ReadableByteStreamSkipFuture<S> where S: ByteStream {
+  type Item = (usize, ReadableByteStream<S>);
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    let mut s = self.stream.take().expect("stream in use");
+    self.skip_saved(&mut s);
+
+    // if the source can seek, there's no need to read anything.
+    if self.remaining > 0 && !s.stream.is_done() {
+      if let Some(seeker) = s.seeker {
+        let n = seeker(s.stream.get_mut(), self.remaining)?;
+        self.advance(n);
+        return self.finish(s);
+      }
+    }
+
+    loop {
+      if self.remaining == 0 { return self.finish(s) }
+
+      match s.stream.poll() {
+        Ok(Async::NotReady) => {
+          self.stream = Some(s);
+          return Ok(Async::NotReady);
+        },
+        Ok(Async::Ready(None)) => return self.finish(s),
+        Ok(Async::Ready(Some(buffer))) => {
+          if buffer.len() <= self.remaining {
+            self.advance(buffer.len());
+          } else {
+            // the next read will save as much as fits.
+            let n = self.remaining;
+            self.advance(n);
+            s.overflow = Some(buffer.slice_from(n));
+          }
+        },
+        Err(error) => return Err(error)
+      }
+    }
+  }
+}
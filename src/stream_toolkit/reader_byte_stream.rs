@@ -0,0 +1,55 @@
+use bytes::Bytes;
+use futures::{Async, Poll, Stream};
+use std::{cmp, io};
+use std::io::SeekFrom;
+
+use super::SeekableByteStream;
+
+/// `Stream<Bytes>` that reads blocks from a synchronous `io::Read`, like a
+/// file. Each read blocks, so this is best for local files.
+#[must_use = "streams do nothing unless polled"]
+pub struct ReaderByteStream<R> where R: io::Read {
+  reader: R,
+  block_size: usize
+}
+
+impl<R> ReaderByteStream<R> where R: io::Read {
+  pub fn new(reader: R, block_size: usize) -> ReaderByteStream<R> {
+    assert!(block_size > 0);
+    ReaderByteStream { reader, block_size }
+  }
+
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+}
+
+impl<R> Stream for ReaderByteStream<R> where R: io::Read {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    let mut buffer = vec![ 0u8; self.block_size ];
+    loop {
+      match self.reader.read(&mut buffer) {
+        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+        Err(e) => return Err(e),
+        Ok(0) => return Ok(Async::Ready(None)),
+        Ok(n) => {
+          buffer.truncate(n);
+          return Ok(Async::Ready(Some(Bytes::from(buffer))));
+        }
+      }
+    }
+  }
+}
+
+impl<R> SeekableByteStream for ReaderByteStream<R> where R: io::Read + io::Seek {
+  fn skip_ahead(&mut self, count: usize) -> io::Result<usize> {
+    let here = self.reader.seek(SeekFrom::Current(0))?;
+    let end = self.reader.seek(SeekFrom::End(0))?;
+    let target = cmp::min(end, here.saturating_add(count as u64));
+    self.reader.seek(SeekFrom::Start(target))?;
+    Ok((target - here) as usize)
+  }
+}
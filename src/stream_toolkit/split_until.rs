@@ -1,6 +1,8 @@
 use futures::{Async, Future, IntoFuture, Poll, Stream, task};
 use std::sync::{Arc, Mutex};
 
+use super::CompletionError;
+
 pub trait SplitUntil {
   /// Split this stream in two, using a function to determine which item is
   /// the split point.
@@ -15,6 +17,10 @@ pub trait SplitUntil {
   /// resolves. If `is_last` resolves to true, the `SplitStream` is
   /// completed, and `SplitFuture` resolves to the remainder of the original
   /// stream.
+  ///
+  /// If `SplitStream` hits an error, `SplitFuture` fails with
+  /// `CompletionError::Failed`, and if it's dropped before it completes,
+  /// `SplitFuture` fails with `CompletionError::Abandoned`.
   fn split_until<P, R>(self, is_last: P) -> (SplitStream<Self, P, R>, SplitFuture<Self, P, R>)
     where
       Self: Stream + Sized,
@@ -49,6 +55,8 @@ struct Inner<S, P, R> where S: Stream, R: IntoFuture {
   stream: Option<S>,
   is_last: P,
   complete: bool,
+  // the SplitStream ended early, from an error or being dropped:
+  failure: Option<CompletionError>,
 
   // when we have an item, but we're waiting for the future to complete:
   pending_future: Option<R::Future>,
@@ -69,6 +77,7 @@ impl<S, P, R> Inner<S, P, R>
       stream: Some(stream),
       is_last,
       complete: false,
+      failure: None,
       pending_future: None,
       pending_item: None,
       remainder_task: None
@@ -105,6 +114,13 @@ impl<S, P, R> Inner<S, P, R>
     self.complete = true;
     for t in self.remainder_task.take() { t.unpark() };
   }
+
+  // the SplitStream can't finish. tell SplitFuture, if necessary.
+  fn fail(&mut self, failure: CompletionError) {
+    self.clear_pending();
+    if self.failure.is_none() { self.failure = Some(failure) };
+    for t in self.remainder_task.take() { t.unpark() };
+  }
 }
 
 
@@ -132,7 +148,7 @@ impl<S, P, R> Stream for SplitStream<S, P, R>
 
     match inner.feed() {
       Err(e) => {
-        inner.clear_pending();
+        inner.fail(CompletionError::Failed);
         Err(e)
       },
       Ok(FeederState::Waiting) => Ok(Async::NotReady),
@@ -144,7 +160,7 @@ impl<S, P, R> Stream for SplitStream<S, P, R>
       Ok(FeederState::Processing) => {
         match inner.pending_future.as_mut().unwrap().poll() {
           Err(e) => {
-            inner.clear_pending();
+            inner.fail(CompletionError::Failed);
             Err(e)
           },
           Ok(Async::NotReady) => Ok(Async::NotReady),
@@ -164,6 +180,21 @@ impl<S, P, R> Stream for SplitStream<S, P, R>
 }
 
 
+impl<S, P, R> Drop for SplitStream<S, P, R> where S: Stream, R: IntoFuture {
+  fn drop(&mut self) {
+    let mut inner = match self.inner.lock() {
+      Ok(inner) => inner,
+      // don't make a panic worse.
+      Err(_) => return
+    };
+    if !inner.complete && inner.failure.is_none() {
+      inner.failure = Some(CompletionError::Abandoned);
+      for t in inner.remainder_task.take() { t.unpark() };
+    }
+  }
+}
+
+
 // ----- SplitFuture
 
 #[must_use = "futures do nothing unless polled"]
@@ -175,13 +206,15 @@ impl<S, P, R> Future for SplitFuture<S, P, R>
   where
     S: Stream,
     P: FnMut(&S::Item) -> R,
-    R: IntoFuture<Item = bool, Error = S::Error>
+    R: IntoFuture<Item = bool, Error = S::Error>,
+    S::Error: From<CompletionError>
 {
   type Item = S;
   type Error = S::Error;
 
   fn poll(&mut self) -> Poll<S, S::Error> {
     let mut inner = self.inner.lock().unwrap();
+    for failure in inner.failure { return Err(failure.into()) };
     if !inner.complete {
       inner.remainder_task = Some(task::park());
       return Ok(Async::NotReady);
@@ -1,6 +1,8 @@
 use futures::{Async, Future, IntoFuture, Poll, Stream, task};
 use std::sync::{Arc, Mutex};
 
+use super::CompletionError;
+
 enum State<StateT, ItemFutureT, StateFutureT> {
   /// No active future is running. Ready to call the function and process the next future.
   Ready(StateT),
@@ -15,12 +17,20 @@ enum State<StateT, ItemFutureT, StateFutureT> {
   Done(StateFutureT),
 
   /// Stream ended, but not in a good way.
-  Error
+  Error,
+
+  /// The stream was dropped before it ended.
+  Abandoned
 }
 
-struct Inner<StateT, ItemFutureT: IntoFuture, StateFutureT: IntoFuture> {
+struct Inner<StateT, SkipT, ItemFutureT: IntoFuture, StateFutureT: IntoFuture> {
   generator_state: Option<State<StateT, ItemFutureT::Future, StateFutureT::Future>>,
-  task: Option<task::Task>
+  task: Option<task::Task>,
+
+  // if the stream is dropped before it ends, the completion future uses
+  // this function to finish the stream, discarding the items:
+  skip: Option<SkipT>,
+  dropped: bool
 }
 
 /// Generate a stream from an initial state, and a function that returns a
@@ -49,30 +59,70 @@ struct Inner<StateT, ItemFutureT: IntoFuture, StateFutureT: IntoFuture> {
 /// });
 /// ```
 ///
-/// FIXME: say moar
+/// If the stream fails, the error is returned from the stream, and the
+/// completion future fails with `CompletionError::Failed`. If the stream is
+/// dropped before it ends, the completion fails with
+/// `CompletionError::Abandoned`.
 pub fn generate_stream<FunctionT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT>(state: StateT, f: FunctionT)
   -> (
-    StreamGenerator<StateT, FunctionT, ItemFutureT, StateFutureT>,
-    StreamGeneratorCompletion<StateT, ItemFutureT, StateFutureT>
+    StreamGenerator<StateT, FunctionT, FunctionT, ItemFutureT, StateFutureT>,
+    StreamGeneratorCompletion<StateT, FunctionT, ItemFutureT, StateFutureT>
   )
   where
     FunctionT: FnMut(StateT) -> ItemFutureT,
     ItemFutureT: IntoFuture<Item = (Option<ItemT>, StateFutureT), Error = ErrorT>,
     StateFutureT: IntoFuture<Item = StateT, Error = ErrorT>
 {
-  let inner = Arc::new(Mutex::new(Inner { generator_state: Some(State::Ready(state)), task: None }));
+  build(state, f, None)
+}
+
+/// Generate a stream like `generate_stream`, but if the stream is dropped
+/// before it ends, the completion future finishes it instead of failing.
+/// It calls `skip` in place of `f` for each remaining item, and discards the
+/// items, so `skip` can take a cheaper path, like skipping over data
+/// instead of reading it.
+pub fn generate_skippable_stream<FunctionT, SkipT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT>(
+  state: StateT,
+  f: FunctionT,
+  skip: SkipT
+) -> (
+  StreamGenerator<StateT, FunctionT, SkipT, ItemFutureT, StateFutureT>,
+  StreamGeneratorCompletion<StateT, SkipT, ItemFutureT, StateFutureT>
+)
+  where
+    FunctionT: FnMut(StateT) -> ItemFutureT,
+    SkipT: FnMut(StateT) -> ItemFutureT,
+    ItemFutureT: IntoFuture<Item = (Option<ItemT>, StateFutureT), Error = ErrorT>,
+    StateFutureT: IntoFuture<Item = StateT, Error = ErrorT>
+{
+  build(state, f, Some(skip))
+}
+
+fn build<FunctionT, SkipT, ItemFutureT, StateFutureT, StateT>(state: StateT, f: FunctionT, skip: Option<SkipT>)
+  -> (
+    StreamGenerator<StateT, FunctionT, SkipT, ItemFutureT, StateFutureT>,
+    StreamGeneratorCompletion<StateT, SkipT, ItemFutureT, StateFutureT>
+  )
+  where ItemFutureT: IntoFuture, StateFutureT: IntoFuture
+{
+  let inner = Arc::new(Mutex::new(Inner {
+    generator_state: Some(State::Ready(state)),
+    task: None,
+    skip,
+    dropped: false
+  }));
   let generator = StreamGenerator { inner: inner.clone(), f };
   let completion = StreamGeneratorCompletion { inner: inner.clone() };
   ( generator, completion )
 }
 
-pub struct StreamGenerator<StateT, FunctionT, ItemFutureT: IntoFuture, StateFutureT: IntoFuture> {
+pub struct StreamGenerator<StateT, FunctionT, SkipT, ItemFutureT: IntoFuture, StateFutureT: IntoFuture> {
   f: FunctionT,
-  inner: Arc<Mutex<Inner<StateT, ItemFutureT, StateFutureT>>>
+  inner: Arc<Mutex<Inner<StateT, SkipT, ItemFutureT, StateFutureT>>>
 }
 
-impl<FunctionT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT> Stream
-  for StreamGenerator<StateT, FunctionT, ItemFutureT, StateFutureT>
+impl<FunctionT, SkipT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT> Stream
+  for StreamGenerator<StateT, FunctionT, SkipT, ItemFutureT, StateFutureT>
   where
     FunctionT: FnMut(StateT) -> ItemFutureT,
     ItemFutureT: IntoFuture<Item = (Option<ItemT>, StateFutureT), Error = ErrorT>,
@@ -95,6 +145,7 @@ impl<FunctionT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT> Stream
           return match item_future.poll() {
             Err(e) => {
               inner.generator_state = Some(State::Error);
+              for t in inner.task.take() { t.unpark() };
               Err(e)
             },
             Ok(Async::NotReady) => {
@@ -117,6 +168,7 @@ impl<FunctionT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT> Stream
           match state_future.poll() {
             Err(e) => {
               inner.generator_state = Some(State::Error);
+              for t in inner.task.take() { t.unpark() };
               return Err(e);
             },
             Ok(Async::NotReady) => {
@@ -129,13 +181,103 @@ impl<FunctionT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT> Stream
           }
         },
 
-        State::Done(_) => {
+        State::Done(state_future) => {
+          // leave the final state for the completion future.
+          inner.generator_state = Some(State::Done(state_future));
           return Ok(Async::Ready(None));
         },
 
         // it makes no sense to poll a stream after an error, so just keep saying it ended.
         State::Error => {
+          inner.generator_state = Some(State::Error);
           return Ok(Async::Ready(None));
+        },
+
+        State::Abandoned => unreachable!()
+      }
+    }
+  }
+}
+
+impl<StateT, FunctionT, SkipT, ItemFutureT, StateFutureT> Drop
+  for StreamGenerator<StateT, FunctionT, SkipT, ItemFutureT, StateFutureT>
+  where ItemFutureT: IntoFuture, StateFutureT: IntoFuture
+{
+  fn drop(&mut self) {
+    let mut inner = match self.inner.lock() {
+      Ok(inner) => inner,
+      // don't make a panic worse.
+      Err(_) => return
+    };
+    let finished = match inner.generator_state {
+      Some(State::Done(_)) | Some(State::Error) => true,
+      _ => false
+    };
+    if !finished {
+      if inner.skip.is_some() {
+        inner.dropped = true;
+      } else {
+        inner.generator_state = Some(State::Abandoned);
+      }
+      for t in inner.task.take() { t.unpark() };
+    }
+  }
+}
+
+
+impl<StateT, SkipT, ItemFutureT, ItemT, StateFutureT> Inner<StateT, SkipT, ItemFutureT, StateFutureT>
+  where
+    SkipT: FnMut(StateT) -> ItemFutureT,
+    ItemFutureT: IntoFuture<Item = (Option<ItemT>, StateFutureT), Error = StateFutureT::Error>,
+    StateFutureT: IntoFuture<Item = StateT>
+{
+  // run the rest of the stream using `skip`, throwing away the items, until
+  // the stream ends (or fails).
+  fn skip_to_end(&mut self) -> Poll<(), StateFutureT::Error> {
+    loop {
+      match self.generator_state.take().expect("polling future twice") {
+        State::Ready(state) => {
+          let item_future = (self.skip.as_mut().expect("no skip function"))(state).into_future();
+          self.generator_state = Some(State::WorkingOnItem(item_future));
+        },
+
+        State::WorkingOnItem(mut item_future) => {
+          match item_future.poll() {
+            Err(e) => {
+              self.generator_state = Some(State::Error);
+              return Err(e);
+            },
+            Ok(Async::NotReady) => {
+              self.generator_state = Some(State::WorkingOnItem(item_future));
+              return Ok(Async::NotReady);
+            },
+            Ok(Async::Ready((item, state_future))) => {
+              let state_future = state_future.into_future();
+              self.generator_state =
+                Some(if item.is_some() { State::WorkingOnState(state_future) } else { State::Done(state_future) });
+            }
+          }
+        },
+
+        State::WorkingOnState(mut state_future) => {
+          match state_future.poll() {
+            Err(e) => {
+              self.generator_state = Some(State::Error);
+              return Err(e);
+            },
+            Ok(Async::NotReady) => {
+              self.generator_state = Some(State::WorkingOnState(state_future));
+              return Ok(Async::NotReady);
+            },
+            Ok(Async::Ready(state)) => {
+              self.generator_state = Some(State::Ready(state));
+            }
+          }
+        },
+
+        other => {
+          self.generator_state = Some(other);
+          return Ok(Async::Ready(()));
         }
       }
     }
@@ -146,35 +288,56 @@ impl<FunctionT, ItemFutureT, ItemT, StateFutureT, StateT, ErrorT> Stream
 // ----- StreamGeneratorCompletion
 
 #[must_use = "futures do nothing unless polled"]
-pub struct StreamGeneratorCompletion<StateT, ItemFutureT: IntoFuture, StateFutureT: IntoFuture> {
-  inner: Arc<Mutex<Inner<StateT, ItemFutureT, StateFutureT>>>
+pub struct StreamGeneratorCompletion<StateT, SkipT, ItemFutureT: IntoFuture, StateFutureT: IntoFuture> {
+  inner: Arc<Mutex<Inner<StateT, SkipT, ItemFutureT, StateFutureT>>>
 }
 
-impl<StateT, ItemFutureT, StateFutureT> Future for StreamGeneratorCompletion<StateT, ItemFutureT, StateFutureT>
+impl<StateT, SkipT, ItemFutureT, ItemT, StateFutureT> Future
+  for StreamGeneratorCompletion<StateT, SkipT, ItemFutureT, StateFutureT>
   where
-    ItemFutureT: IntoFuture,
-    StateFutureT: IntoFuture<Item = StateT>
+    SkipT: FnMut(StateT) -> ItemFutureT,
+    ItemFutureT: IntoFuture<Item = (Option<ItemT>, StateFutureT), Error = StateFutureT::Error>,
+    StateFutureT: IntoFuture<Item = StateT>,
+    StateFutureT::Error: From<CompletionError>
 {
   type Item = StateT;
   type Error = StateFutureT::Error;
 
   fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
     let mut inner = self.inner.lock().unwrap();
-    match inner.generator_state.take().expect("polling future twice") {
-      State::Done(mut state_future) => {
-        match state_future.poll() {
-          Err(e) => Err(e),
-          Ok(Async::NotReady) => {
-            inner.generator_state = Some(State::Done(state_future));
-            Ok(Async::NotReady)
-          },
-          Ok(Async::Ready(state)) => Ok(Async::Ready(state))
+    loop {
+      match inner.generator_state.take().expect("polling future twice") {
+        State::Done(mut state_future) => {
+          return match state_future.poll() {
+            Err(e) => Err(e),
+            Ok(Async::NotReady) => {
+              inner.generator_state = Some(State::Done(state_future));
+              Ok(Async::NotReady)
+            },
+            Ok(Async::Ready(state)) => Ok(Async::Ready(state))
+          };
+        },
+        State::Error => {
+          inner.generator_state = Some(State::Error);
+          return Err(CompletionError::Failed.into());
+        },
+        State::Abandoned => {
+          inner.generator_state = Some(State::Abandoned);
+          return Err(CompletionError::Abandoned.into());
+        },
+        other => {
+          inner.generator_state = Some(other);
+          if !inner.dropped {
+            inner.task = Some(task::park());
+            return Ok(Async::NotReady);
+          }
+          // the stream was dropped before it ended, so skip the rest of it here.
+          match inner.skip_to_end() {
+            Err(e) => return Err(e),
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(())) => ()
+          }
         }
-      },
-      other => {
-        inner.task = Some(task::park());
-        inner.generator_state = Some(other);
-        Ok(Async::NotReady)
       }
     }
   }
@@ -1,33 +1,91 @@
+#[cfg(feature = "std")]
 use bytes::{Bytes};
-use std::error::Error;
-use std::fmt;
+use core::{fmt, str};
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::string::{String, ToString};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use std::io;
-use std::str;
+
+use error::{self, Error, ErrorKind};
 use zint;
 
 const KIND_BOOLEAN: u8 = 3;
 const KIND_NUMBER: u8 = 2;
+const KIND_BYTES: u8 = 1;
 const KIND_STRING: u8 = 0;
 
+/// Longest string or byte field that can be encoded (10 bits).
+pub const MAX_FIELD_LENGTH: usize = 1023;
+
+/// String fields with this id can hold an extended field.
+pub const EXTENDED_ID: u8 = 15;
+
+/// Longest name for an extended field.
+pub const MAX_EXTENDED_NAME_LENGTH: usize = 31;
+
 /// An unordered set of TLV fields, where:
-///   - type can be only boolean, unsigned int, or UTF-8 string
-///   - length (of strings) can't exceed 1023 bytes
+///   - type can be only boolean, unsigned int, UTF-8 string, or raw bytes
+///   - length (of strings or bytes) can't exceed 1023 bytes
 ///   - key is a small int, from 0 - 15, per type
 ///
+/// For more keys than that, an extended field is keyed by any number (up to
+/// 64 bits), or a short name. It's stored as a string field with id 15,
+/// holding:
+///   - one letter for the value's kind, `B`, `N`, `S`, or `X` (bytes),
+///     then `:`
+///   - the key: `#` and a decimal number, or a name, then `=`
+///   - the value: nothing for a boolean, a decimal number, the string
+///     itself, or the bytes in hex
+///
+/// Bytes fields are new in format version 0.1. Readers of version 0.0
+/// reject them as an unknown kind, so a header that has any can't be
+/// written as 0.0. Extended fields are only strings to those readers, so
+/// they're ignored, the same way a newer reader that doesn't know a
+/// particular extended key never asks for it. A string with id 15 that
+/// isn't laid out like this is just a string.
+///
 /// This is used to store metadata in the bottle header.
 pub struct Table {
   fields: Vec<Field>
 }
 
-enum FieldValue {
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
   Boolean,
   Number(u64),
-  String(String)
+  String(String),
+  Bytes(Vec<u8>),
+  Extended(ExtendedKey, Box<FieldValue>)
 }
 
-struct Field {
-  id: u8,
-  value: FieldValue,
+/// The key for an extended field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExtendedKey {
+  Number(u64),
+  Name(String)
+}
+
+impl From<u64> for ExtendedKey {
+  fn from(n: u64) -> ExtendedKey {
+    ExtendedKey::Number(n)
+  }
+}
+
+impl<'a> From<&'a str> for ExtendedKey {
+  fn from(name: &'a str) -> ExtendedKey {
+    ExtendedKey::Name(name.to_string())
+  }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Field {
+  pub id: u8,
+  pub value: FieldValue,
 }
 
 impl Table {
@@ -53,44 +111,127 @@ impl Table {
     self.fields.push(Field { id: id, value: FieldValue::String(value) });
   }
 
+  /// Add raw bytes, like a salt or a digest.
+  pub fn add_bytes(&mut self, id: u8, value: Vec<u8>) {
+    assert!(id <= 15);
+    assert!(value.len() <= MAX_FIELD_LENGTH);
+    self.fields.push(Field { id: id, value: FieldValue::Bytes(value) });
+  }
+
+  /// Add a field with an extended key. The value can be anything but
+  /// another extended field. A name can't start with `#` or contain `=`.
+  pub fn add_extended<K: Into<ExtendedKey>>(&mut self, key: K, value: FieldValue) {
+    let key = key.into();
+    if let ExtendedKey::Name(ref name) = key {
+      assert!(name.len() <= MAX_EXTENDED_NAME_LENGTH && !name.starts_with('#') && !name.contains('='));
+    }
+    let value = FieldValue::Extended(key, Box::new(value));
+    let mut content = Vec::new();
+    encode_value(&value, &mut content);
+    assert!(content.len() <= MAX_FIELD_LENGTH);
+    self.fields.push(Field { id: EXTENDED_ID, value });
+  }
+
+  /// Are there any bytes fields? (They need format version 0.1.)
+  pub fn has_bytes_fields(&self) -> bool {
+    self.fields.iter().any(|f| match f.value {
+      FieldValue::Bytes(_) => true,
+      _ => false
+    })
+  }
+
+  /// Is the boolean with this id set?
+  pub fn get_bool(&self, id: u8) -> bool {
+    self.fields.iter().any(|f| match f.value {
+      FieldValue::Boolean => f.id == id,
+      _ => false
+    })
+  }
+
+  /// Return the number with this id, if there is one.
+  pub fn get_number(&self, id: u8) -> Option<u64> {
+    self.fields.iter().filter_map(|f| match f.value {
+      FieldValue::Number(value) if f.id == id => Some(value),
+      _ => None
+    }).next()
+  }
+
+  /// Return the string with this id, if there is one.
+  pub fn get_string(&self, id: u8) -> Option<&str> {
+    self.fields.iter().filter_map(|f| match f.value {
+      FieldValue::String(ref value) if f.id == id => Some(value.as_ref()),
+      _ => None
+    }).next()
+  }
+
+  /// Return every string with this id, in order.
+  pub fn get_strings(&self, id: u8) -> Vec<&str> {
+    self.fields.iter().filter_map(|f| match f.value {
+      FieldValue::String(ref value) if f.id == id => Some(value.as_ref()),
+      _ => None
+    }).collect()
+  }
+
+  /// Return the bytes with this id, if there are any.
+  pub fn get_bytes(&self, id: u8) -> Option<&[u8]> {
+    self.fields.iter().filter_map(|f| match f.value {
+      FieldValue::Bytes(ref value) if f.id == id => Some(value.as_ref()),
+      _ => None
+    }).next()
+  }
+
+  /// Return the value of the extended field with this key, if there is one.
+  pub fn get_extended<K: Into<ExtendedKey>>(&self, key: K) -> Option<&FieldValue> {
+    let key = key.into();
+    self.fields.iter().filter_map(|f| match f.value {
+      FieldValue::Extended(ref k, ref value) if *k == key => Some(&**value),
+      _ => None
+    }).next()
+  }
+
+  #[cfg(feature = "std")]
   pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+    let mut buffer = Vec::new();
+    self.encode_into(&mut buffer);
+    writer.write_all(&buffer)
+  }
+
+  /// Append the encoded table to a buffer.
+  pub fn encode_into(&self, out: &mut Vec<u8>) {
     for ref f in &self.fields {
-      let content_length: usize = match f.value {
-        FieldValue::Boolean => 0,
-        FieldValue::Number(value) => zint::bytes_needed(value),
-        FieldValue::String(ref value) => value.len()
-      };
-      let kind: u8 = match f.value {
-        FieldValue::Boolean => KIND_BOOLEAN,
-        FieldValue::Number(_) => KIND_NUMBER,
-        FieldValue::String(_) => KIND_STRING
-      };
-      writer.write_all(&[
-        (kind << 6) | (f.id << 2) | (((content_length >> 8) & 0x2) as u8),
-        (content_length & 0xff) as u8
-      ])?;
-
-      // write content:
-      match f.value {
-        FieldValue::Boolean => (),
-        FieldValue::Number(value) => writer.write_all(zint::encode_packed_u64(value).as_ref())?,
-        FieldValue::String(ref value) => writer.write_all(value.as_ref())?
-      };
+      let mut content = Vec::new();
+      let kind = encode_value(&f.value, &mut content);
+      out.extend_from_slice(&[
+        (kind << 6) | (f.id << 2) | (((content.len() >> 8) & 0x3) as u8),
+        (content.len() & 0xff) as u8
+      ]);
+      out.extend_from_slice(&content);
     }
-    Ok(())
   }
 
+  #[cfg(feature = "std")]
   pub fn encode(&self) -> Bytes {
-    let mut cursor = io::Cursor::new(Vec::new());
-    // unwrap is ok cuz it can't really fail
-    self.write(&mut cursor).unwrap();
-    Bytes::from(cursor.into_inner())
+    let mut buffer = Vec::new();
+    self.encode_into(&mut buffer);
+    Bytes::from(buffer)
+  }
+
+  #[cfg(feature = "std")]
+  pub fn decode(buffer: Bytes) -> error::Result<Table> {
+    Table::decode_slice(&buffer)
   }
 
-  pub fn decode(buffer: Bytes) -> io::Result<Table> {
-    let mut table = Table::new();
+  pub fn decode_slice(buffer: &[u8]) -> error::Result<Table> {
+    Ok(Table { fields: Table::decode_fields(buffer)?.into_iter().map(|( _, field )| field).collect() })
+  }
+
+  /// Decode each field of an encoded table, along with the range of the
+  /// buffer it was decoded from.
+  pub fn decode_fields(buffer: &[u8]) -> error::Result<Vec<( Range<usize>, Field )>> {
+    let mut fields = Vec::new();
     let mut i: usize = 0;
     while i < buffer.len() {
+      let start = i;
       if i + 2 > buffer.len() { return Err(truncated_error()) }
       let kind = (buffer[i] & 0xc0) >> 6;
       let id = (buffer[i] & 0x3c) >> 2;
@@ -98,39 +239,161 @@ impl Table {
       i += 2;
       if i + length > buffer.len() { return Err(truncated_error()) }
 
-      let content = buffer.slice(i, i + length); //&buffer[i .. i + length];
-      let value = match kind {
-        KIND_BOOLEAN => FieldValue::Boolean,
-        KIND_NUMBER => FieldValue::Number(zint::decode_packed_u64(content)),
-        KIND_STRING => FieldValue::String(str::from_utf8(content.as_ref()).map_err(convert_error)?.to_string()),
-        _ => return Err(unknown_kind_error())
+      let content = &buffer[i .. i + length];
+      let extended = if kind == KIND_STRING && id == EXTENDED_ID { decode_extended(content) } else { None };
+      let value = match extended {
+        Some(value) => value,
+        None => decode_value(kind, content)?
       };
-      table.fields.push(Field { id: id, value: value });
       i += length;
+      fields.push(( start .. i, Field { id: id, value: value } ));
     }
-    Ok(table)
+    Ok(fields)
   }
 }
 
 impl fmt::Debug for Table {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "Table({})", self.fields.iter().map(|f| match f.value {
-      FieldValue::Boolean => format!("B{}", f.id),
-      FieldValue::Number(value) => format!("N{}={}", f.id, value),
-      FieldValue::String(ref value) => format!("S{}={:?}", f.id, value)
-    }).collect::<Vec<String>>().join(", "))
+    write!(f, "Table(")?;
+    for (i, field) in self.fields.iter().enumerate() {
+      write!(f, "{}{:?}", if i == 0 { "" } else { ", " }, field)?;
+    }
+    write!(f, ")")
+  }
+}
+
+impl fmt::Debug for Field {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.value {
+      FieldValue::Boolean => write!(f, "B{}", self.id),
+      FieldValue::Number(value) => write!(f, "N{}={}", self.id, value),
+      FieldValue::String(ref value) => write!(f, "S{}={:?}", self.id, value),
+      FieldValue::Bytes(ref value) => {
+        write!(f, "X{}=", self.id)?;
+        write_hex(f, value)
+      },
+      FieldValue::Extended(ref key, ref value) => {
+        match *key {
+          ExtendedKey::Number(n) => write!(f, "E(#{})=", n)?,
+          ExtendedKey::Name(ref name) => write!(f, "E({})=", name)?
+        };
+        match **value {
+          FieldValue::Boolean => write!(f, "B"),
+          FieldValue::Number(value) => write!(f, "N{}", value),
+          FieldValue::String(ref value) => write!(f, "S{:?}", value),
+          FieldValue::Bytes(ref value) => {
+            write!(f, "X")?;
+            write_hex(f, value)
+          },
+          FieldValue::Extended(_, _) => write!(f, "?")
+        }
+      }
+    }
   }
 }
 
+fn write_hex(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+  for b in data { write!(f, "{:02x}", b)? };
+  Ok(())
+}
+
+// append a value's content to a buffer, and return its kind.
+fn encode_value(value: &FieldValue, out: &mut Vec<u8>) -> u8 {
+  match *value {
+    FieldValue::Boolean => KIND_BOOLEAN,
+    FieldValue::Number(n) => {
+      zint::encode_packed_u64_into(n, out);
+      KIND_NUMBER
+    },
+    FieldValue::String(ref s) => {
+      out.extend_from_slice(s.as_ref());
+      KIND_STRING
+    },
+    FieldValue::Bytes(ref b) => {
+      out.extend_from_slice(b);
+      KIND_BYTES
+    },
+    FieldValue::Extended(ref key, ref value) => {
+      out.push(match **value {
+        FieldValue::Boolean => b'B',
+        FieldValue::Number(_) => b'N',
+        FieldValue::String(_) => b'S',
+        FieldValue::Bytes(_) => b'X',
+        FieldValue::Extended(_, _) => panic!("Extended fields can't be nested")
+      });
+      out.push(b':');
+      match *key {
+        ExtendedKey::Number(n) => out.extend_from_slice(format!("#{}", n).as_bytes()),
+        ExtendedKey::Name(ref name) => out.extend_from_slice(name.as_bytes())
+      };
+      out.push(b'=');
+      match **value {
+        FieldValue::Number(n) => out.extend_from_slice(format!("{}", n).as_bytes()),
+        FieldValue::String(ref s) => out.extend_from_slice(s.as_bytes()),
+        FieldValue::Bytes(ref b) => for x in b { out.extend_from_slice(format!("{:02x}", x).as_bytes()) },
+        _ => ()
+      };
+      KIND_STRING
+    }
+  }
+}
+
+fn decode_value(kind: u8, content: &[u8]) -> error::Result<FieldValue> {
+  Ok(match kind {
+    KIND_BOOLEAN => FieldValue::Boolean,
+    KIND_NUMBER => {
+      if content.len() > 8 { return Err(long_number_error()) }
+      FieldValue::Number(zint::decode_packed_u64(content))
+    },
+    KIND_STRING => FieldValue::String(str::from_utf8(content).map_err(convert_error)?.to_string()),
+    KIND_BYTES => FieldValue::Bytes(content.to_vec()),
+    _ => return Err(unknown_kind_error())
+  })
+}
+
+// a string field that isn't laid out like an extended field is `None`.
+fn decode_extended(content: &[u8]) -> Option<FieldValue> {
+  let text = str::from_utf8(content).ok()?;
+  // a `:` second means the first letter is ASCII, so it's safe to split.
+  if text.len() < 2 || text.as_bytes()[1] != b':' { return None }
+  let ( kind, text ) = ( &text[0 .. 1], &text[2 ..] );
+  let split = text.find('=')?;
+  let ( key, value ) = ( &text[.. split], &text[split + 1 ..] );
+
+  let key = if key.as_bytes().first() == Some(&b'#') {
+    ExtendedKey::Number(key[1 ..].parse().ok()?)
+  } else {
+    ExtendedKey::Name(key.to_string())
+  };
+  let value = match kind {
+    "B" if value.is_empty() => FieldValue::Boolean,
+    "N" => FieldValue::Number(value.parse().ok()?),
+    "S" => FieldValue::String(value.to_string()),
+    "X" => FieldValue::Bytes(decode_hex(value)?),
+    _ => return None
+  };
+  Some(FieldValue::Extended(key, Box::new(value)))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+  let digit = |c: u8| (c as char).to_digit(16);
+  // an odd digit at the end has no pair, so it's not hex.
+  text.as_bytes().chunks(2).map(|pair| Some((digit(pair[0])? << 4 | digit(*pair.get(1)?)?) as u8)).collect()
+}
+
 // convert a UTF-8 decoding error into a normal I/O error
-fn convert_error(e: str::Utf8Error) -> io::Error {
-  io::Error::new(io::ErrorKind::InvalidInput, e.description())
+fn convert_error(e: str::Utf8Error) -> Error {
+  Error::new(ErrorKind::InvalidInput, format!("{}", e))
+}
+
+fn truncated_error() -> Error {
+  Error::new(ErrorKind::UnexpectedEof, "Truncated header table")
 }
 
-fn truncated_error() -> io::Error {
-  io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated header table")
+fn long_number_error() -> Error {
+  Error::new(ErrorKind::InvalidData, "Number field is too long")
 }
 
-fn unknown_kind_error() -> io::Error {
-  io::Error::new(io::ErrorKind::InvalidInput, "Unknown field kind")
+fn unknown_kind_error() -> Error {
+  Error::new(ErrorKind::InvalidInput, "Unknown field kind")
 }
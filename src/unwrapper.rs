@@ -0,0 +1,150 @@
+use futures::{Future, future, Stream, stream};
+use std::io;
+use std::sync::Arc;
+
+use bottle::{Bottle, read_bottle_with_limits};
+use compressed_bottle::{Codec, codec_id, read_compressed_bottle};
+use encrypted_bottle::{cipher_id, KeyProvider, read_encrypted_bottle, recipients};
+use hashed_bottle::{DigestCheck, HashType, hash_type, read_hashed_bottle, signer, SignerStatus};
+use header::BottleType;
+use reader_limits::{Limiter, ReaderLimits};
+use stream_toolkit::{BoxedByteStream, BoxedIoFuture, ByteStream, ReadableByteStream};
+
+/// A bottle whose stream types have been erased, because they depend on
+/// how many layers were peeled off to reach it.
+pub type BoxedBottle = Bottle<Box<Stream<Item = BoxedByteStream, Error = io::Error>>>;
+
+/// A layer that was peeled off, from the outside in.
+#[derive(Clone, Debug)]
+pub enum LayerReport {
+  /// The digest isn't checked until the data inside has been read.
+  Hashed { hash_type: HashType, digest: DigestCheck, signer: SignerStatus },
+  Encrypted { cipher: String, recipients: Vec<String> },
+  Compressed { codec: String }
+}
+
+/// Reads a bottle, peeling off each hashed, encrypted, or compressed layer
+/// until it reaches a bottle of any other type (usually a file or folder
+/// bottle), which it returns along with a report of the layers it found.
+///
+/// Codecs and keys aren't built in, so decompressing or decrypting only
+/// works with the codecs and key provider given.
+///
+/// Warning: data from inside a hashed layer is streamed to the caller
+/// before its digest has been checked. A bad digest only shows up as an
+/// error at the end of the innermost bottle's streams, so don't trust
+/// (or act on) any of the data until they've ended without one.
+#[derive(Clone, Default)]
+pub struct Unwrapper {
+  codecs: Vec<Arc<Codec>>,
+  keys: Option<Arc<KeyProvider>>
+}
+
+impl Unwrapper {
+  pub fn new() -> Unwrapper {
+    Unwrapper::default()
+  }
+
+  pub fn with_codec(mut self, codec: Arc<Codec>) -> Unwrapper {
+    self.codecs.push(codec);
+    self
+  }
+
+  pub fn with_keys(self, keys: Arc<KeyProvider>) -> Unwrapper {
+    Unwrapper { keys: Some(keys), ..self }
+  }
+
+  /// Peel the layers off a bottle. The data is not verified yet: if there
+  /// was a hashed layer, its digest is only checked once the returned
+  /// bottle's streams have been read to the end, and a mismatch fails the
+  /// last stream. Until then, the data may have been tampered with, and
+  /// each `LayerReport::Hashed` digest is `DigestStatus::Unchecked`.
+  ///
+  /// Every layer is held to the default `ReaderLimits`.
+  pub fn read<S>(&self, s: ReadableByteStream<S>) -> BoxedIoFuture<( BoxedBottle, Vec<LayerReport> )>
+    where S: ByteStream + 'static
+  {
+    self.read_with_limits(s, Limiter::new(ReaderLimits::default()))
+  }
+
+  /// Read with no limits at all. Only use this for data you trust.
+  pub fn read_unlimited<S>(&self, s: ReadableByteStream<S>) -> BoxedIoFuture<( BoxedBottle, Vec<LayerReport> )>
+    where S: ByteStream + 'static
+  {
+    self.read_with_limits(s, Limiter::unlimited())
+  }
+
+  /// Read, failing with a `LimitExceeded` error if the bottle (including
+  /// every layer) goes over any of the limits.
+  pub fn read_with_limits<S>(&self, s: ReadableByteStream<S>, limiter: Limiter)
+    -> BoxedIoFuture<( BoxedBottle, Vec<LayerReport> )>
+    where S: ByteStream + 'static
+  {
+    let unwrapper = self.clone();
+    Box::new(read_bottle_with_limits(s, limiter).and_then(move |(bottle, _)| {
+      match unwrapper.peel(bottle, Box::new(future::ok(())), Vec::new()) {
+        Ok(future) => future,
+        Err(e) => Box::new(future::err(e))
+      }
+    }))
+  }
+
+  // `finish` reads whatever is left of each layer peeled off so far, so
+  // that hashes are checked after the innermost bottle has been read.
+  fn peel<S>(self, bottle: Bottle<S>, finish: BoxedIoFuture<()>, mut layers: Vec<LayerReport>)
+    -> io::Result<BoxedIoFuture<( BoxedBottle, Vec<LayerReport> )>>
+    where
+      S: Stream<Error = io::Error> + 'static,
+      S::Item: ByteStream + 'static,
+  {
+    // decrypted or decompressed data wasn't counted by this layer's frames.
+    let mut limiter = bottle.limiter.clone();
+    let data: BoxedByteStream = match bottle.header.bottle_type {
+      BottleType::Hashed => {
+        let hash_type = hash_type(&bottle.header.table)?;
+        let signer = signer(&bottle.header.table);
+        let ( data, digest ) = read_hashed_bottle(bottle);
+        layers.push(LayerReport::Hashed { hash_type, digest, signer });
+        Box::new(data)
+      },
+      BottleType::Encrypted => {
+        let id = cipher_id(&bottle.header.table)?;
+        let recipients = recipients(&bottle.header.table);
+        let cipher = self.keys.as_ref().ok_or_else(|| unwrap_error("No key provider for an encrypted bottle"))?.cipher(id, &recipients)?;
+        layers.push(LayerReport::Encrypted { cipher: cipher.name().to_string(), recipients });
+        limiter = limiter.decoded();
+        Box::new(read_encrypted_bottle(bottle, cipher))
+      },
+      BottleType::Compressed => {
+        let id = codec_id(&bottle.header.table)?;
+        let codec = self.codecs.iter().find(|codec| codec.id() == id).cloned().ok_or_else(|| {
+          unwrap_error(&format!("No codec for a compressed bottle: {}", id))
+        })?;
+        layers.push(LayerReport::Compressed { codec: codec.name().to_string() });
+        limiter = limiter.decoded();
+        Box::new(read_compressed_bottle(bottle, codec))
+      },
+      _ => {
+        let finish = finish.map(|_| stream::empty()).flatten_stream();
+        let streams: Box<Stream<Item = BoxedByteStream, Error = io::Error>> = Box::new(bottle.streams.map(|s| {
+          Box::new(s) as BoxedByteStream
+        }).chain(finish));
+        let bottle = Bottle { header: bottle.header, streams, limiter: bottle.limiter };
+        return Ok(Box::new(future::ok(( bottle, layers ))));
+      }
+    };
+
+    // the next layer is nested inside this one's data.
+    Ok(Box::new(read_bottle_with_limits(ReadableByteStream::from(data), limiter).and_then(move |(bottle, rest)| {
+      let rest = rest.and_then(|s| s.into_stream().for_each(|_| Ok(())));
+      match self.peel(bottle, Box::new(rest.and_then(|_| finish)), layers) {
+        Ok(future) => future,
+        Err(e) => Box::new(future::err(e))
+      }
+    })))
+  }
+}
+
+fn unwrap_error(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,169 @@
+use futures::{Future, Stream, stream};
+use std::{fs, io};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use stream_toolkit::{ByteStream, ReaderByteStream};
+
+// "📀", to distinguish a volume from a bottle (🍼).
+static VOLUME_MAGIC: [u8; 4] = [ 0xf0, 0x9f, 0x93, 0x80 ];
+const VOLUME_VERSION: u8 = 0;
+const FLAG_LAST: u8 = 1;
+
+/// Size of the header at the start of each volume:
+///   - magic (4 bytes)
+///   - version (1 byte)
+///   - flags (1 byte): bit 0 is set on the last volume
+///   - reserved (2 bytes)
+///   - volume number, starting at 0 (u32, LSB)
+///   - archive id, shared by every volume of the same archive (u64, LSB)
+pub const VOLUME_HEADER_SIZE: usize = 20;
+
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Filename for volume `index` (from 0) of an archive: `<base>.001`, etc.
+pub fn volume_path(base: &Path, index: u32) -> PathBuf {
+  let mut name = base.as_os_str().to_os_string();
+  name.push(format!(".{:03}", index + 1));
+  PathBuf::from(name)
+}
+
+/// Writes one logical byte stream across a numbered sequence of files, each
+/// no larger than `max_size` (including its header).
+pub struct VolumeWriter {
+  base: PathBuf,
+  max_size: u64,
+  archive_id: u64,
+  paths: Vec<PathBuf>,
+  current: Option<fs::File>,
+  current_size: u64
+}
+
+impl VolumeWriter {
+  pub fn new(base: &Path, max_size: u64) -> VolumeWriter {
+    assert!(max_size > VOLUME_HEADER_SIZE as u64);
+    VolumeWriter {
+      base: base.to_path_buf(),
+      max_size,
+      archive_id: new_archive_id(),
+      paths: Vec::new(),
+      current: None,
+      current_size: 0
+    }
+  }
+
+  pub fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+    while data.len() > 0 {
+      if self.current.is_none() || self.current_size >= self.max_size { self.next_volume()?; }
+      let n = ::std::cmp::min(data.len() as u64, self.max_size - self.current_size) as usize;
+      self.current.as_mut().unwrap().write_all(&data[0 .. n])?;
+      self.current_size += n as u64;
+      data = &data[n ..];
+    }
+    Ok(())
+  }
+
+  /// Mark the final volume as the last one, and return the list of volume
+  /// files written.
+  pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+    if self.current.is_none() { self.next_volume()?; }
+    let mut file = self.current.take().unwrap();
+    // the flags byte comes right after the magic and version:
+    file.seek(SeekFrom::Start(5))?;
+    file.write_all(&[ FLAG_LAST ])?;
+    file.sync_all()?;
+    Ok(self.paths)
+  }
+
+  fn next_volume(&mut self) -> io::Result<()> {
+    if let Some(file) = self.current.take() { file.sync_all()?; }
+    let index = self.paths.len() as u32;
+    let path = volume_path(&self.base, index);
+    let mut file = fs::File::create(&path)?;
+    file.write_all(&encode_volume_header(index, self.archive_id, 0))?;
+    self.paths.push(path);
+    self.current = Some(file);
+    self.current_size = VOLUME_HEADER_SIZE as u64;
+    Ok(())
+  }
+}
+
+/// Write a byte stream (like an encoded bottle) across volumes named
+/// `<base>.001`, `<base>.002`, and so on, each at most `max_size` bytes.
+/// Returns the list of volumes written.
+pub fn write_volumes<S>(s: S, base: &Path, max_size: u64) -> impl Future<Item = Vec<PathBuf>, Error = io::Error>
+  where S: ByteStream
+{
+  let writer = VolumeWriter::new(base, max_size);
+  s.fold(writer, |mut writer, data| writer.write(&data).map(|_| writer)).and_then(|writer| writer.finish())
+}
+
+/// Read a list of volumes, in order, as one seamless byte stream. Each
+/// volume's header is checked to make sure they're all from the same
+/// archive, in the right order, with none missing.
+pub fn read_volumes(paths: Vec<PathBuf>) -> impl ByteStream {
+  let count = paths.len();
+  let mut archive_id: Option<u64> = None;
+  let volumes = stream::iter(paths.into_iter().enumerate().map(Ok));
+  volumes.and_then(move |(index, path)| {
+    let mut file = fs::File::open(&path)?;
+    let mut buffer = [ 0u8; VOLUME_HEADER_SIZE ];
+    file.read_exact(&mut buffer)?;
+    let header = decode_volume_header(&buffer)?;
+    if header.index != index as u32 { return Err(bad_volume_error(&path, "out of order")) }
+    if *archive_id.get_or_insert(header.archive_id) != header.archive_id {
+      return Err(bad_volume_error(&path, "from a different archive"));
+    }
+    let is_last = (header.flags & FLAG_LAST) != 0;
+    if is_last && index + 1 < count { return Err(bad_volume_error(&path, "marked as the last volume")) }
+    if !is_last && index + 1 == count { return Err(bad_volume_error(&path, "missing volumes after this one")) }
+    Ok(ReaderByteStream::new(file, BLOCK_SIZE))
+  }).flatten()
+}
+
+struct VolumeHeader {
+  flags: u8,
+  index: u32,
+  archive_id: u64
+}
+
+fn encode_volume_header(index: u32, archive_id: u64, flags: u8) -> [u8; VOLUME_HEADER_SIZE] {
+  let mut buffer = [ 0u8; VOLUME_HEADER_SIZE ];
+  buffer[0 .. 4].copy_from_slice(&VOLUME_MAGIC);
+  buffer[4] = VOLUME_VERSION;
+  buffer[5] = flags;
+  for i in 0 .. 4 { buffer[8 + i] = (index >> (8 * i)) as u8 };
+  for i in 0 .. 8 { buffer[12 + i] = (archive_id >> (8 * i)) as u8 };
+  buffer
+}
+
+fn decode_volume_header(buffer: &[u8]) -> io::Result<VolumeHeader> {
+  if buffer[0 .. 4] != VOLUME_MAGIC { return Err(bad_volume_magic_error()) }
+  if buffer[4] != VOLUME_VERSION { return Err(bad_volume_version_error(buffer[4])) }
+  let index = (0 .. 4).fold(0u32, |n, i| n | ((buffer[8 + i] as u32) << (8 * i)));
+  let archive_id = (0 .. 8).fold(0u64, |n, i| n | ((buffer[12 + i] as u64) << (8 * i)));
+  Ok(VolumeHeader { flags: buffer[5], index, archive_id })
+}
+
+// no rand crate, but `RandomState` is seeded randomly per process.
+fn new_archive_id() -> u64 {
+  let mut hasher = RandomState::new().build_hasher();
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+  hasher.write_u32(now);
+  hasher.finish()
+}
+
+fn bad_volume_magic_error() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, "Incorrect magic (not a 4bottle volume)")
+}
+
+fn bad_volume_version_error(version: u8) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Incompatible volume version: {}", version))
+}
+
+fn bad_volume_error(path: &Path, reason: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, format!("Bad volume {:?}: {}", path, reason))
+}
@@ -1,10 +1,14 @@
+#[cfg(feature = "std")]
 use bytes::{Bytes};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
 
 pub const END_OF_STREAM: u8 = 0;
 pub const END_OF_BOTTLE: u8 = 0xff;
 pub const END_OF_STREAM_ARRAY: [u8; 1] = [ END_OF_STREAM ];
 pub const END_OF_BOTTLE_ARRAY: [u8; 1] = [ END_OF_BOTTLE ];
 
+#[cfg(feature = "std")]
 lazy_static! {
   pub static ref END_OF_STREAM_BYTES: Bytes = Bytes::from(&END_OF_STREAM_ARRAY[..]);
   pub static ref END_OF_BOTTLE_BYTES: Bytes = Bytes::from(&END_OF_BOTTLE_ARRAY[..]);
@@ -12,7 +16,15 @@ lazy_static! {
 
 /// Encode a u64 as 1 - 8 bytes packed, LSB, with buffer length passed
 /// out-of-band.
+#[cfg(feature = "std")]
 pub fn encode_packed_u64(number: u64) -> Bytes {
+  let mut buffer = Vec::with_capacity(8);
+  encode_packed_u64_into(number, &mut buffer);
+  Bytes::from(buffer)
+}
+
+/// Append the packed encoding of a u64 to a buffer.
+pub fn encode_packed_u64_into(number: u64, out: &mut Vec<u8>) {
   let mut index = 0;
   let mut buffer: [u8; 8] = [ 0; 8 ];
   let mut n = number;
@@ -24,14 +36,14 @@ pub fn encode_packed_u64(number: u64) -> Bytes {
   }
   buffer[index] = (n & 0xff) as u8;
   index += 1;
-  Bytes::from(&buffer[0 .. index])
+  out.extend_from_slice(&buffer[0 .. index]);
 }
 
 /// Decode a packed u64 back into a u64.
-pub fn decode_packed_u64(buffer: Bytes) -> u64 {
+pub fn decode_packed_u64<B: AsRef<[u8]>>(buffer: B) -> u64 {
   let mut rv: u64 = 0;
   let mut shift: u8 = 0;
-  for b in buffer.iter() {
+  for b in buffer.as_ref().iter() {
     rv += (*b as u64) << shift;
     shift += 8;
   }
@@ -54,6 +66,9 @@ pub fn bytes_needed(mut number: u64) -> usize {
 
 // ----- frame length
 
+/// Largest length that can be encoded for a frame (22 bits).
+pub const MAX_FRAME_LENGTH: usize = (1 << 22) - 1;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum FrameLength {
   EndOfStream,
@@ -63,9 +78,17 @@ pub enum FrameLength {
 
 /// Encode a u32 length as 1 to 3 bytes, using the top 2 bits to track how
 /// many additional bytes were needed.
+#[cfg(feature = "std")]
 pub fn encode_length(number: usize) -> Bytes {
+  let mut buffer = Vec::with_capacity(3);
+  encode_length_into(number, &mut buffer);
+  Bytes::from(buffer)
+}
+
+/// Append the encoding of a frame length to a buffer.
+pub fn encode_length_into(number: usize, out: &mut Vec<u8>) {
   assert!(number > 0);
-  assert!(number < (1 << 22));
+  assert!(number <= MAX_FRAME_LENGTH);
   let mut index = 3;
   let mut buffer: [u8; 3] = [ 0; 3 ];
   let mut n = number;
@@ -76,7 +99,7 @@ pub fn encode_length(number: usize) -> Bytes {
     n >>= 8;
   }
   buffer[index] = buffer[index] | ((2 - index) << 6) as u8;
-  Bytes::from(&buffer[index ..])
+  out.extend_from_slice(&buffer[index ..]);
 }
 
 /// Decode the first byte of a u32 length into a count of additional bytes,
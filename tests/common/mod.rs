@@ -0,0 +1,89 @@
+// helpers shared by the tests that need a folder, a lot of data, small
+// archives, or stand-in codecs and ciphers. not every test uses all of them.
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use futures::{Future, Stream, stream};
+use lib4bottle::compressed_bottle::Codec;
+use lib4bottle::encrypted_bottle::Cipher;
+use lib4bottle::file_bottle::{FileMetadata, write_file_bottle, write_folder_bottle};
+use lib4bottle::stream_toolkit::{BoxedByteStream, ByteFrame, stream_of};
+use std::{env, fs};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+
+static NEXT_FOLDER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+  // `RandomState` is seeded randomly per process, so separate test runs
+  // (even at the same time) get separate folders.
+  static ref RUN_ID: u64 = RandomState::new().build_hasher().finish();
+}
+
+/// A new, empty folder that no other test is using.
+pub fn temp_folder(name: &str) -> PathBuf {
+  let n = NEXT_FOLDER.fetch_add(1, Ordering::SeqCst);
+  let path = env::temp_dir().join(format!("lib4bottle-test-{:016x}-{}-{}", *RUN_ID, n, name));
+  let _ = fs::remove_dir_all(&path);
+  fs::create_dir_all(&path).unwrap();
+  path
+}
+
+/// Deterministic "random" data, so chunk boundaries are repeatable.
+pub fn noise(count: usize, seed: u32) -> Vec<u8> {
+  let mut n = seed;
+  (0 .. count).map(|_| {
+    n = n.wrapping_mul(1103515245).wrapping_add(12345);
+    (n >> 16) as u8
+  }).collect()
+}
+
+/// An encoded file bottle holding `data`.
+pub fn file_bottle(metadata: &FileMetadata, data: Bytes) -> BoxedByteStream {
+  Box::new(write_file_bottle(metadata, stream_of(data)).encode())
+}
+
+/// An encoded file bottle, with its size filled in.
+pub fn file(name: &str, data: &'static [u8]) -> BoxedByteStream {
+  let mut metadata = FileMetadata::new(name.to_string());
+  metadata.size = data.len() as u64;
+  file_bottle(&metadata, Bytes::from_static(data))
+}
+
+/// An encoded folder bottle holding each of `entries`.
+pub fn folder(name: &str, entries: Vec<BoxedByteStream>) -> BoxedByteStream {
+  let mut metadata = FileMetadata::new(name.to_string());
+  metadata.is_folder = true;
+  Box::new(write_folder_bottle(&metadata, stream::iter_ok(entries)).encode())
+}
+
+// stand-ins that just flip bits, so each layer's data is different.
+pub struct Invert;
+
+impl Codec for Invert {
+  fn id(&self) -> u64 { 9 }
+  fn name(&self) -> &str { "invert" }
+  fn compress(&self, data: BoxedByteStream) -> BoxedByteStream { xor(data, 0xff) }
+  fn decompress(&self, data: BoxedByteStream) -> BoxedByteStream { xor(data, 0xff) }
+}
+
+pub struct Xor;
+
+impl Cipher for Xor {
+  fn id(&self) -> u64 { 7 }
+  fn name(&self) -> &str { "xor" }
+  fn recipients(&self) -> Vec<String> { vec![ "alice".to_string(), "bob".to_string() ] }
+  fn encrypt(&self, data: BoxedByteStream) -> BoxedByteStream { xor(data, 0x55) }
+  fn decrypt(&self, data: BoxedByteStream) -> BoxedByteStream { xor(data, 0x55) }
+}
+
+pub fn xor(data: BoxedByteStream, key: u8) -> BoxedByteStream {
+  Box::new(data.map(move |b| Bytes::from(b.iter().map(|x| x ^ key).collect::<Vec<u8>>())))
+}
+
+/// Collect a whole byte stream into one buffer.
+pub fn pack(s: BoxedByteStream) -> Bytes {
+  ByteFrame::from(s.collect().wait().unwrap()).pack()
+}
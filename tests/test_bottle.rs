@@ -6,12 +6,21 @@ extern crate lib4bottle;
 mod test_bottle {
   use bytes::{Bytes};
   use futures::{Future, Stream, stream};
-  use lib4bottle::bottle::{Bottle, read_bottle, read_framed_stream, write_framed_stream};
+  use lib4bottle::bottle::{
+    Bottle,
+    FramingPolicy,
+    read_bottle,
+    read_frame_length,
+    read_framed_stream,
+    write_framed_stream,
+    write_framed_stream_with_policy
+  };
   use lib4bottle::header::{BottleType};
   use lib4bottle::stream_toolkit::{
-    ReadableByteStream, FromHex, stream_of, stream_of_hex, stream_of_streams, stream_of_vec, ToHex
+    CompletionError, ReadableByteStream, FromHex, stream_of, stream_of_hex, stream_of_streams, stream_of_vec, ToHex
   };
   use lib4bottle::table::Table;
+  use lib4bottle::zint::FrameLength;
   use std::io;
 
   static MAGIC_HEX: &str = "f09f8dbc0000";
@@ -37,6 +46,32 @@ mod test_bottle {
     assert_eq!(b.collect().wait().unwrap().to_hex(), "0c68656c6c6f207361696c6f7200");
   }
 
+  #[test]
+  fn split_big_frames() {
+    let s = stream_of_vec(vec![ Bytes::from_static(b"hello"), Bytes::from_static(b" sailor") ]);
+    let b = write_framed_stream_with_policy(s, FramingPolicy::new(1024, 5));
+    assert_eq!(b.collect().wait().unwrap().to_hex(), "0568656c6c6f05207361696c026f7200");
+
+    let s = stream_of_vec(vec![ Bytes::from_static(b"he"), Bytes::from_static(b"llo"), Bytes::from_static(b"!") ]);
+    let b = write_framed_stream_with_policy(s, FramingPolicy::new(2, 5));
+    assert_eq!(b.collect().wait().unwrap().to_hex(), "026865036c6c6f012100");
+  }
+
+  #[test]
+  fn write_a_frame_over_the_maximum_size() {
+    let data = Bytes::from(vec![ 7u8; (1 << 22) + 10 ]);
+    let framed = write_framed_stream(stream_of(data.clone())).collect().wait().unwrap();
+    // 3 bytes of length, a full frame, then one byte of length for the rest:
+    assert_eq!(framed.len(), 5);
+    assert_eq!(framed[0].to_hex(), "bfffff");
+    assert_eq!(framed[2].to_hex(), "0b");
+    assert_eq!(framed[4].to_hex(), "00");
+    let frames = ReadableByteStream::from(stream_of_vec(framed));
+    let (stream, _) = read_framed_stream(frames).wait().unwrap();
+    let contents = stream.unwrap().into_stream().collect().wait().unwrap();
+    assert_eq!(contents.iter().map(|b| b.len()).sum::<usize>(), data.len());
+  }
+
   #[test]
   fn write_a_small_bottle() {
     let mut t = Table::new();
@@ -75,6 +110,22 @@ mod test_bottle {
     );
   }
 
+  #[test]
+  fn read_frame_lengths() {
+    let s = stream_of_hex("04403ebd043a00ff");
+    let (length, s) = read_frame_length(s).wait().unwrap();
+    assert_eq!(length, FrameLength::Length(4));
+    let (length, s) = read_frame_length(s).wait().unwrap();
+    assert_eq!(length, FrameLength::Length(0x3e));
+    let (length, s) = read_frame_length(s).wait().unwrap();
+    assert_eq!(length, FrameLength::Length(3998778));
+    let (length, s) = read_frame_length(s).wait().unwrap();
+    assert_eq!(length, FrameLength::EndOfStream);
+    let (length, s) = read_frame_length(s).wait().unwrap();
+    assert_eq!(length, FrameLength::EndOfBottle);
+    assert!(read_frame_length(s).wait().is_err());
+  }
+
   #[test]
   fn read_a_data_block() {
     let data1 = stream_of_hex("0568656c6c6f00ff");
@@ -110,6 +161,29 @@ mod test_bottle {
     assert_eq!(data4.into_stream().collect().wait().unwrap().to_hex(), "ff");
   }
 
+  #[test]
+  fn skip_a_dropped_stream() {
+    let data1 = ReadableByteStream::from(stream_of(Bytes::from("03f0f0f0026865016c00e0ff".from_hex())));
+    let (stream, future) = read_framed_stream(data1).wait().unwrap();
+    let (item, stream) = stream.unwrap().into_stream().into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(item.unwrap().to_hex(), "f0f0f0");
+    drop(stream);
+    assert_eq!(future.wait().unwrap().into_stream().collect().wait().unwrap().to_hex(), "e0ff");
+  }
+
+  #[test]
+  fn skip_unread_streams_in_a_bottle() {
+    let data = stream_of_hex(&format!("{}a000036361740003646f670003656c6b00ff", MAGIC_HEX)[..]);
+    let (bottle, end_stream) = read_bottle(data).wait().unwrap();
+    let (_, streams) = bottle.streams.into_future().wait().map_err(|_| ()).unwrap();
+    let (_, streams) = streams.into_future().wait().map_err(|_| ()).unwrap();
+    let (item, streams) = streams.into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(item.unwrap().collect().wait().unwrap().to_hex(), "656c6b");
+    let (item, _) = streams.into_future().wait().map_err(|_| ()).unwrap();
+    assert!(item.is_none());
+    assert_eq!(end_stream.wait().map_err(|_| ()).unwrap().into_stream().collect().wait().unwrap().to_hex(), "");
+  }
+
   #[test]
   fn read_a_bottle() {
     let data1 = stream_of_hex(&format!("{}a0000363617400ff", MAGIC_HEX)[..]);
@@ -154,4 +228,14 @@ mod test_bottle {
     let data3 = end_stream.wait().map_err(|_| ()).unwrap();
     assert_eq!(data3.into_stream().collect().wait().unwrap().to_hex(), "");
   }
+
+  #[test]
+  fn fail_the_remainder_when_a_bottle_is_truncated() {
+    let data = stream_of_hex(&format!("{}a000056865", MAGIC_HEX)[..]);
+    let (bottle, end_stream) = read_bottle(data).wait().unwrap();
+    assert!(bottle.streams.flatten().collect().wait().is_err());
+    // the remainder fails instead of waiting forever:
+    let e = end_stream.wait().unwrap_err();
+    assert!(e.get_ref().unwrap().is::<CompletionError>());
+  }
 }
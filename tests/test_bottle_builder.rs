@@ -0,0 +1,77 @@
+#![type_length_limit="4194304"]
+
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+extern crate sha2;
+
+mod common;
+
+#[cfg(test)]
+mod test_bottle_builder {
+  use bytes::Bytes;
+  use futures::{Future, Stream};
+  use lib4bottle::bottle::read_bottle;
+  use lib4bottle::bottle_builder::{BottleBuilder, Layer};
+  use lib4bottle::compressed_bottle::Codec;
+  use lib4bottle::encrypted_bottle::Cipher;
+  use lib4bottle::file_bottle::FileMetadata;
+  use lib4bottle::hashed_bottle::HashType;
+  use lib4bottle::header::BottleType;
+  use lib4bottle::stream_toolkit::{ByteFrame, ReadableByteStream, stream_of, ToHex};
+  use common::{Invert, pack, Xor};
+  use sha2::{Digest, Sha256};
+  use std::sync::Arc;
+
+  // read a bottle, returning its header (as a string) and each stream.
+  fn unwrap(data: Bytes) -> ( BottleType, String, Vec<Bytes> ) {
+    let (bottle, _) = read_bottle(ReadableByteStream::from(stream_of(data))).wait().unwrap();
+    let header = format!("{:?}", bottle.header.table);
+    let streams = bottle.streams.and_then(|s| s.collect()).map(|buffers| ByteFrame::from(buffers).pack());
+    ( bottle.header.bottle_type, header, streams.collect().wait().unwrap() )
+  }
+
+  #[test]
+  fn build_layers() {
+    let builder = BottleBuilder::file(&FileMetadata::new("file.txt".to_string()), stream_of(Bytes::from("hello")))
+      .compress(Arc::new(Invert))
+      .encrypt(Arc::new(Xor))
+      .hash(HashType::Sha256);
+    assert_eq!(builder.layers(), &[ Layer::Compressed(9), Layer::Encrypted(7), Layer::Hashed(HashType::Sha256) ]);
+
+    let ( bottle_type, table, streams ) = unwrap(pack(builder.encode()));
+    assert_eq!(bottle_type, BottleType::Hashed);
+    assert_eq!(table, "Table(N0=0)");
+    assert_eq!(streams.len(), 2);
+    let mut hasher = Sha256::default();
+    hasher.input(&streams[0]);
+    assert_eq!(streams[1].to_hex(), hasher.result().as_slice().to_hex());
+
+    let ( bottle_type, table, streams ) = unwrap(streams[0].clone());
+    assert_eq!(bottle_type, BottleType::Encrypted);
+    assert_eq!(table, "Table(N0=7, S0=\"alice\", S0=\"bob\")");
+    let decrypted = pack(Xor.decrypt(Box::new(stream_of(streams[0].clone()))));
+
+    let ( bottle_type, table, streams ) = unwrap(decrypted);
+    assert_eq!(bottle_type, BottleType::Compressed);
+    assert_eq!(table, "Table(N0=9)");
+    let decompressed = pack(Invert.decompress(Box::new(stream_of(streams[0].clone()))));
+
+    let ( bottle_type, _, streams ) = unwrap(decompressed);
+    assert_eq!(bottle_type, BottleType::File);
+    assert_eq!(streams, vec![ Bytes::from("hello") ]);
+  }
+
+  #[test]
+  fn reject_compressing_encrypted_data() {
+    let builder = BottleBuilder::bottle(stream_of(Bytes::from("x")))
+      .encrypt(Arc::new(Xor))
+      .hash(HashType::Sha256)
+      .compress(Arc::new(Invert));
+    assert_eq!(builder.layers(), &[ Layer::Encrypted(7), Layer::Hashed(HashType::Sha256) ]);
+    let e = builder.encode().collect().wait().unwrap_err();
+    assert_eq!(format!("{}", e), "Can't compress data that's already encrypted");
+  }
+}
@@ -0,0 +1,117 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_bottle_kind {
+  use bytes::{Bytes};
+  use futures::{Future, Stream};
+  use lib4bottle::bottle::{Bottle, read_bottle, read_bottle_with_limits};
+  use lib4bottle::bottle_kind::{FieldKind, FieldSpec, BottleKind, Registry};
+  use lib4bottle::header::{BottleType};
+  use lib4bottle::reader_limits::Limiter;
+  use lib4bottle::stream_toolkit::{FromHex, ReadableByteStream, stream_of, stream_of_hex, stream_of_streams};
+  use lib4bottle::table::Table;
+  use std::io;
+  use std::sync::Arc;
+
+  static MAGIC_HEX: &str = "f09f8dbc0000";
+
+  struct Photo {
+    id: u8
+  }
+
+  impl BottleKind for Photo {
+    fn id(&self) -> u8 {
+      self.id
+    }
+
+    fn name(&self) -> &str {
+      "Photo"
+    }
+
+    fn schema(&self) -> Vec<FieldSpec> {
+      vec![
+        FieldSpec { name: "width", kind: FieldKind::Number, id: 0, required: true },
+        FieldSpec { name: "caption", kind: FieldKind::String, id: 0, required: false }
+      ]
+    }
+  }
+
+  fn photo_registry(id: u8) -> Arc<Registry> {
+    let mut registry = Registry::new();
+    registry.register(Arc::new(Photo { id })).unwrap();
+    Arc::new(registry)
+  }
+
+  fn encode_photo(id: u8, table: Table, registry: Arc<Registry>) -> io::Result<Vec<u8>> {
+    let data = stream_of(Bytes::from("f0f0".from_hex()));
+    let b = Bottle::new(BottleType::Other(id), table, stream_of_streams(vec![ data ])).with_registry(registry);
+    b.encode().collect().wait().map(|buffers| buffers.iter().fold(Vec::new(), |mut v, b| { v.extend_from_slice(b); v }))
+  }
+
+  #[test]
+  fn registered_kind_round_trips() {
+    let registry = photo_registry(12);
+    assert_eq!(registry.bottle_type_name(&BottleType::Other(12)), Some("Photo".to_string()));
+
+    let mut table = Table::new();
+    table.add_number(0, 640);
+    let data = encode_photo(12, table, registry.clone()).unwrap();
+    let limiter = Limiter::unlimited().with_registry(registry);
+    let (bottle, _) = read_bottle_with_limits(ReadableByteStream::from(stream_of(Bytes::from(data))), limiter).wait().unwrap();
+    assert_eq!(bottle.header.bottle_type, BottleType::Other(12));
+    assert_eq!(bottle.header.table.get_number(0), Some(640));
+  }
+
+  #[test]
+  fn schema_is_checked() {
+    let registry = photo_registry(13);
+    let e = encode_photo(13, Table::new(), registry.clone()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(format!("{}", e), "Missing width field in Photo bottle header");
+
+    let limiter = Limiter::unlimited().with_registry(registry);
+    let e = read_bottle_with_limits(stream_of_hex(&format!("{}d000ff", MAGIC_HEX)), limiter).wait().err().unwrap();
+    assert_eq!(format!("{}", e), "Missing width field in Photo bottle header");
+  }
+
+  #[test]
+  fn registries_are_separate() {
+    let registry = photo_registry(13);
+    assert_eq!(Registry::new().bottle_type_name(&BottleType::Other(13)), None);
+    assert!(encode_photo(13, Table::new(), Arc::new(Registry::new())).is_ok());
+    assert!(read_bottle(stream_of_hex(&format!("{}d000ff", MAGIC_HEX))).wait().is_ok());
+    assert!(encode_photo(13, Table::new(), registry).is_err());
+  }
+
+  #[test]
+  fn reject_type_ids_out_of_range() {
+    let e = encode_photo(16, Table::new(), Arc::new(Registry::new())).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(format!("{}", e), "Bottle type 16 is out of range (0 - 15)");
+  }
+
+  #[test]
+  fn unknown_types_are_raw() {
+    let registry = photo_registry(12);
+    assert_eq!(registry.bottle_type_name(&BottleType::Other(9)), None);
+    assert_eq!(registry.bottle_type_name(&BottleType::File), Some("File".to_string()));
+    let (bottle, _) = read_bottle(stream_of_hex(&format!("{}9000ff", MAGIC_HEX))).wait().unwrap();
+    assert_eq!(bottle.header.bottle_type, BottleType::Other(9));
+    assert_eq!(bottle.streams.collect().wait().unwrap().len(), 0);
+  }
+
+  #[test]
+  fn reject_bad_registrations() {
+    let mut registry = Registry::new();
+    let e = registry.register(Arc::new(Photo { id: 10 })).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(format!("{}", e), "Photo can't use type id 10");
+    assert!(registry.register(Arc::new(Photo { id: 16 })).is_err());
+
+    registry.register(Arc::new(Photo { id: 14 })).unwrap();
+    let e = registry.register(Arc::new(Photo { id: 14 })).unwrap_err();
+    assert_eq!(format!("{}", e), "Photo can't use type id 14: it's already Photo");
+  }
+}
@@ -0,0 +1,70 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_bottle_sink {
+  use bytes::{Bytes};
+  use futures::{Future, Sink, stream};
+  use lib4bottle::bottle::{FramingPolicy};
+  use lib4bottle::bottle_sink::{BottleSink, BottleSinkItem};
+  use lib4bottle::header::{BottleType};
+  use lib4bottle::stream_toolkit::{FromHex, ToHex};
+  use lib4bottle::table::Table;
+  use std::io;
+
+  static MAGIC_HEX: &str = "f09f8dbc0000";
+
+  fn write_items(items: Vec<BottleSinkItem>, policy: FramingPolicy) -> io::Result<String> {
+    let sink = Vec::new().sink_map_err(|_| io::Error::new(io::ErrorKind::Other, "vec"));
+    let bottle_sink = BottleSink::with_policy(BottleType::Test, Table::new(), sink, policy);
+    // `send_all` closes the sink when it's done.
+    bottle_sink.send_all(stream::iter_ok::<_, io::Error>(items)).wait().map(|(sink, _)| {
+      sink.into_inner().into_inner().to_hex()
+    })
+  }
+
+  fn data(hex: &str) -> BottleSinkItem {
+    BottleSinkItem::Data(Bytes::from(hex.from_hex()))
+  }
+
+  #[test]
+  fn write_a_bottle_of_several_streams() {
+    let items = vec![
+      BottleSinkItem::StartStream, data("f0f0f0"), BottleSinkItem::EndStream,
+      BottleSinkItem::StartStream, data("e0"), data("e0e0"), BottleSinkItem::EndStream,
+      BottleSinkItem::StartStream, BottleSinkItem::EndStream
+    ];
+    assert_eq!(
+      write_items(items, FramingPolicy::default()).unwrap(),
+      format!("{}a00003f0f0f00003e0e0e00000ff", MAGIC_HEX)
+    );
+  }
+
+  #[test]
+  fn frame_with_the_policy() {
+    let items = vec![ BottleSinkItem::StartStream, data("01"), data("0203"), data("040506"), BottleSinkItem::EndStream ];
+    assert_eq!(
+      write_items(items, FramingPolicy::new(2, 2)).unwrap(),
+      format!("{}a0000201020103020405010600ff", MAGIC_HEX)
+    );
+  }
+
+  #[test]
+  fn reject_data_outside_a_stream() {
+    assert!(write_items(vec![ data("01") ], FramingPolicy::default()).is_err());
+    assert!(write_items(vec![ BottleSinkItem::StartStream ], FramingPolicy::default()).is_err());
+  }
+
+  #[test]
+  fn reject_type_ids_out_of_range() {
+    let sink = Vec::new().sink_map_err(|_| io::Error::new(io::ErrorKind::Other, "vec"));
+    let mut bottle_sink = BottleSink::new(BottleType::Other(16), Table::new(), sink);
+    for _ in 0 .. 2 {
+      let e = bottle_sink.start_send(BottleSinkItem::StartStream).err().unwrap();
+      assert_eq!(format!("{}", e), "Bottle type 16 is out of range (0 - 15)");
+    }
+    assert!(bottle_sink.close().is_err());
+    assert_eq!(bottle_sink.into_inner().into_inner().len(), 0);
+  }
+}
@@ -5,7 +5,7 @@ extern crate lib4bottle;
 #[cfg(test)]
 mod test_buffered_stream {
   use bytes::Bytes;
-  use lib4bottle::stream_toolkit::{BufferedByteStream, stream_of_vec, stream_to_string_vec};
+  use lib4bottle::stream_toolkit::{BufferedByteStream, MemoryLimit, stream_of_vec, stream_to_string_vec};
 
   #[test]
   fn combine_small_buffers() {
@@ -40,4 +40,16 @@ mod test_buffered_stream {
     let b = BufferedByteStream::new(s, 5, true);
     assert_eq!(stream_to_string_vec(b.pack()), vec![ "hello", "kitty", "howar", "eyou!" ]);
   }
+
+  #[test]
+  fn splits_big_buffers_at_the_memory_limit() {
+    let s = stream_of_vec(vec![
+      Bytes::from_static(b"hell"),
+      Bytes::from_static(b"okittyhowareyou!")
+    ]);
+    let limit = MemoryLimit::new(8);
+    let b = BufferedByteStream::with_memory_limit(s, 5, false, limit.clone());
+    assert_eq!(stream_to_string_vec(b.pack()), vec![ "hellokit", "tyhoware", "you!" ]);
+    assert!(limit.peak_buffered() <= limit.max_bytes());
+  }
 }
@@ -0,0 +1,78 @@
+#![type_length_limit="4194304"]
+
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+
+mod common;
+
+#[cfg(test)]
+mod test_chunked_bottle {
+  use bytes::Bytes;
+  use futures::{Future, Stream};
+  use lib4bottle::bottle::read_bottle;
+  use lib4bottle::chunk_store::{ChunkId, ChunkStore};
+  use lib4bottle::chunked_bottle::{read_chunked_bottle, write_chunked_bottle};
+  use lib4bottle::header::BottleType;
+  use lib4bottle::stream_toolkit::{ByteFrame, ChunkSizes, ReadableByteStream, stream_of, stream_of_vec, ToHex};
+  use common::{noise, temp_folder};
+  use std::fs;
+  use std::io::Write;
+  use std::path::PathBuf;
+  use std::sync::Arc;
+
+  fn count_files(path: &PathBuf) -> usize {
+    fs::read_dir(path).unwrap().map(|entry| fs::read_dir(entry.unwrap().path()).unwrap().count()).sum()
+  }
+
+  #[test]
+  fn store_and_fetch_chunks() {
+    let path = temp_folder("store");
+    let store = ChunkStore::open(&path).unwrap();
+    let frame = ByteFrame::from(vec![ Bytes::from_static(b"hello "), Bytes::from_static(b"sailor") ]);
+    let id = store.put(&frame).unwrap();
+    assert_eq!(id, ChunkId::of(&ByteFrame::from(Bytes::from_static(b"hello sailor"))));
+    assert!(store.contains(&id));
+    assert_eq!(store.get(&id).unwrap(), Bytes::from_static(b"hello sailor"));
+
+    // storing it again doesn't make a new file:
+    assert_eq!(store.put(&frame).unwrap(), id);
+    assert_eq!(count_files(&path), 1);
+  }
+
+  #[test]
+  #[should_panic(expected = "Corrupted chunk")]
+  fn detect_corrupted_chunk() {
+    let path = temp_folder("corrupt");
+    let store = ChunkStore::open(&path).unwrap();
+    let id = store.put(&ByteFrame::from(Bytes::from_static(b"hello"))).unwrap();
+    let hex = id.0.to_hex();
+    fs::File::create(path.join(&hex[0 .. 2]).join(&hex[2 ..])).unwrap().write_all(b"jello").unwrap();
+    store.get(&id).unwrap();
+  }
+
+  #[test]
+  fn round_trip_and_dedupe() {
+    let path = temp_folder("round-trip");
+    let store = Arc::new(ChunkStore::open(&path).unwrap());
+    let sizes = ChunkSizes::new(256, 1024, 4096);
+    let data = noise(30000, 7);
+
+    let encoded = write_chunked_bottle(stream_of(Bytes::from(data.clone())), store.clone(), sizes).encode();
+    let encoded = encoded.collect().wait().unwrap();
+    let chunk_count = count_files(&path);
+
+    let (bottle, _) = read_bottle(ReadableByteStream::from(stream_of_vec(encoded))).wait().unwrap();
+    assert_eq!(bottle.header.bottle_type, BottleType::Chunked);
+    let restored = read_chunked_bottle(bottle, store.clone()).collect().wait().unwrap();
+    assert_eq!(ByteFrame::from(restored).pack(), Bytes::from(data.clone()));
+
+    // a second backup with a small edit only adds a few new chunks.
+    let mut edited = data.clone();
+    edited[20000] ^= 0xff;
+    write_chunked_bottle(stream_of(Bytes::from(edited)), store.clone(), sizes).encode().collect().wait().unwrap();
+    assert!(count_files(&path) <= chunk_count + 2);
+  }
+}
@@ -0,0 +1,76 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+
+mod common;
+
+#[cfg(test)]
+mod test_chunked_byte_stream {
+  use bytes::Bytes;
+  use common::noise;
+  use futures::{Future, Stream};
+  use lib4bottle::stream_toolkit::{ChunkedByteStream, ChunkSizes, stream_of, stream_of_vec};
+
+  fn chunk_lengths(data: Vec<u8>, sizes: ChunkSizes) -> Vec<usize> {
+    let s = ChunkedByteStream::new(stream_of(Bytes::from(data)), sizes);
+    s.collect().wait().unwrap().iter().map(|frame| frame.length).collect()
+  }
+
+  #[test]
+  fn small_stream_is_one_chunk() {
+    let s = ChunkedByteStream::new(stream_of(Bytes::from_static(b"hello")), ChunkSizes::default());
+    let frames = s.collect().wait().unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].pack(), Bytes::from_static(b"hello"));
+  }
+
+  #[test]
+  fn empty_stream_has_no_chunks() {
+    let s = ChunkedByteStream::new(stream_of_vec(vec![]), ChunkSizes::default());
+    assert_eq!(s.collect().wait().unwrap().len(), 0);
+  }
+
+  #[test]
+  fn respects_size_limits() {
+    let sizes = ChunkSizes::new(256, 1024, 4096);
+    let lengths = chunk_lengths(noise(100000, 1), sizes);
+    assert_eq!(lengths.iter().sum::<usize>(), 100000);
+    for &n in &lengths[.. lengths.len() - 1] {
+      assert!(n >= 256 && n <= 4096);
+    }
+    // normalized chunking keeps the average close to the target:
+    let average = 100000 / lengths.len();
+    assert!(average > 512 && average < 2048);
+  }
+
+  #[test]
+  fn zeros_are_cut_at_maximum() {
+    let sizes = ChunkSizes::new(64, 256, 1000);
+    assert_eq!(chunk_lengths(vec![ 0; 2500 ], sizes), vec![ 1000, 1000, 500 ]);
+  }
+
+  #[test]
+  fn boundaries_ignore_buffer_sizes() {
+    let sizes = ChunkSizes::new(256, 1024, 4096);
+    let data = noise(20000, 2);
+    let pieces = data.chunks(77).map(|c| Bytes::from(c)).collect::<Vec<Bytes>>();
+    let s = ChunkedByteStream::new(stream_of_vec(pieces), sizes);
+    let lengths = s.collect().wait().unwrap().iter().map(|frame| frame.length).collect::<Vec<usize>>();
+    assert_eq!(lengths, chunk_lengths(data, sizes));
+  }
+
+  #[test]
+  fn insert_only_disturbs_nearby_chunks() {
+    let sizes = ChunkSizes::new(256, 1024, 4096);
+    let data = noise(50000, 3);
+    let mut edited = data.clone();
+    for b in b"an insert near the front".iter().rev() { edited.insert(1000, *b) };
+
+    let before = chunk_lengths(data, sizes);
+    let after = chunk_lengths(edited, sizes);
+    let shared = before.iter().rev().zip(after.iter().rev()).take_while(|&(a, b)| a == b).count();
+    assert!(shared >= before.len() - 3);
+  }
+}
@@ -0,0 +1,145 @@
+extern crate bytes;
+extern crate futures;
+extern crate iovec;
+extern crate lib4bottle;
+extern crate tokio_io;
+
+#[cfg(test)]
+mod test_drain_into_writer {
+  use bytes::{Buf, Bytes};
+  use futures::{Async, Future, Poll, task};
+  use iovec::IoVec;
+  use lib4bottle::bottle::{write_framed_stream};
+  use lib4bottle::stream_toolkit::{drain_into_async_writer, drain_into_writer, stream_of_vec, ToHex};
+  use std::io;
+  use std::io::Write;
+  use tokio_io::AsyncWrite;
+
+  // remember each write call, and only take `limit` bytes at a time. if
+  // `stalls`, each write would block the first time it's tried.
+  struct RecordingWriter {
+    data: Vec<u8>,
+    writes: usize,
+    limit: usize,
+    stalls: bool,
+    stalled: bool
+  }
+
+  impl RecordingWriter {
+    fn new(limit: usize, stalls: bool) -> RecordingWriter {
+      RecordingWriter { data: Vec::new(), writes: 0, limit, stalls, stalled: false }
+    }
+  }
+
+  impl Write for RecordingWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+      if self.stalls && !self.stalled {
+        self.stalled = true;
+        task::current().notify();
+        return Err(io::Error::new(io::ErrorKind::WouldBlock, "stall"));
+      }
+      self.stalled = false;
+      self.writes += 1;
+      let count = ::std::cmp::min(buffer.len(), self.limit);
+      self.data.extend_from_slice(&buffer[.. count]);
+      Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl AsyncWrite for RecordingWriter {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+      Ok(().into())
+    }
+  }
+
+  // a writer that supports vectored writes, remembering how many buffers
+  // each one gathered.
+  struct GatheringWriter {
+    data: Vec<u8>,
+    gathered: Vec<usize>
+  }
+
+  impl Write for GatheringWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+      self.gathered.push(1);
+      self.data.extend_from_slice(buffer);
+      Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl AsyncWrite for GatheringWriter {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+      Ok(().into())
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+      let count = {
+        let filler = [ 0u8 ];
+        let mut vecs: [&IoVec; 16] = [ (&filler[..]).into(); 16 ];
+        let n = buf.bytes_vec(&mut vecs);
+        self.gathered.push(n);
+        let before = self.data.len();
+        for v in &vecs[.. n] { self.data.extend_from_slice(v) }
+        self.data.len() - before
+      };
+      buf.advance(count);
+      Ok(Async::Ready(count))
+    }
+  }
+
+  #[test]
+  fn write_a_framed_stream_in_one_call() {
+    let s = write_framed_stream(stream_of_vec(vec![ Bytes::from_static(b"hello"), Bytes::from_static(b"sailor") ]));
+    let writer = drain_into_writer(s, RecordingWriter::new(1024, false)).wait().unwrap();
+    assert_eq!(writer.data.to_hex(), "0b68656c6c6f7361696c6f7200");
+    assert_eq!(writer.writes, 1);
+  }
+
+  #[test]
+  fn finish_partial_writes() {
+    let s = write_framed_stream(stream_of_vec(vec![ Bytes::from_static(b"hello"), Bytes::from_static(b"sailor") ]));
+    let writer = drain_into_writer(s, RecordingWriter::new(4, false)).wait().unwrap();
+    assert_eq!(writer.data.to_hex(), "0b68656c6c6f7361696c6f7200");
+    assert_eq!(writer.writes, 4);
+  }
+
+  #[test]
+  fn wait_for_an_async_writer() {
+    let s = write_framed_stream(stream_of_vec(vec![ Bytes::from_static(b"hello"), Bytes::from_static(b"sailor") ]));
+    let writer = drain_into_async_writer(s, RecordingWriter::new(4, true)).wait().unwrap();
+    assert_eq!(writer.data.to_hex(), "0b68656c6c6f7361696c6f7200");
+    // without vectored writes, each buffer goes out by itself.
+    assert_eq!(writer.writes, 6);
+
+    // a blocking writer isn't allowed to stall.
+    let s = write_framed_stream(stream_of_vec(vec![ Bytes::from_static(b"hello") ]));
+    let e = drain_into_writer(s, RecordingWriter::new(4, true)).wait().err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+  }
+
+  #[test]
+  fn gather_buffers_into_a_vectored_write() {
+    let s = write_framed_stream(stream_of_vec(vec![ Bytes::from_static(b"hello"), Bytes::from_static(b"sailor") ]));
+    let writer = drain_into_async_writer(s, GatheringWriter { data: Vec::new(), gathered: Vec::new() }).wait().unwrap();
+    assert_eq!(writer.data.to_hex(), "0b68656c6c6f7361696c6f7200");
+    assert_eq!(writer.gathered.len(), 1);
+    assert!(writer.gathered[0] > 1);
+  }
+
+  #[test]
+  fn write_big_buffers_alone() {
+    let big = Bytes::from(vec![ 1u8; 100 * 1024 ]);
+    let s = stream_of_vec(vec![ Bytes::from_static(b"hi"), big.clone(), Bytes::from_static(b"there") ]);
+    let writer = drain_into_writer(s, RecordingWriter::new(1024 * 1024, false)).wait().unwrap();
+    assert_eq!(writer.data.len(), 100 * 1024 + 7);
+    assert_eq!(writer.writes, 3);
+  }
+}
@@ -0,0 +1,107 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_dump {
+  use bytes::{Bytes};
+  use futures::{Future, stream};
+  use lib4bottle::bottle::{Bottle, FramingPolicy};
+  use lib4bottle::dump::{dump, dump_stream};
+  use lib4bottle::header::{BottleType};
+  use lib4bottle::reader_limits::is_limit_exceeded;
+  use lib4bottle::stream_toolkit::{FromHex, stream_of, stream_of_hex, stream_of_streams, stream_of_vec};
+  use lib4bottle::table::Table;
+  use std::io;
+
+  static MAGIC_HEX: &str = "f09f8dbc0000";
+
+  #[test]
+  fn dump_a_small_bottle() {
+    let mut t = Table::new();
+    t.add_number(0, 150);
+    let data = stream_of(Bytes::from("f0f0f0".from_hex()));
+    let b = Bottle::new(BottleType::Test, t, stream_of_streams(vec![ data ]));
+    let d = dump_stream(b.encode_with_policy(FramingPolicy::new(1, 2))).wait().unwrap();
+    assert_eq!(
+      d.to_json(),
+      "{\"offset\":0,\"type\":\"Test\",\"fields\":[{\"offset\":8,\"id\":0,\"number\":150}],\"streams\":[\
+        {\"offset\":11,\"length\":3,\"frames\":[{\"offset\":11,\"length\":2},{\"offset\":14,\"length\":1}]}]}"
+    );
+    assert_eq!(d.to_hex_dump(), vec![
+      "00000000  f0 9f 8d bc                                      magic",
+      "00000004  00 00                                            version 0.0",
+      "00000006  a0 03                                            type Test, table: 3 bytes",
+      "00000008  80 01 96                                         field N0=150",
+      "0000000b  02                                               frame: 2 bytes",
+      "0000000c  f0 f0                                              data",
+      "0000000e  01                                               frame: 1 bytes",
+      "0000000f  f0                                                 data (continued)",
+      "00000010  00                                               end of stream",
+      "00000011  ff                                               end of bottle",
+      ""
+    ].join("\n"));
+  }
+
+  #[test]
+  fn dump_a_nested_bottle() {
+    let empty_stream = stream::empty::<stream::Empty<Bytes, io::Error>, io::Error>();
+    let b1 = Bottle::new(BottleType::Test, Table::new(), empty_stream);
+    let b2 = Bottle::new(BottleType::Test2, Table::new(), stream_of_streams(vec![ b1.encode() ]));
+    let d = dump_stream(b2.encode_with_policy(FramingPolicy::new(1024, 3))).wait().unwrap();
+    let inner = d.bottle.streams[0].bottle.as_ref().unwrap();
+    assert_eq!(inner.offset, 9);
+    assert_eq!(inner.streams.len(), 0);
+    assert_eq!(d.bottle.streams[0].frames.iter().map(|f| ( f.offset, f.length )).collect::<Vec<_>>(), vec![
+      ( 8, 3 ), ( 12, 3 ), ( 16, 3 )
+    ]);
+    // the inner bottle's header is split across frames of the outer stream:
+    assert_eq!(
+      d.lines.iter().map(|line| format!("{}:{}", line.offset, line.meaning)).collect::<Vec<String>>(),
+      vec![
+        "0:magic", "4:version 0.0", "6:type Test2, table: 0 bytes",
+        "8:frame: 3 bytes", "9:magic",
+        "12:frame: 3 bytes", "13:magic (continued)", "14:version 0.0",
+        "16:frame: 3 bytes", "17:type Test, table: 0 bytes", "19:end of bottle",
+        "20:end of stream", "21:end of bottle"
+      ]
+    );
+  }
+
+  #[test]
+  fn fail_on_a_truncated_bottle() {
+    let data = stream_of_hex(&format!("{}a00003f0f0", MAGIC_HEX)).into_stream();
+    assert_eq!(dump_stream(data).wait().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    assert!(dump(&"f09f8dbc0000".from_hex()).is_err());
+  }
+
+  #[test]
+  fn dump_as_it_arrives() {
+    // a nested bottle, followed by more data in the same stream:
+    let data = format!("{}b00003f09f8d09bc0000a00001aa00ff03fe000000ff", MAGIC_HEX).as_str().from_hex();
+    let whole = dump(&data).unwrap();
+    let chunks = data.iter().map(|&b| Bytes::from(vec![ b ])).collect::<Vec<Bytes>>();
+    let pieces = dump_stream(stream_of_vec(chunks)).wait().unwrap();
+    assert_eq!(pieces.to_hex_dump(), whole.to_hex_dump());
+    assert_eq!(pieces.to_json(), whole.to_json());
+    assert_eq!(
+      whole.lines.iter().skip(12).map(|line| format!("{}:{}", line.offset, line.meaning)).collect::<Vec<String>>(),
+      vec![ "21:end of bottle", "22:frame: 3 bytes", "23:data after bottle", "26:end of stream", "27:end of bottle" ]
+    );
+  }
+
+  #[test]
+  fn limit_nesting() {
+    let mut data = format!("{}a000ff", MAGIC_HEX).as_str().from_hex();
+    for _ in 0 .. 40 {
+      let mut outer = format!("{}b000", MAGIC_HEX).as_str().from_hex();
+      // three-byte frame lengths, so every level fits:
+      let n = data.len();
+      outer.extend_from_slice(&[ 0x80, (n >> 8) as u8, n as u8 ]);
+      outer.extend_from_slice(&data);
+      outer.extend_from_slice(&[ 0, 0xff ]);
+      data = outer;
+    }
+    assert!(is_limit_exceeded(&dump(&data).unwrap_err()));
+  }
+}
@@ -0,0 +1,107 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_encoder {
+  use bytes::{Bytes};
+  use futures::{Future, Stream};
+  use lib4bottle::bottle::{Bottle, FramingPolicy};
+  use lib4bottle::encoder::{BottleEncoder};
+  use lib4bottle::header::{BottleType, Header};
+  use lib4bottle::parser::{BottleParser, ParseEvent};
+  use lib4bottle::stream_toolkit::{FromHex, stream_of_streams, stream_of_vec, ToHex};
+  use lib4bottle::table::Table;
+
+  static MAGIC_HEX: &str = "f09f8dbc0000";
+
+  #[test]
+  fn encode_a_bottle() {
+    let mut t = Table::new();
+    t.add_number(0, 150);
+    let mut out = Vec::new();
+    let mut e = BottleEncoder::new();
+    e.begin_bottle(&Header::new(BottleType::Test, t), &mut out).unwrap();
+    e.begin_stream().unwrap();
+    e.write(&"f0f0".from_hex(), &mut out).unwrap();
+    e.write(&"f0".from_hex(), &mut out).unwrap();
+    e.end_stream(&mut out).unwrap();
+    e.begin_stream().unwrap();
+    e.end_stream(&mut out).unwrap();
+    e.end_bottle(&mut out).unwrap();
+    assert_eq!(e.depth(), 0);
+    assert_eq!(out.to_hex(), format!("{}a00380019603f0f0f00000ff", MAGIC_HEX));
+  }
+
+  #[test]
+  fn match_the_stream_encoder() {
+    let policy = FramingPolicy::new(3, 4);
+    let data = vec![ "01", "0203", "04", "05060708090a" ];
+
+    let streams = stream_of_streams(vec![
+      stream_of_vec(data.iter().map(|hex| Bytes::from(hex.from_hex())).collect::<Vec<Bytes>>())
+    ]);
+    let expected = Bottle::new(BottleType::Test, Table::new(), streams).encode_with_policy(policy);
+
+    let mut out = Vec::new();
+    let mut e = BottleEncoder::with_policy(policy);
+    e.begin_bottle(&Header::new(BottleType::Test, Table::new()), &mut out).unwrap();
+    e.begin_stream().unwrap();
+    for hex in data { e.write(&hex.from_hex(), &mut out).unwrap() };
+    e.end_stream(&mut out).unwrap();
+    e.end_bottle(&mut out).unwrap();
+    assert_eq!(out.to_hex(), expected.collect().wait().unwrap().to_hex());
+  }
+
+  #[test]
+  fn encode_nested_bottles() {
+    let mut out = Vec::new();
+    let mut e = BottleEncoder::with_policy(FramingPolicy::new(1, 5));
+    e.begin_bottle(&Header::new(BottleType::Test2, Table::new()), &mut out).unwrap();
+    e.begin_stream().unwrap();
+    e.begin_bottle(&Header::new(BottleType::Test, Table::new()), &mut out).unwrap();
+    assert_eq!(e.depth(), 2);
+    e.begin_stream().unwrap();
+    e.write(&"aa".from_hex(), &mut out).unwrap();
+    e.end_stream(&mut out).unwrap();
+    e.end_bottle(&mut out).unwrap();
+    e.end_stream(&mut out).unwrap();
+    e.end_bottle(&mut out).unwrap();
+
+    let mut parser = BottleParser::with_nesting(|header| header.bottle_type == BottleType::Test2);
+    let mut events = Vec::new();
+    assert_eq!(parser.feed(&out, |depth, event| {
+      events.push(match event {
+        ParseEvent::BottleStart(header) => format!("{}: start {:?}", depth, header.bottle_type),
+        ParseEvent::Data(data) => format!("{}: {}", depth, data.to_hex()),
+        event => format!("{}: {:?}", depth, event)
+      });
+    }).unwrap(), out.len());
+    assert_eq!(events, vec![
+      "0: start Test2", "0: StreamStart",
+      "1: start Test", "1: StreamStart", "1: aa", "1: StreamEnd", "1: BottleEnd",
+      "0: StreamEnd", "0: BottleEnd"
+    ]);
+  }
+
+  #[test]
+  fn reject_calls_out_of_order() {
+    let mut out = Vec::new();
+    let mut e = BottleEncoder::new();
+    assert!(e.begin_stream().is_err());
+    e.begin_bottle(&Header::new(BottleType::Test, Table::new()), &mut out).unwrap();
+    assert!(e.write(&[ 1 ], &mut out).is_err());
+    assert!(e.begin_bottle(&Header::new(BottleType::Test, Table::new()), &mut out).is_err());
+    e.begin_stream().unwrap();
+    assert!(e.begin_stream().is_err());
+    assert!(e.end_bottle(&mut out).is_err());
+  }
+
+  #[test]
+  fn reject_type_ids_out_of_range() {
+    let mut out = Vec::new();
+    let e = BottleEncoder::new().begin_bottle(&Header::new(BottleType::Other(16), Table::new()), &mut out).unwrap_err();
+    assert_eq!(format!("{}", e), "Bottle type 16 is out of range (0 - 15)");
+    assert_eq!(out.len(), 0);
+  }
+}
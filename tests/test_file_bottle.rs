@@ -0,0 +1,64 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+
+mod common;
+
+#[cfg(test)]
+mod test_file_bottle {
+  use futures::{Future, Stream, stream};
+  use lib4bottle::file_bottle::{FileMetadata, read_file_entries};
+  use lib4bottle::stream_toolkit::{BoxedByteStream, ReadableByteStream, ToHex};
+  use common::{file, folder};
+
+  // read every entry, returning "path=contents" (or "path/" for folders).
+  fn list(s: BoxedByteStream) -> Vec<String> {
+    let entries = read_file_entries(ReadableByteStream::from(s)).and_then(|entry| {
+      let path = entry.path;
+      let data: BoxedByteStream = entry.data.unwrap_or_else(|| Box::new(stream::empty()));
+      let is_folder = entry.metadata.is_folder;
+      data.collect().map(move |data| {
+        if is_folder { format!("{}/", path) } else { format!("{}={}", path, data.to_hex()) }
+      })
+    });
+    entries.collect().wait().unwrap()
+  }
+
+  #[test]
+  fn metadata_round_trip() {
+    let mut metadata = FileMetadata::new("cat.jpg".to_string());
+    metadata.mime_type = Some("image/jpeg".to_string());
+    metadata.size = 23;
+    metadata.posix_mode = Some(0o644);
+    metadata.modified_nanos = Some(1500000000000000000);
+    let table = metadata.to_table();
+    assert_eq!(
+      format!("{:?}", table),
+      "Table(S0=\"cat.jpg\", S1=\"image/jpeg\", N0=23, N1=420, N3=1500000000000000000)"
+    );
+    assert_eq!(FileMetadata::from_table(&table).unwrap(), metadata);
+  }
+
+  #[test]
+  fn read_a_file() {
+    assert_eq!(list(file("hello.txt", b"hello")), vec![ "hello.txt=68656c6c6f" ]);
+  }
+
+  #[test]
+  fn read_nested_folders() {
+    let s = folder("top", vec![
+      file("a", b"\x01"),
+      folder("sub", vec![ file("b", b"\x02\x02"), folder("empty", vec![]) ]),
+      file("c", b"\x03")
+    ]);
+    assert_eq!(list(s), vec![ "top/", "top/a=01", "top/sub/", "top/sub/b=0202", "top/sub/empty/", "top/c=03" ]);
+  }
+
+  #[test]
+  #[should_panic(expected = "Invalid filename")]
+  fn refuse_unsafe_filenames() {
+    list(folder("top", vec![ file("..", b"\x01") ]));
+  }
+}
@@ -7,9 +7,10 @@ extern crate lib4bottle;
 #[cfg(test)]
 mod test_header {
   use futures::{Future, Stream};
-  use lib4bottle::header::{BottleType, Header};
+  use lib4bottle::header::{BottleType, Header, Version, FIRST_VERSION, VERSION};
   use lib4bottle::stream_toolkit::{stream_of_hex, ToHex};
-  use lib4bottle::table::Table;
+  use lib4bottle::table::{FieldValue, Table};
+  use std::io;
 
   static MAGIC_HEX: &str = "f09f8dbc0000";
 
@@ -21,6 +22,19 @@ mod test_header {
     assert_eq!(b.encode().collect().wait().unwrap().to_hex(), format!("{}a003800196", MAGIC_HEX));
   }
 
+  #[test]
+  fn write_header_into_a_buffer() {
+    let mut t = Table::new();
+    t.add_number(0, 150);
+    let mut buffer = vec![ 0xee ];
+    Header::new(BottleType::Test, t).encode_into(&mut buffer).unwrap();
+    assert_eq!(buffer.to_hex(), format!("ee{}a003800196", MAGIC_HEX));
+    let ( version, bottle_type, table_length ) = Header::decode_prefix(&buffer[1 ..]).unwrap();
+    assert_eq!(version, FIRST_VERSION);
+    assert_eq!(bottle_type, BottleType::Test);
+    assert_eq!(format!("{:?}", Table::decode_slice(&buffer[9 .. 9 + table_length]).unwrap()), "Table(N0=150)");
+  }
+
   #[test]
   #[should_panic(expected = "UnexpectedEof")]
   fn validate_header_length() {
@@ -40,15 +54,73 @@ mod test_header {
   }
 
   #[test]
-  #[should_panic(expected = "Incompatible version")]
-  fn validate_header_flags() {
-    Header::decode(stream_of_hex("f09f8dbc00ff0000")).wait().unwrap();
+  fn read_newer_minor_version() {
+    // a newer minor version may have fields this reader doesn't know about.
+    let (header, _) = Header::decode(stream_of_hex("f09f8dbc0003a007800196b8021234")).wait().unwrap();
+    assert_eq!(header.version, Version { major: 0, minor: 3 });
+    assert_eq!(header.table.get_number(0), Some(150));
+    assert_eq!(format!("{:?}", header), "Header(Test, Table(N0=150, N14=13330))");
+  }
+
+  #[test]
+  fn decode_a_short_prefix() {
+    let e = Header::decode_prefix(&[ 0xf0, 0x9f, 0x8d, 0xbc ]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+  }
+
+  #[test]
+  fn write_the_oldest_version_that_fits() {
+    let mut t = Table::new();
+    t.add_bytes(0, vec![ 1 ]);
+    let h = Header::new(BottleType::Test, t);
+    assert_eq!(h.version, VERSION);
+    assert_eq!(h.encode().collect().wait().unwrap().to_hex(), "f09f8dbc0001a003400101");
+    assert_eq!(Header::new(BottleType::Test, Table::new()).version, FIRST_VERSION);
+  }
+
+  #[test]
+  fn fail_to_write_a_table_too_new_for_its_version() {
+    let mut t = Table::new();
+    t.add_bytes(0, vec![ 1 ]);
+    let h = Header::new(BottleType::Test, t).with_version(FIRST_VERSION);
+    let mut buffer = Vec::new();
+    assert_eq!(h.encode_into(&mut buffer).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(buffer.len(), 0);
+    assert!(h.encode_buffers().is_err());
+    assert!(h.encode().collect().wait().is_err());
+  }
+
+  #[test]
+  #[should_panic(expected = "Can't write version 0.2")]
+  fn write_only_known_versions() {
+    Header::new(BottleType::Test, Table::new()).with_version(Version { major: 0, minor: 2 });
+  }
+
+  #[test]
+  fn write_an_older_version() {
+    let mut t = Table::new();
+    t.add_number(0, 150);
+    let h = Header::new(BottleType::Test, t).with_version(Version { major: 0, minor: 0 });
+    assert_eq!(h.encode().collect().wait().unwrap().to_hex(), "f09f8dbc0000a003800196");
+
+    // bytes fields are too new for 0.0.
+    let mut t = Table::new();
+    t.add_bytes(0, vec![ 1 ]);
+    let h = Header::new(BottleType::Test, t).with_version(Version { major: 0, minor: 0 });
+    let e = h.check_version().unwrap_err();
+    assert_eq!(e.to_string(), "Version 0.0 can't hold bytes fields");
+
+    // extended fields are strings to a 0.0 reader, which it ignores.
+    let mut t = Table::new();
+    t.add_extended("name", FieldValue::Boolean);
+    assert!(Header::new(BottleType::Test, t).with_version(Version { major: 0, minor: 0 }).check_version().is_ok());
   }
 
   #[test]
-  #[should_panic(expected = "Unknown bottle type")]
-  fn validate_header_bottle_type() {
-    Header::decode(stream_of_hex("f09f8dbc0000f000")).wait().unwrap();
+  fn read_unknown_bottle_type() {
+    let (header, _) = Header::decode(stream_of_hex("f09f8dbc0000f000")).wait().unwrap();
+    assert_eq!(header.bottle_type, BottleType::Other(15));
+    assert_eq!(header.bottle_type.id(), 15);
   }
 
   #[test]
@@ -0,0 +1,104 @@
+#![type_length_limit="4194304"]
+
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+
+mod common;
+
+#[cfg(test)]
+mod test_incremental {
+  use common::temp_folder;
+  use futures::{Future, Stream};
+  use lib4bottle::incremental::{ArchiveIndex, restore_archives, write_incremental_archive};
+  use lib4bottle::stream_toolkit::{ReadableByteStream, stream_of_vec};
+  use std::fs;
+  use std::io::{Read, Write};
+  use std::path::Path;
+
+  fn write_file(path: &Path, data: &str) {
+    fs::File::create(path).unwrap().write_all(data.as_bytes()).unwrap();
+  }
+
+  // every file under a folder, as "path=contents", sorted.
+  fn describe(path: &Path, prefix: &str) -> Vec<String> {
+    let mut rv = Vec::new();
+    let mut entries = fs::read_dir(path).unwrap().map(|e| e.unwrap()).collect::<Vec<fs::DirEntry>>();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+      let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+      if entry.file_type().unwrap().is_dir() {
+        rv.push(format!("{}/", name));
+        rv.extend(describe(&entry.path(), &format!("{}/", name)));
+      } else {
+        let mut data = String::new();
+        fs::File::open(entry.path()).unwrap().read_to_string(&mut data).unwrap();
+        rv.push(format!("{}={}", name, data));
+      }
+    }
+    rv
+  }
+
+  fn paths(index: &ArchiveIndex) -> Vec<String> {
+    index.entries.iter().map(|(path, metadata)| {
+      format!("{}{}", path, if metadata.is_deleted { " (deleted)" } else { "" })
+    }).collect()
+  }
+
+  #[test]
+  fn full_then_incremental() {
+    let base = temp_folder("incremental");
+    let source = base.join("photos");
+    fs::create_dir_all(source.join("sub")).unwrap();
+    write_file(&source.join("a.txt"), "apple");
+    write_file(&source.join("sub").join("b.txt"), "banana");
+    write_file(&source.join("sub").join("c.txt"), "cherry");
+
+    let (full, index) = write_incremental_archive(&source, &ArchiveIndex::new(), true).unwrap();
+    let full = full.collect().wait().unwrap();
+    assert_eq!(paths(&index), vec![ "photos", "photos/a.txt", "photos/sub", "photos/sub/b.txt", "photos/sub/c.txt" ]);
+
+    write_file(&source.join("a.txt"), "apricot");
+    fs::remove_file(source.join("sub").join("c.txt")).unwrap();
+    write_file(&source.join("d.txt"), "date");
+
+    let (incremental, _) = write_incremental_archive(&source, &index, true).unwrap();
+    let incremental = incremental.collect().wait().unwrap();
+    let changes = ArchiveIndex::read(ReadableByteStream::from(stream_of_vec(incremental.clone()))).wait().unwrap();
+    assert_eq!(paths(&changes), vec![ "photos", "photos/a.txt", "photos/d.txt", "photos/sub", "photos/sub/c.txt (deleted)" ]);
+
+    // the index of the full archive, updated by the incremental, matches the disk:
+    let mut restored_index = ArchiveIndex::read(ReadableByteStream::from(stream_of_vec(full.clone()))).wait().unwrap();
+    restored_index.update(changes);
+    let hashes = |index: &ArchiveIndex| {
+      index.entries.iter().map(|(path, m)| format!("{} {:?}", path, m.sha256)).collect::<Vec<String>>()
+    };
+    assert_eq!(hashes(&restored_index), hashes(&ArchiveIndex::scan(&source, true).unwrap()));
+
+    let dest = base.join("restore");
+    let archives = vec![
+      ReadableByteStream::from(stream_of_vec(full)),
+      ReadableByteStream::from(stream_of_vec(incremental))
+    ];
+    restore_archives(archives, &dest).wait().unwrap();
+    assert_eq!(describe(&dest, ""), vec![
+      "photos/", "photos/a.txt=apricot", "photos/d.txt=date", "photos/sub/", "photos/sub/b.txt=banana"
+    ]);
+  }
+
+  #[test]
+  fn unchanged_folder_is_nearly_empty() {
+    let base = temp_folder("unchanged");
+    let source = base.join("docs");
+    fs::create_dir_all(&source).unwrap();
+    write_file(&source.join("a.txt"), "apple");
+
+    let (_, index) = write_incremental_archive(&source, &ArchiveIndex::new(), false).unwrap();
+    let (incremental, _) = write_incremental_archive(&source, &index, false).unwrap();
+    let incremental = incremental.collect().wait().unwrap();
+    let changes = ArchiveIndex::read(ReadableByteStream::from(stream_of_vec(incremental))).wait().unwrap();
+    assert_eq!(paths(&changes), vec![ "docs" ]);
+  }
+}
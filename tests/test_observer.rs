@@ -0,0 +1,131 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_observer {
+  use bytes::{Bytes};
+  use futures::{Future, Stream};
+  use lib4bottle::bottle::{Bottle, FramingPolicy, read_bottle_observed, read_bottle_with_limits, read_nested_bottle};
+  use lib4bottle::header::{BottleType, Header};
+  use lib4bottle::observer::{BottleObserver};
+  use lib4bottle::reader_limits::{Limiter, ReaderLimits};
+  use lib4bottle::stream_toolkit::{FromHex, stream_of, stream_of_hex, stream_of_streams, ToHex};
+  use lib4bottle::table::Table;
+  use std::sync::{Arc, Mutex};
+
+  static MAGIC_HEX: &str = "f09f8dbc0000";
+
+  #[derive(Default)]
+  struct Recorder {
+    events: Mutex<Vec<String>>
+  }
+
+  impl Recorder {
+    fn events(&self) -> Vec<String> {
+      self.events.lock().unwrap().clone()
+    }
+
+    fn push(&self, event: String) {
+      self.events.lock().unwrap().push(event);
+    }
+  }
+
+  impl BottleObserver for Recorder {
+    fn header(&self, depth: usize, header: &Header) {
+      self.push(format!("{}: header {:?}", depth, header.bottle_type));
+    }
+
+    fn nested_bottle(&self, depth: usize) {
+      self.push(format!("{}: nested", depth));
+    }
+
+    fn stream_start(&self, depth: usize, index: usize) {
+      self.push(format!("{}: start {}", depth, index));
+    }
+
+    fn stream_end(&self, depth: usize, index: usize) {
+      self.push(format!("{}: end {}", depth, index));
+    }
+
+    fn frame(&self, depth: usize, length: usize) {
+      self.push(format!("{}: frame {}", depth, length));
+    }
+  }
+
+  #[test]
+  fn observe_encoding() {
+    let recorder = Arc::new(Recorder::default());
+    let data1 = stream_of(Bytes::from("f0f0f0".from_hex()));
+    let data2 = stream_of(Bytes::from("e0e0e0".from_hex()));
+    let b = Bottle::new(BottleType::Test, Table::new(), stream_of_streams(vec![ data1, data2 ]));
+    assert_eq!(
+      b.encode_observed(FramingPolicy::new(1024, 2), recorder.clone()).collect().wait().unwrap().to_hex(),
+      format!("{}a00002f0f001f00002e0e001e000ff", MAGIC_HEX)
+    );
+    assert_eq!(recorder.events(), vec![
+      "0: header Test",
+      "0: start 0", "0: frame 2", "0: frame 1", "0: end 0",
+      "0: start 1", "0: frame 2", "0: frame 1", "0: end 1"
+    ]);
+  }
+
+  #[test]
+  fn observe_decoding() {
+    let recorder = Arc::new(Recorder::default());
+    let data = stream_of_hex(&format!("{}a00003f0f0f00002e0e00000ff", MAGIC_HEX));
+    let (bottle, end_stream) = read_bottle_observed(data, recorder.clone()).wait().unwrap();
+    let streams = bottle.streams.and_then(|s| s.collect()).collect().wait().unwrap();
+    assert_eq!(streams.len(), 3);
+    end_stream.wait().unwrap();
+    assert_eq!(recorder.events(), vec![
+      "0: header Test",
+      "0: start 0", "0: frame 3", "0: end 0",
+      "0: start 1", "0: frame 2", "0: end 1",
+      "0: start 2", "0: end 2"
+    ]);
+  }
+
+  #[test]
+  fn skip_frames_over_the_limits() {
+    let recorder = Arc::new(Recorder::default());
+    let data = stream_of_hex(&format!("{}a00003f0f0f00002e0e000ff", MAGIC_HEX));
+    let limiter = Limiter::new(ReaderLimits { max_frames: 1, ..ReaderLimits::default() }).with_observer(recorder.clone());
+    let (bottle, _) = read_bottle_with_limits(data, limiter).wait().unwrap();
+    assert!(bottle.streams.and_then(|s| s.collect()).collect().wait().is_err());
+    // the second frame is refused before it's reported.
+    assert_eq!(recorder.events(), vec![ "0: header Test", "0: start 0", "0: frame 3", "0: end 0", "0: start 1" ]);
+  }
+
+  #[test]
+  fn observe_nested_bottles() {
+    let recorder = Arc::new(Recorder::default());
+    let data = stream_of_hex(&format!("{}b00009{}a000ff00ff", MAGIC_HEX, MAGIC_HEX));
+    let (bottle, _) = read_bottle_observed(data, recorder.clone()).wait().unwrap();
+    let inner = bottle.streams.into_future().map_err(|(e, _)| e).wait().unwrap().0.unwrap();
+    let (inner_bottle, _) = read_nested_bottle(inner).wait().unwrap();
+    assert_eq!(inner_bottle.streams.collect().wait().unwrap().len(), 0);
+    assert_eq!(recorder.events(), vec![
+      "0: header Test2", "0: start 0", "0: frame 9", "1: nested", "1: header Test"
+    ]);
+  }
+
+  #[test]
+  fn observe_encoding_nested_bottles() {
+    let recorder = Arc::new(Recorder::default());
+    let policy = FramingPolicy::new(1024, 1024);
+    let inner = Bottle::new(BottleType::Test, Table::new(), stream_of_streams(vec![ stream_of(Bytes::from_static(b"hi")) ]));
+    let inner = inner.encode_observed_at(policy, recorder.clone(), 1);
+    let b = Bottle::new(BottleType::Test2, Table::new(), stream_of_streams(vec![ inner ]));
+    assert_eq!(
+      b.encode_observed(policy, recorder.clone()).collect().wait().unwrap().to_hex(),
+      format!("{}b0000d{}a00002686900ff00ff", MAGIC_HEX, MAGIC_HEX)
+    );
+    // the inner bottle is written before the outer frame that holds it.
+    assert_eq!(recorder.events(), vec![
+      "0: header Test2", "0: start 0",
+      "1: nested", "1: header Test", "1: start 0", "1: frame 2", "1: end 0",
+      "0: frame 13", "0: end 0"
+    ]);
+  }
+}
@@ -0,0 +1,138 @@
+#![type_length_limit="8388608"]
+
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_parity_bottle {
+  use bytes::Bytes;
+  use futures::{Future, Stream};
+  use lib4bottle::header::{BottleType, Header};
+  use lib4bottle::parity_bottle::{crc32, encode_parity_bottle, ParityParameters, read_parity_bottle, read_parity_groups};
+  use lib4bottle::table::Table;
+  use lib4bottle::stream_toolkit::{ByteStream, ReadableByteStream, stream_of, stream_of_vec};
+  use std::io;
+
+  fn collect_bytes<S: ByteStream>(s: S) -> io::Result<Vec<u8>> {
+    s.fold(Vec::new(), |mut v, b| { v.extend_from_slice(&b); Ok::<Vec<u8>, io::Error>(v) }).wait()
+  }
+
+  // 16-byte blocks of "a", "b", "c", ...
+  fn sample(blocks: u8, extra: usize) -> Vec<u8> {
+    let mut data = (0 .. blocks).flat_map(|i| vec![ b'a' + i; 16 ]).collect::<Vec<u8>>();
+    data.extend(vec![ b'z'; extra ]);
+    data
+  }
+
+  fn encode(data: &[u8]) -> Vec<u8> {
+    let parameters = ParityParameters::new(16, 4, 2);
+    collect_bytes(encode_parity_bottle(stream_of(Bytes::from(data)), parameters)).unwrap()
+  }
+
+  // flip a byte in the middle of the first block full of `c`.
+  fn damage(encoded: &mut Vec<u8>, c: u8) {
+    let block = vec![ c; 16 ];
+    let index = encoded.windows(16).position(|w| w == &block[..]).unwrap();
+    encoded[index + 8] ^= 0xff;
+  }
+
+  // where the first group header (length 64) starts.
+  fn group_header(encoded: &[u8]) -> usize {
+    let mut copy = vec![ 64, 0, 0, 0 ];
+    let crc = crc32(&copy);
+    copy.extend_from_slice(&[ crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8 ]);
+    encoded.windows(8).position(|w| w == &copy[..]).unwrap()
+  }
+
+  fn decode(encoded: Vec<u8>) -> Result<Vec<u8>, String> {
+    let s = ReadableByteStream::from(stream_of_vec(vec![ Bytes::from(encoded) ]));
+    collect_bytes(read_parity_bottle(s)).map_err(|e| e.to_string())
+  }
+
+  #[test]
+  fn round_trip() {
+    for &(blocks, extra) in &[ (0, 0), (0, 5), (4, 0), (9, 3) ] {
+      let data = sample(blocks, extra);
+      assert_eq!(decode(encode(&data)).unwrap(), data);
+    }
+  }
+
+  #[test]
+  fn repair_damaged_blocks() {
+    let data = sample(9, 3);
+    let mut encoded = encode(&data);
+    damage(&mut encoded, b'b');
+    damage(&mut encoded, b'd');
+    damage(&mut encoded, b'i');
+    assert_eq!(decode(encoded.clone()).unwrap(), data);
+
+    let s = ReadableByteStream::from(stream_of_vec(vec![ Bytes::from(encoded) ]));
+    let repaired = read_parity_groups(s).map(|group| group.repaired_blocks).collect().wait().unwrap();
+    assert_eq!(repaired, vec![ 2, 0, 1 ]);
+  }
+
+  #[test]
+  fn too_much_damage() {
+    let mut encoded = encode(&sample(4, 0));
+    damage(&mut encoded, b'a');
+    damage(&mut encoded, b'b');
+    damage(&mut encoded, b'c');
+    assert_eq!(decode(encoded), Err("Too many damaged blocks to repair: 3".to_string()));
+  }
+
+  #[test]
+  fn damaged_group_header() {
+    let data = sample(9, 3);
+    let mut encoded = encode(&data);
+    let index = group_header(&encoded);
+    encoded[index] ^= 0xff;
+    encoded[index + 12] ^= 0xff;
+    assert_eq!(decode(encoded.clone()).unwrap(), data);
+
+    encoded[index + 20] ^= 0xff;
+    assert_eq!(decode(encoded), Err("Damaged parity group".to_string()));
+  }
+
+  #[test]
+  fn damaged_frame_lengths() {
+    let data = sample(9, 3);
+    let mut encoded = encode(&data);
+    // the length of the group header's frame, and of the first record.
+    let index = group_header(&encoded);
+    encoded[index - 1] ^= 0xff;
+    encoded[index + 24] ^= 0xff;
+    assert_eq!(decode(encoded).unwrap(), data);
+  }
+
+  #[test]
+  fn reject_huge_parameters() {
+    let mut table = Table::new();
+    table.add_number(0, 1 << 40);
+    table.add_number(1, 200);
+    table.add_number(2, 2);
+    let mut encoded = Vec::new();
+    Header::new(BottleType::Parity, table).encode_into(&mut encoded).unwrap();
+    encoded.push(0xff);
+    assert_eq!(decode(encoded), Err("Invalid parity parameters".to_string()));
+
+    let mut table = Table::new();
+    table.add_number(0, 1 << 20);
+    table.add_number(1, 200);
+    table.add_number(2, 2);
+    let mut encoded = Vec::new();
+    Header::new(BottleType::Parity, table).encode_into(&mut encoded).unwrap();
+    encoded.push(0xff);
+    assert_eq!(decode(encoded), Err("Invalid parity parameters".to_string()));
+
+    // the parity blocks count too.
+    let mut table = Table::new();
+    table.add_number(0, 1 << 20);
+    table.add_number(1, 1);
+    table.add_number(2, 255);
+    let mut encoded = Vec::new();
+    Header::new(BottleType::Parity, table).encode_into(&mut encoded).unwrap();
+    encoded.push(0xff);
+    assert_eq!(decode(encoded), Err("Invalid parity parameters".to_string()));
+  }
+}
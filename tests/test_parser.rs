@@ -0,0 +1,193 @@
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_parser {
+  use lib4bottle::header::{BottleType};
+  use lib4bottle::parser::{BottleParser, ParseEvent, Piece};
+  use lib4bottle::reader_limits::{is_limit_exceeded, ReaderLimits};
+  use lib4bottle::stream_toolkit::{FromHex, ToHex};
+  use std::io;
+
+  static MAGIC_HEX: &str = "f09f8dbc0000";
+
+  // feed the data in chunks of `size`, describing each event.
+  fn parse(parser: &mut BottleParser, data: &[u8], size: usize) -> io::Result<( Vec<String>, usize )> {
+    let mut events = Vec::new();
+    let mut used = 0;
+    for chunk in data.chunks(size) {
+      used += parser.feed(chunk, |depth, event| {
+        let description = match event {
+          ParseEvent::BottleStart(header) => format!("start {:?} {:?}", header.bottle_type, header.table),
+          ParseEvent::StreamStart => "stream".to_string(),
+          ParseEvent::Data(data) => data.to_hex(),
+          ParseEvent::StreamEnd => "end stream".to_string(),
+          ParseEvent::BottleEnd => "end".to_string()
+        };
+        events.push(format!("{}: {}", depth, description));
+      })?;
+    }
+    Ok(( events, used ))
+  }
+
+  #[test]
+  fn parse_a_bottle() {
+    let data = format!("{}a00380019603f0f0f00002e0e00000ff", MAGIC_HEX).as_str().from_hex();
+    let ( events, used ) = parse(&mut BottleParser::new(), &data, 1024).unwrap();
+    assert_eq!(used, data.len());
+    assert_eq!(events, vec![
+      "0: start Test Table(N0=150)",
+      "0: stream", "0: f0f0f0", "0: end stream",
+      "0: stream", "0: e0e0", "0: end stream",
+      "0: stream", "0: end stream",
+      "0: end"
+    ]);
+  }
+
+  #[test]
+  fn parse_a_byte_at_a_time() {
+    let data = format!("{}a00003f0f0f002414200010000ff", MAGIC_HEX).as_str().from_hex();
+    let mut parser = BottleParser::new();
+    let ( events, used ) = parse(&mut parser, &data, 1).unwrap();
+    assert_eq!(used, data.len());
+    assert!(parser.is_done());
+    assert_eq!(events, vec![
+      "0: start Test Table()",
+      "0: stream", "0: f0", "0: f0", "0: f0", "0: 41", "0: 42", "0: end stream",
+      "0: stream", "0: 00", "0: end stream",
+      "0: end"
+    ]);
+  }
+
+  #[test]
+  fn stop_at_the_end_of_the_bottle() {
+    let data = format!("{}a000ff{}", MAGIC_HEX, MAGIC_HEX).as_str().from_hex();
+    let mut parser = BottleParser::new();
+    let ( events, used ) = parse(&mut parser, &data, 1024).unwrap();
+    assert_eq!(used, 9);
+    assert!(parser.is_done());
+    assert_eq!(events, vec![ "0: start Test Table()", "0: end" ]);
+  }
+
+  #[test]
+  fn parse_nested_bottles() {
+    // the inner bottle is split across frames of the outer stream:
+    let data = format!("{}b00003f09f8d09bc0000a00001aa00ff00ff", MAGIC_HEX).as_str().from_hex();
+    for size in vec![ 1, 3, 1024 ] {
+      let mut parser = BottleParser::with_nesting(|header| header.bottle_type == BottleType::Test2);
+      let ( events, used ) = parse(&mut parser, &data, size).unwrap();
+      assert_eq!(used, data.len());
+      assert_eq!(events, vec![
+        "0: start Test2 Table()",
+        "0: stream",
+        "1: start Test Table()",
+        "1: stream", "1: aa", "1: end stream",
+        "1: end",
+        "0: end stream",
+        "0: end"
+      ], "chunks of {}", size);
+    }
+  }
+
+  #[test]
+  fn reject_bad_data() {
+    let bad_magic = "f09f8dbb0000a000ff".from_hex();
+    assert_eq!(parse(&mut BottleParser::new(), &bad_magic, 1024).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+    let trailing = format!("{}b00009{}a000ff010000ff", MAGIC_HEX, MAGIC_HEX).as_str().from_hex();
+    let mut parser = BottleParser::with_nesting(|header| header.bottle_type == BottleType::Test2);
+    assert!(parse(&mut parser, &trailing, 1024).is_err());
+
+    let truncated = format!("{}b00004{}00ff", MAGIC_HEX, "f09f8dbc").as_str().from_hex();
+    let mut parser = BottleParser::with_nesting(|header| header.bottle_type == BottleType::Test2);
+    assert!(parse(&mut parser, &truncated, 1024).is_err());
+  }
+
+  // a Test2 bottle holding a Test2 bottle ... `levels` deep, with a Test
+  // bottle in the middle.
+  fn nested_bottles(levels: usize) -> Vec<u8> {
+    let mut data = format!("{}a000ff", MAGIC_HEX).as_str().from_hex();
+    for _ in 0 .. levels {
+      let mut outer = format!("{}b000", MAGIC_HEX).as_str().from_hex();
+      outer.push(data.len() as u8);
+      outer.extend_from_slice(&data);
+      outer.extend_from_slice(&[ 0, 0xff ]);
+      data = outer;
+    }
+    data
+  }
+
+  #[test]
+  fn limit_nesting_depth() {
+    let data = nested_bottles(5);
+    let mut parser = BottleParser::with_nesting(|header| header.bottle_type == BottleType::Test2);
+    assert_eq!(parse(&mut parser, &data, 1024).unwrap().1, data.len());
+
+    let limits = ReaderLimits { max_depth: 4, ..ReaderLimits::default() };
+    let mut parser = BottleParser::with_nesting(|header| header.bottle_type == BottleType::Test2).with_limits(limits);
+    let e = parse(&mut parser, &data, 1024).unwrap_err();
+    assert!(is_limit_exceeded(&e));
+    assert_eq!(format!("{}", e), "Reader limit exceeded: nesting depth (maximum 4)");
+
+    let limits = ReaderLimits { max_depth: 4, ..ReaderLimits::default() };
+    let mut parser = BottleParser::with_magic_nesting().with_limits(limits);
+    assert!(is_limit_exceeded(&parse(&mut parser, &data, 7).unwrap_err()));
+  }
+
+  #[test]
+  fn limit_streams_frames_and_bytes() {
+    // five streams, each with one frame.
+    let data = nested_bottles(5);
+    let parser = |limits: ReaderLimits| {
+      BottleParser::with_nesting(|header| header.bottle_type == BottleType::Test2).with_limits(limits)
+    };
+
+    let e = parse(&mut parser(ReaderLimits { max_streams: 4, ..ReaderLimits::default() }), &data, 1024).unwrap_err();
+    assert_eq!(format!("{}", e), "Reader limit exceeded: stream count (maximum 4)");
+    let e = parse(&mut parser(ReaderLimits { max_frames: 4, ..ReaderLimits::default() }), &data, 1024).unwrap_err();
+    assert_eq!(format!("{}", e), "Reader limit exceeded: frame count (maximum 4)");
+
+    // the nested bottles are inside the outer frame, so they aren't counted
+    // again.
+    let outer_frame = data.len() as u64 - 11;
+    let limits = ReaderLimits { max_bytes: outer_frame, ..ReaderLimits::default() };
+    assert_eq!(parse(&mut parser(limits), &data, 1024).unwrap().1, data.len());
+    let limits = ReaderLimits { max_bytes: outer_frame - 1, ..ReaderLimits::default() };
+    let e = parse(&mut parser(limits), &data, 1024).unwrap_err();
+    assert!(is_limit_exceeded(&e));
+    assert_eq!(format!("{}", e), format!("Reader limit exceeded: total bytes (maximum {})", outer_frame - 1));
+  }
+
+  #[test]
+  fn report_pieces() {
+    // the inner bottle is split across frames of the outer stream, and
+    // followed by data:
+    let data = format!("{}b00003f09f8d09bc0000a00001aa00ff03fe000000ff", MAGIC_HEX).as_str().from_hex();
+    let mut parser = BottleParser::with_magic_nesting();
+    let mut pieces = Vec::new();
+    let used = parser.feed_pieces(&data, |depth, piece, runs| {
+      let description = match piece {
+        Piece::Header(header, bytes) => format!("header {:?} {}", header.bottle_type, bytes.to_hex()),
+        Piece::Length(length, bytes) => format!("{:?} {}", length, bytes.to_hex()),
+        Piece::Data(data) => data.to_hex()
+      };
+      let runs = runs.iter().map(|r| format!("{}..{}", r.start, r.end)).collect::<Vec<String>>().join(",");
+      pieces.push(format!("{}: {} @{}", depth, description, runs));
+    }).unwrap();
+    assert_eq!(used, data.len());
+    assert!(parser.is_done());
+    assert_eq!(pieces, vec![
+      "0: header Test2 f09f8dbc0000b000 @0..8",
+      "0: Length(3) 03 @8..9",
+      "0: Length(9) 09 @12..13",
+      "1: header Test f09f8dbc0000a000 @9..12,13..18",
+      "1: Length(1) 01 @18..19",
+      "1: aa @19..20",
+      "1: EndOfStream 00 @20..21",
+      "1: EndOfBottle ff @21..22",
+      "0: Length(3) 03 @22..23",
+      "0: fe0000 @23..26",
+      "0: EndOfStream 00 @26..27",
+      "0: EndOfBottle ff @27..28"
+    ]);
+  }
+}
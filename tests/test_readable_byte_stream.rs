@@ -6,7 +6,8 @@ extern crate lib4bottle;
 mod test_stream_reader {
   use bytes::{Bytes};
   use futures::{Future, Stream};
-  use lib4bottle::stream_toolkit::{ReadableByteStream, ReadMode, stream_of, stream_of_vec, ToHex};
+  use lib4bottle::stream_toolkit::{FromHex, MemoryLimit, ReadableByteStream, ReaderByteStream, ReadMode, stream_of, stream_of_vec, ToHex};
+  use std::io;
 
   #[test]
   fn stream_read_exact_slices() {
@@ -48,6 +49,18 @@ mod test_stream_reader {
     assert!(s.read_exact(12).wait().is_err());
   }
 
+  #[test]
+  fn into_inner_after_peeking() {
+    let s = ReadableByteStream::from(stream_of_vec(vec![
+      Bytes::from_static(b"pro"), Bytes::from_static(b"gress"), Bytes::from_static(b"ive")
+    ]));
+    let (data, s) = s.peek(5).wait().unwrap();
+    assert_eq!(data.vec.to_hex(), "70726f6772");
+    let (saved, rest) = s.into_inner();
+    assert_eq!(saved.unwrap(), Bytes::from_static(b"progress"));
+    assert_eq!(rest.collect().wait().unwrap().to_hex(), "697665");
+  }
+
   #[test]
   fn stream_read_exact_returns_valid_continuation_stream() {
     let s = ReadableByteStream::from(stream_of(Bytes::from_static(b"progressive")));
@@ -91,4 +104,140 @@ mod test_stream_reader {
     assert_eq!(frame4.vec.to_hex(), "");
     assert_eq!(s.into_stream().collect().wait().unwrap().to_hex(), "");
   }
+
+  #[test]
+  fn stream_skip() {
+    let s = ReadableByteStream::from(stream_of_vec(vec![
+      Bytes::from_static(b"pr"),
+      Bytes::from_static(b"ogres"),
+      Bytes::from_static(b"s"),
+      Bytes::from_static(b"ive")
+    ]));
+    let (data1, s) = s.read_exact(1).wait().unwrap();
+    assert_eq!(data1.vec.to_hex(), "70");
+    let (count, s) = s.skip(4).wait().unwrap();
+    assert_eq!(count, 4);
+    let (data2, s) = s.read_exact(2).wait().unwrap();
+    assert_eq!(data2.vec.to_hex(), "6573");
+    let (count, s) = s.skip_to_end().wait().unwrap();
+    assert_eq!(count, 4);
+    assert_eq!(s.into_stream().collect().wait().unwrap().to_hex(), "");
+  }
+
+  #[test]
+  fn stream_skip_refuses_to_truncate() {
+    let s = ReadableByteStream::from(stream_of(Bytes::from_static(b"progressive")));
+    assert!(s.skip(12).wait().is_err());
+  }
+
+  #[test]
+  fn stream_skip_by_seeking() {
+    let reader = io::Cursor::new(b"progressive".to_vec());
+    let s = ReadableByteStream::seekable(ReaderByteStream::new(reader, 4));
+    let (data1, s) = s.read_exact(1).wait().unwrap();
+    assert_eq!(data1.vec.to_hex(), "70");
+    let (count, s) = s.skip(5).wait().unwrap();
+    assert_eq!(count, 5);
+    let (data2, s) = s.read_exact(2).wait().unwrap();
+    assert_eq!(data2.vec.to_hex(), "7373");
+    let (count, s) = s.skip_to_end().wait().unwrap();
+    assert_eq!(count, 3);
+    let (_, reader) = s.into_inner();
+    assert_eq!(reader.into_inner().position(), 11);
+    assert!(ReadableByteStream::seekable(ReaderByteStream::new(io::Cursor::new(vec![ 0u8; 3 ]), 4))
+      .skip(4).wait().is_err());
+  }
+
+  #[test]
+  fn stream_read_integers() {
+    let s = ReadableByteStream::from(stream_of_vec(vec![
+      Bytes::from("ff0102".from_hex()),
+      Bytes::from("0102010203".from_hex()),
+      Bytes::from("0401020304010203".from_hex()),
+      Bytes::from("04050607080102030405060708".from_hex())
+    ]));
+    let (n, s) = s.read_u8().wait().unwrap();
+    assert_eq!(n, 0xff);
+    let (n, s) = s.read_u16_le().wait().unwrap();
+    assert_eq!(n, 0x0201);
+    let (n, s) = s.read_u16_be().wait().unwrap();
+    assert_eq!(n, 0x0102);
+    let (n, s) = s.read_u32_le().wait().unwrap();
+    assert_eq!(n, 0x04030201);
+    let (n, s) = s.read_u32_be().wait().unwrap();
+    assert_eq!(n, 0x01020304);
+    let (n, s) = s.read_u64_le().wait().unwrap();
+    assert_eq!(n, 0x0807060504030201);
+    let (n, s) = s.read_u64_be().wait().unwrap();
+    assert_eq!(n, 0x0102030405060708);
+    assert!(s.read_u8().wait().is_err());
+  }
+
+  #[test]
+  fn stream_read_until() {
+    let s = ReadableByteStream::from(stream_of_vec(vec![
+      Bytes::from_static(b"pr"),
+      Bytes::from_static(b"ogres"),
+      Bytes::from_static(b"s ive")
+    ]));
+    let (data1, s) = s.read_until(b's').wait().unwrap();
+    assert_eq!(data1.vec.to_hex(), "70726f67726573");
+    let (data2, s) = s.read_until(b' ').wait().unwrap();
+    assert_eq!(data2.vec.to_hex(), "7320");
+    let (data3, s) = s.read_until(b'x').wait().unwrap();
+    assert_eq!(data3.vec.to_hex(), "697665");
+    assert_eq!(s.into_stream().collect().wait().unwrap().to_hex(), "");
+  }
+
+  #[test]
+  fn stream_peek() {
+    let s = ReadableByteStream::from(stream_of_vec(vec![ Bytes::from_static(b"pr"), Bytes::from_static(b"ogress") ]));
+    let (data1, s) = s.peek(3).wait().unwrap();
+    assert_eq!(data1.vec.to_hex(), "70726f");
+    let (data2, s) = s.read_exact(4).wait().unwrap();
+    assert_eq!(data2.vec.to_hex(), "70726f67");
+    assert!(s.peek(5).wait().is_err());
+  }
+
+  #[test]
+  fn stream_read_with_memory_limit() {
+    let limit = MemoryLimit::new(4);
+    let s = ReadableByteStream::from(stream_of_vec(vec![
+      Bytes::from_static(b"pr"),
+      Bytes::from_static(b"ogres"),
+      Bytes::from_static(b"sive")
+    ])).with_memory_limit(limit.clone());
+    let (frame1, s) = s.read(1, ReadMode::Lazy).wait().unwrap();
+    assert_eq!(frame1.vec.to_hex(), "7072");
+    let (frame2, s) = s.read_at_most(10).wait().unwrap();
+    assert_eq!(frame2.vec.to_hex(), "6f677265");
+    assert!(s.read_exact(5).wait().is_err());
+    assert!(limit.peak_buffered() <= limit.max_bytes());
+  }
+
+  #[test]
+  fn stream_stays_under_memory_limit() {
+    let limit = MemoryLimit::new(4);
+    let s = ReadableByteStream::from(stream_of_vec(vec![
+      Bytes::from_static(b"pro"),
+      Bytes::from_static(b"gressive")
+    ])).with_memory_limit(limit.clone());
+    let (frame1, s) = s.read_exact(4).wait().unwrap();
+    assert_eq!(frame1.vec.to_hex(), "70726f67");
+    let (count, s) = s.skip(2).wait().unwrap();
+    assert_eq!(count, 2);
+    let (frame2, s) = s.read(1, ReadMode::Lazy).wait().unwrap();
+    assert_eq!(frame2.vec.to_hex(), "73736976");
+    assert_eq!(s.into_stream().collect().wait().unwrap().to_hex(), "65");
+    assert_eq!(limit.peak_buffered(), 4);
+  }
+
+  #[test]
+  fn stream_peek_then_read_a_little() {
+    let s = ReadableByteStream::from(stream_of_vec(vec![ Bytes::from_static(b"pr"), Bytes::from_static(b"ogress") ]));
+    let (_, s) = s.peek(3).wait().unwrap();
+    let (data, s) = s.read_exact(1).wait().unwrap();
+    assert_eq!(data.vec.to_hex(), "70");
+    assert_eq!(s.into_stream().collect().wait().unwrap().to_hex(), "726f6772657373");
+  }
 }
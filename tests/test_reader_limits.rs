@@ -0,0 +1,130 @@
+#![type_length_limit="4194304"]
+
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+
+mod common;
+
+#[cfg(test)]
+mod test_reader_limits {
+  use futures::{Future, Stream, stream};
+  use lib4bottle::bottle::{read_bottle_with_limits, read_nested_bottle};
+  use lib4bottle::file_bottle::{FileEntry, read_file_entries, read_file_entries_with_limits};
+  use lib4bottle::header::Header;
+  use lib4bottle::reader_limits::{is_limit_exceeded, Limiter, ReaderLimits};
+  use lib4bottle::stream_toolkit::{BoxedByteStream, ReadableByteStream, stream_of_hex};
+  use common::{file, folder};
+  use std::io;
+
+  fn count<S>(entries: S) -> Result<usize, io::Error> where S: Stream<Item = FileEntry, Error = io::Error> {
+    entries.and_then(|entry| {
+      let data: BoxedByteStream = entry.data.unwrap_or_else(|| Box::new(stream::empty()));
+      data.for_each(|_| Ok(()))
+    }).collect().wait().map(|entries| entries.len())
+  }
+
+  fn count_entries(s: BoxedByteStream, limits: ReaderLimits) -> Result<usize, io::Error> {
+    count(read_file_entries_with_limits(ReadableByteStream::from(s), Limiter::new(limits)))
+  }
+
+  fn deep() -> BoxedByteStream {
+    folder("a", vec![ folder("b", vec![ folder("c", vec![ file("d", b"hello") ]) ]) ])
+  }
+
+  #[test]
+  fn default_limits_are_roomy() {
+    assert_eq!(count_entries(deep(), ReaderLimits::default()).unwrap(), 4);
+  }
+
+  #[test]
+  fn plain_reads_are_limited() {
+    let too_deep = || (0 .. 33).fold(file("d", b"hello"), |s, _| folder("f", vec![ s ]));
+    let e = count(read_file_entries(ReadableByteStream::from(too_deep()))).unwrap_err();
+    assert_eq!(e.to_string(), "Reader limit exceeded: nesting depth (maximum 32)");
+    assert_eq!(count_entries(too_deep(), ReaderLimits::unlimited()).unwrap(), 34);
+  }
+
+  #[test]
+  fn limit_nesting_depth() {
+    let mut limits = ReaderLimits::default();
+    limits.max_depth = 3;
+    assert_eq!(count_entries(deep(), limits.clone()).unwrap(), 4);
+    limits.max_depth = 2;
+    let e = count_entries(deep(), limits).unwrap_err();
+    assert!(is_limit_exceeded(&e));
+    assert_eq!(e.to_string(), "Reader limit exceeded: nesting depth (maximum 2)");
+  }
+
+  #[test]
+  fn limit_streams() {
+    let s = || folder("a", vec![ file("b", b"1"), file("c", b"2"), file("d", b"3") ]);
+    let mut limits = ReaderLimits::default();
+    // one for each file's data, and one for each file in the folder:
+    limits.max_streams = 6;
+    assert_eq!(count_entries(s(), limits.clone()).unwrap(), 4);
+    limits.max_streams = 5;
+    assert!(is_limit_exceeded(&count_entries(s(), limits).unwrap_err()));
+  }
+
+  #[test]
+  fn limit_frames_and_bytes() {
+    let mut limits = ReaderLimits::default();
+    // one frame for the file's data, and one holding the file in the folder:
+    limits.max_frames = 2;
+    assert_eq!(count_entries(folder("x", vec![ file("a", b"hello") ]), limits.clone()).unwrap(), 2);
+    limits.max_frames = 1;
+    let e = count_entries(folder("x", vec![ file("a", b"hello") ]), limits).unwrap_err();
+    assert_eq!(e.to_string(), "Reader limit exceeded: frame count (maximum 1)");
+
+    let mut limits = ReaderLimits::default();
+    limits.max_bytes = 4;
+    let e = count_entries(file("a", b"hello"), limits).unwrap_err();
+    assert_eq!(e.to_string(), "Reader limit exceeded: total bytes (maximum 4)");
+  }
+
+  #[test]
+  fn count_nested_bytes_once() {
+    // only the folder's frames (holding the whole file bottle) count.
+    let size = file("a", b"hello").concat2().wait().unwrap().len() as u64;
+    let mut limits = ReaderLimits::default();
+    limits.max_bytes = size;
+    assert_eq!(count_entries(folder("x", vec![ file("a", b"hello") ]), limits.clone()).unwrap(), 2);
+    limits.max_bytes = size - 1;
+    assert!(is_limit_exceeded(&count_entries(folder("x", vec![ file("a", b"hello") ]), limits).unwrap_err()));
+  }
+
+  #[test]
+  fn nested_bottles_inherit_limits() {
+    let read_inner = |max_depth| {
+      let limiter = Limiter::new(ReaderLimits { max_depth, .. ReaderLimits::default() });
+      read_bottle_with_limits(ReadableByteStream::from(deep()), limiter).and_then(|(bottle, _)| {
+        bottle.streams.into_future().map_err(|(e, _)| e)
+      }).and_then(|(inner, _)| read_nested_bottle(inner.unwrap())).map(|(bottle, _)| bottle.header).wait()
+    };
+    assert!(read_inner(1).is_ok());
+    assert!(is_limit_exceeded(&read_inner(0).unwrap_err()));
+  }
+
+  #[test]
+  fn limit_table_size() {
+    let mut limits = ReaderLimits::default();
+    limits.max_table_size = 2;
+    let e = Header::decode_with_limits(stream_of_hex("f09f8dbc0000a003800196"), &Limiter::new(limits)).wait().unwrap_err();
+    assert!(is_limit_exceeded(&e));
+
+    let e = read_bottle_with_limits(stream_of_hex("f09f8dbc0000a0000568656c6c6f00ff"), Limiter::new(ReaderLimits {
+      max_bytes: 3,
+      .. ReaderLimits::default()
+    })).and_then(|(bottle, _)| bottle.streams.flatten().collect()).wait().unwrap_err();
+    assert!(is_limit_exceeded(&e));
+  }
+
+  #[test]
+  fn other_errors_are_not_limits() {
+    let e = Header::decode_with_limits(stream_of_hex("00ff00ff00ff00ff"), &Limiter::new(ReaderLimits::default())).wait().unwrap_err();
+    assert!(!is_limit_exceeded(&e));
+  }
+}
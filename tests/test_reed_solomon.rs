@@ -0,0 +1,47 @@
+extern crate lib4bottle;
+
+#[cfg(test)]
+mod test_reed_solomon {
+  use lib4bottle::reed_solomon::ReedSolomon;
+
+  fn sample() -> Vec<Vec<u8>> {
+    (0 .. 4u8).map(|i| (0 .. 8u8).map(|j| i * 31 + j * 7).collect()).collect()
+  }
+
+  fn encode_all(codec: &ReedSolomon) -> Vec<Vec<u8>> {
+    let data = sample();
+    let parity = {
+      let slices = data.iter().map(|d| d.as_ref()).collect::<Vec<&[u8]>>();
+      codec.encode(&slices)
+    };
+    data.into_iter().chain(parity.into_iter()).collect()
+  }
+
+  #[test]
+  fn rebuild_any_missing_shards() {
+    let codec = ReedSolomon::new(4, 2);
+    let shards = encode_all(&codec);
+    assert_eq!(shards.len(), 6);
+
+    for a in 0 .. 6 {
+      for b in a .. 6 {
+        let mut damaged = shards.iter().cloned().map(Some).collect::<Vec<Option<Vec<u8>>>>();
+        damaged[a] = None;
+        damaged[b] = None;
+        codec.reconstruct(&mut damaged).unwrap();
+        assert_eq!(damaged.into_iter().map(|s| s.unwrap()).collect::<Vec<Vec<u8>>>(), shards);
+      }
+    }
+  }
+
+  #[test]
+  fn too_many_missing_shards() {
+    let codec = ReedSolomon::new(4, 2);
+    let mut damaged = encode_all(&codec).into_iter().map(Some).collect::<Vec<Option<Vec<u8>>>>();
+    damaged[0] = None;
+    damaged[2] = None;
+    damaged[5] = None;
+    let error = codec.reconstruct(&mut damaged).unwrap_err();
+    assert_eq!(format!("{}", error), "Too many damaged blocks to repair: 3");
+  }
+}
@@ -0,0 +1,150 @@
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+extern crate sha2;
+
+mod common;
+
+#[cfg(test)]
+mod test_salvage {
+  use bytes::Bytes;
+  use futures::Future;
+  use lib4bottle::file_bottle::FileMetadata;
+  use lib4bottle::reader_limits::ReaderLimits;
+  use lib4bottle::salvage::{salvage, salvage_stream, Salvager, SalvageReport};
+  use lib4bottle::stream_toolkit::{BoxedByteStream, stream_of_vec, ToHex};
+  use common::{file_bottle, folder, noise, pack};
+  use sha2::{Digest, Sha256};
+
+  // a file with its SHA-256, so damage to its contents can be detected.
+  fn file(name: &str, data: &'static [u8]) -> BoxedByteStream {
+    let mut metadata = FileMetadata::new(name.to_string());
+    metadata.size = data.len() as u64;
+    let mut hasher = Sha256::default();
+    hasher.input(data);
+    metadata.sha256 = Some(hasher.result().as_slice().to_hex());
+    file_bottle(&metadata, Bytes::from_static(data))
+  }
+
+  fn sample() -> Vec<u8> {
+    pack(folder("top", vec![
+      file("a", b"apple"),
+      folder("sub", vec![ file("b", b"banana") ]),
+      file("c", b"cherry")
+    ])).to_vec()
+  }
+
+  fn position(buffer: &[u8], text: &[u8]) -> usize {
+    buffer.windows(text.len()).position(|w| w == text).unwrap()
+  }
+
+  fn list(report: &SalvageReport) -> Vec<String> {
+    report.entries.iter().map(|entry| {
+      match entry.data {
+        None => format!("{}/", entry.path),
+        Some(ref data) => format!("{}={}", entry.path, String::from_utf8_lossy(data))
+      }
+    }).collect()
+  }
+
+  #[test]
+  fn intact_archive() {
+    let report = salvage(&sample());
+    assert_eq!(list(&report), vec![ "top/", "top/a=apple", "top/sub/", "top/sub/b=banana", "top/c=cherry" ]);
+    assert_eq!(report.skipped.len(), 0);
+    assert_eq!(report.entries[0].offset, 0);
+  }
+
+  #[test]
+  fn skip_damaged_file() {
+    let mut buffer = sample();
+    let index = position(&buffer, b"banana");
+    buffer[index] = b'B';
+    let report = salvage(&buffer);
+    assert_eq!(list(&report), vec![ "top/", "top/a=apple", "top/sub/", "c=cherry" ]);
+
+    // everything from the damaged file to the next bottle was skipped:
+    let c_offset = report.entries[3].offset;
+    assert_eq!(&buffer[c_offset as usize .. c_offset as usize + 4], b"\xf0\x9f\x8d\xbc");
+    assert_eq!(report.skipped[0].end, c_offset);
+    assert!(report.skipped[0].start < index as u64);
+    assert!(report.skipped[0].start > report.entries[2].offset);
+  }
+
+  #[test]
+  fn skip_damaged_framing() {
+    let mut buffer = sample();
+    // the frame holding "apple" gets a bogus length.
+    let index = position(&buffer, b"apple");
+    buffer[index - 1] = 0x3f;
+    let report = salvage(&buffer);
+    assert_eq!(list(&report), vec![ "top/", "sub/", "sub/b=banana", "c=cherry" ]);
+    assert_eq!(report.skipped.len(), 3);
+  }
+
+  #[test]
+  fn truncated_archive() {
+    let buffer = sample();
+    let index = position(&buffer, b"cherry");
+    let report = salvage(&buffer[0 .. index + 3]);
+    assert_eq!(list(&report), vec![ "top/", "top/a=apple", "top/sub/", "top/sub/b=banana" ]);
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(report.skipped[0].end, index as u64 + 3);
+  }
+
+  #[test]
+  fn not_an_archive() {
+    let report = salvage(b"hello sailor");
+    assert_eq!(report.entries.len(), 0);
+    assert_eq!(report.skipped, vec![ 0 .. 12 ]);
+  }
+
+  #[test]
+  fn salvage_as_it_arrives() {
+    let mut buffer = sample();
+    let index = position(&buffer, b"apple");
+    buffer[index - 1] = 0x3f;
+    let whole = salvage(&buffer);
+    for size in vec![ 1, 5, 64 ] {
+      let chunks = buffer.chunks(size).map(|chunk| Bytes::from(chunk)).collect::<Vec<Bytes>>();
+      let report = salvage_stream(stream_of_vec(chunks)).wait().unwrap();
+      assert_eq!(list(&report), list(&whole), "chunks of {}", size);
+      assert_eq!(report.skipped, whole.skipped, "chunks of {}", size);
+      let offsets = |r: &SalvageReport| r.entries.iter().map(|e| e.offset).collect::<Vec<u64>>();
+      assert_eq!(offsets(&report), offsets(&whole), "chunks of {}", size);
+    }
+  }
+
+  #[test]
+  fn hold_only_the_current_bottle() {
+    let files = (0 .. 200).map(|i| {
+      file_bottle(&FileMetadata::new(format!("f{}", i)), Bytes::from(noise(1000, i)))
+    }).collect();
+    let buffer = pack(folder("top", files));
+    let mut salvager = Salvager::new();
+    for chunk in buffer.chunks(500) {
+      salvager.feed(chunk);
+      assert!(salvager.buffered() < 2000, "holding {} bytes", salvager.buffered());
+    }
+    let report = salvager.finish();
+    assert_eq!(report.entries.len(), 201);
+    assert_eq!(report.skipped.len(), 0);
+  }
+
+  #[test]
+  fn limit_nesting() {
+    let mut s = file("deep", b"treasure");
+    for _ in 0 .. 5 { s = folder("f", vec![ s ]) };
+    let buffer = pack(s);
+    assert_eq!(list(&salvage(&buffer)), vec![ "f/", "f/f/", "f/f/f/", "f/f/f/f/", "f/f/f/f/f/", "f/f/f/f/f/deep=treasure" ]);
+
+    // too deep to read in one go, so the innermost bottles are found by
+    // scanning for them.
+    let mut salvager = Salvager::new().with_limits(ReaderLimits { max_depth: 3, ..ReaderLimits::default() });
+    salvager.feed(&buffer);
+    let report = salvager.finish();
+    assert_eq!(list(&report), vec![ "f/", "f/f/", "f/f/f/", "f/f/f/f/", "f/", "f/deep=treasure" ]);
+  }
+}
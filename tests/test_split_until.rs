@@ -7,7 +7,7 @@ extern crate lib4bottle;
 mod test_stream_split {
   use futures::{future, Future, Stream, stream};
   use std::{io, thread, time};
-  use lib4bottle::stream_toolkit::{SplitUntil};
+  use lib4bottle::stream_toolkit::{CompletionError, SplitUntil};
 
   #[test]
   fn simple_split() {
@@ -44,4 +44,29 @@ mod test_stream_split {
     assert_eq!(right.wait().unwrap().collect().wait().unwrap(), vec![ 5, 6 ]);
     t.join().unwrap();
   }
+
+  fn completion_error(e: io::Error) -> CompletionError {
+    *e.get_ref().unwrap().downcast_ref::<CompletionError>().unwrap()
+  }
+
+  #[test]
+  fn fail_right_on_error() {
+    let items = vec![ Ok(1), Ok(2), Err(io::Error::new(io::ErrorKind::Other, "oh no")), Ok(4) ];
+    let s = stream::iter::<_, _, io::Error>(items.into_iter());
+    let (left, right) = s.split_until(|n| { future::ok(*n == 4) });
+    assert_eq!(left.collect().wait().unwrap_err().to_string(), "oh no");
+    assert_eq!(completion_error(right.wait().unwrap_err()), CompletionError::Failed);
+  }
+
+  #[test]
+  fn abandon_left() {
+    let s = stream::iter::<_, _, io::Error>(vec![ 1, 2, 3, 4, 5, 6 ].into_iter().map(|n| Ok(n)));
+    let (left, right) = s.split_until(|n| { future::ok(*n == 4) });
+    let t = thread::spawn(|| right.wait().map(|_| ()).map_err(completion_error));
+    thread::sleep(time::Duration::from_millis(50));
+    let (item, left) = left.into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(item, Some(1));
+    drop(left);
+    assert_eq!(t.join().unwrap().unwrap_err(), CompletionError::Abandoned);
+  }
 }
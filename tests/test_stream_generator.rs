@@ -4,7 +4,7 @@ extern crate lib4bottle;
 #[cfg(test)]
 mod test_stream_generator {
   use futures::{future, Future, stream, Stream};
-  use lib4bottle::stream_toolkit::generate_stream;
+  use lib4bottle::stream_toolkit::{CompletionError, generate_skippable_stream, generate_stream};
   use std::{io, thread, time};
 
   #[test]
@@ -19,6 +19,23 @@ mod test_stream_generator {
     assert_eq!(future.wait().unwrap(), 10);
   }
 
+  #[test]
+  fn poll_after_end() {
+    let (stream, future) = generate_stream(0, |counter| {
+      future::ok::<_, io::Error>(
+        if counter < 2 { (Some(counter), future::ok(counter + 1)) } else { (None, future::ok(counter)) }
+      )
+    });
+
+    let (_, stream) = stream.into_future().wait().map_err(|_| ()).unwrap();
+    let (_, stream) = stream.into_future().wait().map_err(|_| ()).unwrap();
+    let (item, stream) = stream.into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(item, None);
+    let (item, _) = stream.into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(item, None);
+    assert_eq!(future.wait().unwrap(), 2);
+  }
+
   #[test]
   fn generate_nested_stream() {
     let source: Vec<Result<usize, io::Error>> = (0..10).map(|n| Ok(n)).collect();
@@ -48,4 +65,60 @@ mod test_stream_generator {
     assert_eq!(future.wait().unwrap(), 10);
     t.join().unwrap();
   }
+
+  fn completion_error(e: io::Error) -> CompletionError {
+    *e.get_ref().unwrap().downcast_ref::<CompletionError>().unwrap()
+  }
+
+  #[test]
+  fn fail_completion_on_error() {
+    let (stream, future) = generate_stream(0, |counter| {
+      if counter < 2 {
+        future::ok((Some(counter), future::ok(counter + 1)))
+      } else {
+        future::err(io::Error::new(io::ErrorKind::Other, "oh no"))
+      }
+    });
+
+    let t = thread::spawn(|| future.wait().map_err(completion_error));
+    thread::sleep(time::Duration::from_millis(50));
+    assert_eq!(stream.collect().wait().unwrap_err().to_string(), "oh no");
+    assert_eq!(t.join().unwrap().unwrap_err(), CompletionError::Failed);
+  }
+
+  #[test]
+  fn abandon_stream() {
+    let (stream, future) = generate_stream(0, |counter| {
+      future::ok::<_, io::Error>((Some(counter), future::ok(counter + 1)))
+    });
+
+    let (item, stream) = stream.into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(item, Some(0));
+    let t = thread::spawn(|| future.wait().map_err(completion_error));
+    thread::sleep(time::Duration::from_millis(50));
+    drop(stream);
+    assert_eq!(t.join().unwrap().unwrap_err(), CompletionError::Abandoned);
+  }
+
+  #[test]
+  fn skip_the_rest_of_a_dropped_stream() {
+    let (stream, future) = generate_skippable_stream(
+      (0, 0),
+      |(counter, skipped)| {
+        future::ok::<_, io::Error>(
+          if counter < 10 { (Some(counter), future::ok((counter + 1, skipped))) } else { (None, future::ok((counter, skipped))) }
+        )
+      },
+      |(counter, skipped)| {
+        future::ok::<_, io::Error>(
+          if counter < 10 { (Some(counter), future::ok((counter + 1, skipped + 1))) } else { (None, future::ok((counter, skipped))) }
+        )
+      }
+    );
+
+    let (item, stream) = stream.into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(item, Some(0));
+    drop(stream);
+    assert_eq!(future.wait().unwrap(), (10, 9));
+  }
 }
@@ -5,7 +5,8 @@ extern crate lib4bottle;
 mod test_table {
   use bytes::{Bytes};
   use lib4bottle::stream_toolkit::{FromHex, ToHex};
-  use lib4bottle::table::Table;
+  use lib4bottle::table::{FieldValue, Table};
+  use std::io;
 
   #[test]
   fn pack() {
@@ -41,6 +42,76 @@ mod test_table {
     );
   }
 
+  #[test]
+  fn get_fields() {
+    let t = Table::decode(Bytes::from("c400a802e8030c0469726f6e".from_hex())).unwrap();
+    assert!(t.get_bool(1));
+    assert!(!t.get_bool(2));
+    assert_eq!(t.get_number(10), Some(1000));
+    assert_eq!(t.get_number(3), None);
+    assert_eq!(t.get_string(3), Some("iron"));
+    assert_eq!(t.get_string(10), None);
+  }
+
+  #[test]
+  fn bytes_fields() {
+    let mut t = Table::new();
+    t.add_bytes(5, vec![ 0xde, 0xad ]);
+    assert_eq!(format!("{:?}", t), "Table(X5=dead)");
+    assert_eq!(t.encode().to_hex(), "5402dead");
+
+    let t = Table::decode(Bytes::from("5402deadc400".from_hex())).unwrap();
+    assert_eq!(t.get_bytes(5), Some(&[ 0xde, 0xad ][..]));
+    assert_eq!(t.get_bytes(1), None);
+    assert_eq!(t.get_string(5), None);
+    assert!(t.get_bool(1));
+
+    // long enough to need both high bits of the length:
+    let mut t = Table::new();
+    t.add_bytes(0, vec![ 7; 1000 ]);
+    let t = Table::decode(t.encode()).unwrap();
+    assert_eq!(t.get_bytes(0).map(|b| b.len()), Some(1000));
+  }
+
+  #[test]
+  fn extended_fields() {
+    let mut t = Table::new();
+    t.add_number(15, 3);
+    t.add_extended("author", FieldValue::String(String::from("robey")));
+    t.add_extended(1000u64, FieldValue::Number(5));
+    t.add_extended("sealed", FieldValue::Boolean);
+    t.add_extended("salt", FieldValue::Bytes(vec![ 0xde, 0xad ]));
+    assert_eq!(format!("{:?}", t), "Table(N15=3, E(author)=S\"robey\", E(#1000)=N5, E(sealed)=B, E(salt)=Xdead)");
+    // each one is a string field, which an older reader ignores.
+    assert_eq!(
+      t.encode().to_hex(),
+      concat!("bc0103", "3c0e533a617574686f723d726f626579", "3c094e3a23313030303d35", "3c09423a7365616c65643d", "3c0b583a73616c743d64656164")
+    );
+
+    let t = Table::decode(t.encode()).unwrap();
+    assert_eq!(t.get_number(15), Some(3));
+    assert_eq!(t.get_extended("author"), Some(&FieldValue::String(String::from("robey"))));
+    assert_eq!(t.get_extended(1000u64), Some(&FieldValue::Number(5)));
+    assert_eq!(t.get_extended("sealed"), Some(&FieldValue::Boolean));
+    assert_eq!(t.get_extended("salt"), Some(&FieldValue::Bytes(vec![ 0xde, 0xad ])));
+    assert_eq!(t.get_extended("missing"), None);
+    assert_eq!(t.get_extended(1001u64), None);
+  }
+
+  #[test]
+  fn read_other_strings_with_the_extended_id() {
+    let mut t = Table::new();
+    for s in vec![ "S:author", "N:#99999999999999999999=5", "X:salt=zz", "X:salt=abc", "\u{e9}:x=y", "one\u{0}two" ] { t.add_string(15, s.to_string()) };
+    let t = Table::decode(t.encode()).unwrap();
+    assert_eq!(t.get_strings(15), vec![ "S:author", "N:#99999999999999999999=5", "X:salt=zz", "X:salt=abc", "\u{e9}:x=y", "one\u{0}two" ]);
+  }
+
+  #[test]
+  fn reject_long_numbers() {
+    let e = Table::decode(Bytes::from("8409010203040506070809".from_hex())).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+  }
+
   #[test]
   #[should_panic(expected="Truncated header table")]
   fn unpack_truncated_1() {
@@ -0,0 +1,119 @@
+#![type_length_limit="4194304"]
+
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+
+mod common;
+
+#[cfg(test)]
+mod test_unwrapper {
+  use bytes::Bytes;
+  use futures::{Future, Stream};
+  use lib4bottle::bottle_builder::BottleBuilder;
+  use lib4bottle::encrypted_bottle::{Cipher, KeyProvider};
+  use lib4bottle::file_bottle::{FileMetadata, write_file_bottle};
+  use lib4bottle::hashed_bottle::{DigestStatus, HashType, SignerStatus, write_hashed_bottle};
+  use lib4bottle::header::BottleType;
+  use lib4bottle::stream_toolkit::{ByteFrame, ReadableByteStream, stream_of, stream_of_hex};
+  use lib4bottle::unwrapper::{LayerReport, Unwrapper};
+  use common::{Invert, pack, Xor};
+  use std::io;
+  use std::sync::Arc;
+
+  struct Keys;
+
+  impl KeyProvider for Keys {
+    fn cipher(&self, cipher_id: u64, recipients: &[String]) -> io::Result<Arc<Cipher>> {
+      if cipher_id == 7 && recipients.iter().any(|r| r == "alice") { return Ok(Arc::new(Xor)) }
+      Err(io::Error::new(io::ErrorKind::PermissionDenied, "No key"))
+    }
+  }
+
+  fn layered_file() -> Bytes {
+    pack(BottleBuilder::file(&FileMetadata::new("file.txt".to_string()), stream_of(Bytes::from("hello")))
+      .compress(Arc::new(Invert))
+      .encrypt(Arc::new(Xor))
+      .hash(HashType::Sha256)
+      .encode())
+  }
+
+  #[test]
+  fn peel_layers() {
+    let unwrapper = Unwrapper::new().with_codec(Arc::new(Invert)).with_keys(Arc::new(Keys));
+    let (bottle, layers) = unwrapper.read(ReadableByteStream::from(stream_of(layered_file()))).wait().unwrap();
+    assert_eq!(bottle.header.bottle_type, BottleType::File);
+    assert_eq!(bottle.header.table.get_string(0), Some("file.txt"));
+    assert_eq!(layers.len(), 3);
+    let digest = match layers[0] {
+      LayerReport::Hashed { hash_type, ref digest, ref signer } => {
+        assert_eq!(hash_type, HashType::Sha256);
+        assert_eq!(*signer, SignerStatus::Unsigned);
+        digest.clone()
+      },
+      ref layer => panic!("{:?}", layer)
+    };
+    assert_eq!(format!("{:?}", &layers[1 ..]), concat!(
+      "[Encrypted { cipher: \"xor\", recipients: [\"alice\", \"bob\"] }, ",
+      "Compressed { codec: \"invert\" }]"
+    ));
+    assert_eq!(digest.status(), DigestStatus::Unchecked);
+
+    let streams = bottle.streams.and_then(|s| s.collect()).collect().wait().unwrap();
+    assert_eq!(streams.len(), 1);
+    assert_eq!(ByteFrame::from(streams[0].clone()).pack(), Bytes::from("hello"));
+    assert_eq!(digest.status(), DigestStatus::Valid);
+  }
+
+  #[test]
+  fn detect_bad_digest() {
+    let mut data = layered_file().to_vec();
+    // flip a bit in the digest (just before the end of its stream, and the
+    // end of the bottle).
+    let n = data.len() - 3;
+    data[n] ^= 1;
+    let unwrapper = Unwrapper::new().with_codec(Arc::new(Invert)).with_keys(Arc::new(Keys));
+    let (bottle, _) = unwrapper.read(ReadableByteStream::from(stream_of(Bytes::from(data)))).wait().unwrap();
+    let e = bottle.streams.and_then(|s| s.collect()).collect().wait().unwrap_err();
+    assert_eq!(format!("{}", e), "Hashed bottle digest doesn't match");
+  }
+
+  #[test]
+  fn report_the_signer() {
+    let file = write_file_bottle(&FileMetadata::new("x".to_string()), stream_of(Bytes::from("x"))).encode();
+    let mut hashed = write_hashed_bottle(file, HashType::Sha256);
+    hashed.header.table.add_string(0, "carol".to_string());
+    let data = pack(Box::new(hashed.encode()));
+    let (_, layers) = Unwrapper::new().read(ReadableByteStream::from(stream_of(data))).wait().unwrap();
+    match layers[0] {
+      LayerReport::Hashed { ref signer, .. } => assert_eq!(*signer, SignerStatus::Unverified("carol".to_string())),
+      ref layer => panic!("{:?}", layer)
+    }
+  }
+
+  #[test]
+  fn reject_a_hashed_bottle_without_a_hash_type() {
+    let e = Unwrapper::new().read(stream_of_hex("f09f8dbc00011000ff")).wait().err().unwrap();
+    assert_eq!(format!("{}", e), "Hashed bottle has no hash type");
+  }
+
+  #[test]
+  fn stop_at_a_plain_bottle() {
+    let data = pack(Box::new(write_file_bottle(&FileMetadata::new("x".to_string()), stream_of(Bytes::from("x"))).encode()));
+    let (bottle, layers) = Unwrapper::new().read(ReadableByteStream::from(stream_of(data))).wait().unwrap();
+    assert_eq!(bottle.header.bottle_type, BottleType::File);
+    assert_eq!(layers.len(), 0);
+  }
+
+  #[test]
+  fn missing_codec_or_key() {
+    let e = Unwrapper::new().read(ReadableByteStream::from(stream_of(layered_file()))).wait().err().unwrap();
+    assert_eq!(format!("{}", e), "No key provider for an encrypted bottle");
+
+    let unwrapper = Unwrapper::new().with_keys(Arc::new(Keys));
+    let e = unwrapper.read(ReadableByteStream::from(stream_of(layered_file()))).wait().err().unwrap();
+    assert_eq!(format!("{}", e), "No codec for a compressed bottle: 9");
+  }
+}
@@ -0,0 +1,75 @@
+#![type_length_limit="4194304"]
+
+extern crate bytes;
+extern crate futures;
+extern crate lib4bottle;
+#[macro_use]
+extern crate lazy_static;
+
+mod common;
+
+#[cfg(test)]
+mod test_volume {
+  use bytes::Bytes;
+  use common::temp_folder;
+  use futures::{Future, Stream};
+  use lib4bottle::bottle::{Bottle, read_bottle};
+  use lib4bottle::header::BottleType;
+  use lib4bottle::stream_toolkit::{ReadableByteStream, stream_of, stream_of_streams, ToHex};
+  use lib4bottle::table::Table;
+  use lib4bottle::volume::{read_volumes, VOLUME_HEADER_SIZE, write_volumes};
+  use std::fs;
+  use std::path::PathBuf;
+
+  fn write_sample(base: &PathBuf, max_size: u64) -> Vec<PathBuf> {
+    let data = stream_of(Bytes::from(vec![ 0x55u8; 100 ]));
+    let bottle = Bottle::new(BottleType::Test, Table::new(), stream_of_streams(vec![ data ]));
+    write_volumes(bottle.encode(), base, max_size).wait().unwrap()
+  }
+
+  #[test]
+  fn split_and_rejoin() {
+    let base = temp_folder("volumes").join("archive");
+    let paths = write_sample(&base, 50);
+    // 8 (header) + 103 (stream) + 1 (end of bottle) = 112 bytes, at 30 per volume:
+    assert_eq!(paths.len(), 4);
+    assert_eq!(paths[0].file_name().unwrap().to_string_lossy(), "archive.001");
+    assert_eq!(paths[3].file_name().unwrap().to_string_lossy(), "archive.004");
+    for path in &paths[0 .. 3] { assert_eq!(fs::metadata(path).unwrap().len(), 50) };
+    assert_eq!(fs::metadata(&paths[3]).unwrap().len(), (VOLUME_HEADER_SIZE + 22) as u64);
+
+    let (bottle, _) = read_bottle(ReadableByteStream::from(read_volumes(paths))).wait().unwrap();
+    assert_eq!(bottle.header.bottle_type, BottleType::Test);
+    let data = bottle.streams.and_then(|s| s.collect()).collect().wait().unwrap();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0].to_hex(), vec![ 0x55u8; 100 ].to_hex());
+  }
+
+  #[test]
+  #[should_panic(expected = "missing volumes")]
+  fn detect_missing_volume() {
+    let base = temp_folder("volumes-missing").join("archive");
+    let mut paths = write_sample(&base, 50);
+    paths.pop();
+    read_volumes(paths).collect().wait().unwrap();
+  }
+
+  #[test]
+  #[should_panic(expected = "out of order")]
+  fn detect_out_of_order() {
+    let base = temp_folder("volumes-order").join("archive");
+    let mut paths = write_sample(&base, 50);
+    paths.swap(1, 2);
+    read_volumes(paths).collect().wait().unwrap();
+  }
+
+  #[test]
+  #[should_panic(expected = "different archive")]
+  fn detect_mixed_archives() {
+    let folder = temp_folder("volumes-mixed");
+    let mut paths = write_sample(&folder.join("one"), 50);
+    let other = write_sample(&folder.join("two"), 50);
+    paths[1] = other[1].clone();
+    read_volumes(paths).collect().wait().unwrap();
+  }
+}
@@ -1,10 +1,12 @@
 extern crate bytes;
+extern crate futures;
 extern crate lib4bottle;
 
 #[cfg(test)]
 mod test_zint {
   use bytes::{Bytes};
-  use lib4bottle::stream_toolkit::{FromHex, ToHex};
+  use futures::{Future};
+  use lib4bottle::stream_toolkit::{FromHex, stream_of_hex, ToHex};
   use lib4bottle::zint;
 
   #[test]
@@ -125,4 +127,20 @@ mod test_zint {
       zint::FrameLength::EndOfBottle
     );
   }
+
+  #[test]
+  fn read_zint_length() {
+    let s = stream_of_hex("04403ebd043a00ff");
+    let (length, s) = s.read_zint_length().wait().unwrap();
+    assert_eq!(length, zint::FrameLength::Length(4));
+    let (length, s) = s.read_zint_length().wait().unwrap();
+    assert_eq!(length, zint::FrameLength::Length(0x3e));
+    let (length, s) = s.read_zint_length().wait().unwrap();
+    assert_eq!(length, zint::FrameLength::Length(3998778));
+    let (length, s) = s.read_zint_length().wait().unwrap();
+    assert_eq!(length, zint::FrameLength::EndOfStream);
+    let (length, s) = s.read_zint_length().wait().unwrap();
+    assert_eq!(length, zint::FrameLength::EndOfBottle);
+    assert!(s.read_zint_length().wait().is_err());
+  }
 }